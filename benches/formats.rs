@@ -0,0 +1,145 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ping_the_internet::{
+    file::{
+        decode_slash_16, encode_slash_16, mapped::Decoder, read_slash_16, Codec, Encoding,
+        SaveOptions,
+    },
+    ping::PingResult,
+    stats::Slash16Result,
+    subnet::Subnet,
+};
+
+use async_compression::Level;
+use rand::Rng;
+
+const ITERATIONS: u32 = 20;
+
+/// Compares the size and decode speed of every storage format on a full /16
+///
+/// Uses a synthetic /16 unless a subnet from `./data` is given, e.g.
+/// `cargo bench --bench formats -- 8.8.x.x`
+#[tokio::main]
+async fn main() {
+    let subnet = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .map(|subnet| subnet.parse::<Subnet>().ok().expect("Invalid subnet"));
+
+    let results = match subnet {
+        Some(subnet) => read_slash_16(subnet)
+            .await
+            .expect("Failed to read file")
+            .expect("Subnet not found"),
+        None => synthetic_slash_16(),
+    };
+
+    let candidates = [
+        ("tagged (best)", SaveOptions::default()),
+        ("bitmap (default)", SaveOptions::compact()),
+        (
+            "bitmap (best)",
+            SaveOptions {
+                encoding: Encoding::Bitmap,
+                level: Level::Best,
+                ..Default::default()
+            },
+        ),
+        (
+            "tagged (zstd)",
+            SaveOptions {
+                codec: Codec::Zstd,
+                ..Default::default()
+            },
+        ),
+        (
+            "bitmap (zstd)",
+            SaveOptions {
+                codec: Codec::Zstd,
+                ..SaveOptions::compact()
+            },
+        ),
+    ];
+
+    println!(
+        "| {:^16} | {:^10} | {:^12} | {:^12} | {:^12} |",
+        "FORMAT", "SIZE", "ENCODE", "DECODE", "SYNC DECODE"
+    );
+    println!(
+        "|{:->18}|{:->12}|{:->14}|{:->14}|{:->14}|",
+        "", "", "", "", ""
+    );
+
+    let mut decoder = Decoder::default();
+
+    for (name, options) in candidates {
+        let start_time = Instant::now();
+        let mut data = Vec::new();
+        for _ in 0..ITERATIONS {
+            data = encode_slash_16(&results, &options).await.unwrap();
+        }
+        let encode_time = start_time.elapsed() / ITERATIONS;
+
+        let start_time = Instant::now();
+        for _ in 0..ITERATIONS {
+            let decoded = decode_slash_16(&data).await.unwrap();
+            assert_eq!(decoded, results);
+        }
+        let decode_time = start_time.elapsed() / ITERATIONS;
+
+        let start_time = Instant::now();
+        for _ in 0..ITERATIONS {
+            let view = decoder.decode(&data).unwrap();
+
+            let alive = view
+                .iter()
+                .flatten()
+                .flat_map(|slash_24| slash_24.iter())
+                .filter(|ping_result| matches!(ping_result, PingResult::Success(_)))
+                .count();
+
+            assert!(alive > 0);
+        }
+        let sync_decode_time = start_time.elapsed() / ITERATIONS;
+
+        assert_eq!(decoder.decode(&data).unwrap().to_owned(), results);
+
+        println!(
+            "| {:>16} | {:>8} B | {:>12.2?} | {:>12.2?} | {:>12.2?} |",
+            name,
+            data.len(),
+            encode_time,
+            decode_time,
+            sync_decode_time
+        );
+    }
+}
+
+/// Builds a /16 where every /24 is present, with roughly the mix of
+/// successes, timeouts and errors seen in real scans
+fn synthetic_slash_16() -> Slash16Result {
+    let mut rng = rand::thread_rng();
+
+    let slash_16: Vec<_> = (0..256)
+        .map(|_| {
+            let density = rng.gen_range(0.0..0.3);
+
+            let slash_24: Vec<_> = (0..256)
+                .map(|_| match rng.gen::<f32>() {
+                    x if x < density => {
+                        PingResult::Success(Duration::from_millis(rng.gen_range(5..400)))
+                    }
+                    x if x < density + 0.005 => PingResult::Error,
+                    _ => PingResult::Timeout,
+                })
+                .collect();
+
+            Some(Arc::new(slash_24.try_into().unwrap()))
+        })
+        .collect();
+
+    Arc::new(slash_16.try_into().unwrap())
+}
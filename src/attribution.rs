@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+
+use crate::{
+    file::{DataSource, StorageError},
+    ping::PingResult,
+    progress::Progress,
+    stats::Slash16Result,
+    subnet::Subnet,
+};
+
+/// How the scanned addresses attributed to a route, country or delegation fared
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddressCounts {
+    pub alive: u64,
+    pub timed_out: u64,
+    pub errored: u64,
+}
+
+impl AddressCounts {
+    pub(crate) fn count(&mut self, ping_result: &PingResult) {
+        match ping_result {
+            PingResult::Success(_) => self.alive += 1,
+            PingResult::Timeout => self.timed_out += 1,
+            PingResult::Error => self.errored += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &AddressCounts) {
+        self.alive += other.alive;
+        self.timed_out += other.timed_out;
+        self.errored += other.errored;
+    }
+
+    pub fn scanned(&self) -> u64 {
+        self.alive + self.timed_out + self.errored
+    }
+
+    /// The share of the scanned addresses that replied
+    pub fn alive_percent(&self) -> f32 {
+        if self.scanned() == 0 {
+            return 0.0;
+        }
+
+        (self.alive as f64 / self.scanned() as f64 * 100.0) as f32
+    }
+}
+
+/// Anything the scanned addresses are summed up into, e.g. the totals of a country
+pub trait Attributed {
+    fn counts(&self) -> &AddressCounts;
+}
+
+/// The share of an amount of routed or delegated space that replied
+pub fn density_percent(alive: u64, space: u64) -> f32 {
+    if space == 0 {
+        return 0.0;
+    }
+
+    (alive as f64 / space as f64 * 100.0) as f32
+}
+
+/// Attributes the scanned addresses of every /16 of a subnet, `jobs` at a time,
+/// handing each attribution to `on_attribution` as soon as it is done, in no
+/// particular order
+///
+/// `attribute` is given the /16 and the subnet, so that only the addresses inside
+/// the subnet are attributed when it is smaller than a /16. Returns the number of
+/// /16s that were unreadable and skipped
+pub async fn attribute_subnet<A: Send + 'static>(
+    source: &DataSource,
+    subnet: Subnet,
+    jobs: usize,
+    attribute: impl Fn(Subnet, Subnet, &Slash16Result) -> A + Send + Sync + 'static,
+    mut on_attribution: impl FnMut(A),
+) -> Result<u32, StorageError> {
+    let attribute = Arc::new(attribute);
+    let mut damaged: u32 = 0;
+
+    let mut progress = Progress::new("/16s", subnet.iter_slash_16s().count() as u64);
+
+    let mut attributions = futures::stream::iter(subnet.iter_slash_16s())
+        .map(|b| {
+            let source = source.clone();
+            let attribute = attribute.clone();
+
+            let task = tokio::spawn(async move {
+                let results = source.read_slash_16(b).await?;
+
+                Ok::<_, StorageError>(results.map(|results| attribute(b, subnet, &results)))
+            });
+
+            async move { task.await.expect("Attribution worker panicked") }
+        })
+        .buffer_unordered(jobs);
+
+    while let Some(result) = attributions.next().await {
+        progress.tick(1);
+
+        match result {
+            Ok(Some(attribution)) => on_attribution(attribution),
+            Ok(None) => {}
+            Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                damaged += 1;
+            }
+            Err(e) => {
+                progress.clear();
+                return Err(e);
+            }
+        }
+    }
+
+    progress.finish();
+
+    Ok(damaged)
+}
+
+/// Prints the `top` rows with the most replies, or the first `top` in the order
+/// given unless `by_alive`, ties keep the order they are given in
+pub fn print_ranked<'a, K, T: Attributed + 'a>(
+    rows: impl IntoIterator<Item = (K, &'a T)>,
+    by_alive: bool,
+    top: Option<usize>,
+    mut print_row: impl FnMut(usize, K, &'a T),
+) {
+    let mut ranked: Vec<_> = rows.into_iter().collect();
+
+    if by_alive {
+        ranked.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.counts().alive));
+    }
+
+    ranked.truncate(top.unwrap_or(usize::MAX));
+
+    for (rank, (key, totals)) in ranked.into_iter().enumerate() {
+        print_row(rank + 1, key, totals);
+    }
+}
+
+/// Prints how many of the scanned addresses could not be attributed, e.g. as `Unrouted`
+pub fn print_unattributed(what: &str, unattributed: &AddressCounts, scanned: u64) {
+    println!(
+        "{what}: {} of {} scanned addresses ({:.2}%), {} alive",
+        unattributed.scanned(),
+        scanned,
+        unattributed.scanned() as f64 / scanned.max(1) as f64 * 100.0,
+        unattributed.alive
+    );
+}
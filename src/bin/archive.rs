@@ -0,0 +1,42 @@
+use std::{error::Error, path::PathBuf, time::Instant};
+
+use ping_the_internet::file::archive::{pack, unpack};
+
+const USAGE: &str =
+    "Usage: archive pack <data root> <archive> | archive unpack <archive> <data root>";
+
+/// Converts a run between the `a/b` directory layout and a single archive file
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let [command, from, to] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    };
+
+    let start_time = Instant::now();
+
+    match command.as_str() {
+        "pack" => {
+            let count = pack(&PathBuf::from(from), to).await?;
+            println!(
+                "Packed {count} /16s into {to} in {:.2?}",
+                start_time.elapsed()
+            );
+        }
+        "unpack" => {
+            let count = unpack(from, &PathBuf::from(to)).await?;
+            println!(
+                "Unpacked {count} /16s into {to} in {:.2?}",
+                start_time.elapsed()
+            );
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::error::Error;
+
+use ping_the_internet::{
+    file::{
+        create_file_path, decode_slash_16, save_slash_16_with, Codec, Dictionary, Encoding, Header,
+        SaveOptions, StorageError,
+    },
+    subnet::Subnet,
+};
+
+/// Rewrites every /16 file in a subnet using the given encoding, keeping its codec
+///
+/// Usage: `convert <tagged|bitmap> [subnet]`
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let encoding: Encoding = std::env::args()
+        .nth(1)
+        .and_then(|encoding| encoding.parse().ok())
+        .expect("Usage: convert <tagged|bitmap> [subnet]");
+
+    let subnet: Subnet = std::env::args()
+        .nth(2)
+        .map(|subnet| subnet.parse().ok().expect("Invalid subnet"))
+        .unwrap_or_default();
+
+    let mut total_before: u64 = 0;
+    let mut total_after: u64 = 0;
+
+    for slash_16 in subnet.iter_slash_16s() {
+        let file_path = create_file_path(slash_16);
+
+        if !file_path.exists() {
+            continue;
+        }
+
+        let data = tokio::fs::read(&file_path).await?;
+
+        let decoded = async {
+            let (_, header) = Header::read(&data)?;
+            Ok::<_, StorageError>((header, decode_slash_16(&data).await?))
+        };
+
+        let (header, results) = match decoded.await {
+            Ok(decoded) => decoded,
+            Err(e) if e.is_damaged() => {
+                println!("| {:>13} | {:^29} |", format!("{slash_16}"), format!("{e}"));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut options = match encoding {
+            Encoding::Tagged => SaveOptions::default(),
+            Encoding::Bitmap => SaveOptions::compact(),
+        };
+
+        options.created = Some(header.created);
+        options.provenance = header.provenance;
+
+        if header.codec == Codec::Zstd {
+            options.codec = Codec::Zstd;
+
+            if header.dictionary_id != 0 {
+                options.dictionary = Some(Dictionary::load(header.dictionary_id).await?);
+            }
+        }
+
+        save_slash_16_with(slash_16, results, &options).await?;
+
+        let before = data.len() as u64;
+        let after = tokio::fs::metadata(&file_path).await?.len();
+
+        total_before += before;
+        total_after += after;
+
+        println!(
+            "| {:>13} | {:>9} B -> {:>9} B |",
+            format!("{slash_16}"),
+            before,
+            after
+        );
+    }
+
+    println!(
+        "Converted {} B -> {} B ({:.2}%)",
+        total_before,
+        total_after,
+        total_after as f32 / total_before.max(1) as f32 * 100.0
+    );
+
+    Ok(())
+}
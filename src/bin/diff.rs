@@ -0,0 +1,195 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use ping_the_internet::{
+    diff::{
+        diff_slash_16, holds_other_data, print_diff_csv_header, print_diff_csv_row,
+        print_diff_table_header, print_diff_table_row, save_slash_16_diff, DiffSummary,
+    },
+    file::{
+        mapped::{Slash16View, SyncReader},
+        StorageError,
+    },
+    subnet::{Subnet, SubnetMask},
+};
+
+const USAGE: &str =
+    "Usage: diff <before> <after> [subnet] [--by <8|16|24>] [--csv] [--output <diff root>]";
+
+/// Compares two runs (data roots or archives) address by address
+///
+/// Prints the hosts that appeared, disappeared or stayed alive, and how their RTT
+/// changed, for every /8, /16 or /24. With `--output`, the diff of every /16 is
+/// also saved so `image --diff` can render it, to a root that holds nothing but
+/// earlier diffs
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut runs = Vec::new();
+    let mut subnet = Subnet::default();
+    let mut by = SubnetMask::Slash16;
+    let mut csv = false;
+    let mut output: Option<PathBuf> = None;
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--by" => {
+                by = match args.next().as_deref() {
+                    Some("8") => SubnetMask::Slash8,
+                    Some("16") => SubnetMask::Slash16,
+                    Some("24") => SubnetMask::Slash24,
+                    _ => panic!("{USAGE}"),
+                }
+            }
+            "--csv" => csv = true,
+            "--output" => output = Some(args.next().expect(USAGE).into()),
+            arg if runs.len() < 2 => runs.push(arg.to_string()),
+            arg => subnet = arg.parse().ok().expect("Invalid subnet"),
+        }
+    }
+
+    let [before, after] = runs.as_slice() else {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    };
+
+    if let Some(ref root) = output {
+        check_output(root, [before, after])?;
+    }
+
+    let mut before = SyncReader::open(before)?;
+    let mut after = SyncReader::open(after)?;
+
+    match csv {
+        true => print_diff_csv_header(),
+        false => print_diff_table_header(),
+    }
+
+    let print_row = |subnet: Subnet, summary: Option<&DiffSummary>| match (csv, summary) {
+        (true, Some(summary)) => print_diff_csv_row(subnet, summary),
+        (true, None) => {}
+        (false, summary) => print_diff_table_row(subnet, summary),
+    };
+
+    // A /8 with no /16 in both runs is reported like a missing /16
+    let print_slash_8_row = |subnet: Subnet, summary: &DiffSummary| {
+        print_row(subnet, (summary.unscanned < 1 << 24).then_some(summary))
+    };
+
+    let mut total = DiffSummary::default();
+    let mut slash_8: Option<(Subnet, DiffSummary)> = None;
+
+    for slash_16 in subnet.iter_slash_16s() {
+        /* Flush the previous /8 once we're past it */
+
+        let [a, _, _, _] = slash_16.octets();
+
+        if slash_8.as_ref().is_some_and(|(s, _)| s.octets()[0] != a) {
+            let (s, summary) = slash_8.take().unwrap();
+
+            if by == SubnetMask::Slash8 {
+                print_slash_8_row(s, &summary);
+            }
+        }
+
+        let (_, slash_8_summary) = slash_8.get_or_insert_with(|| {
+            (
+                Subnet::new([a, 0, 0, 0].into(), SubnetMask::Slash8),
+                DiffSummary::default(),
+            )
+        });
+
+        /* Diff the /16 */
+
+        let views = read_view(&mut before, slash_16)?.zip(read_view(&mut after, slash_16)?);
+
+        let Some((before_view, after_view)) = views else {
+            slash_8_summary.merge(&DiffSummary::unscanned_slash_16());
+            total.merge(&DiffSummary::unscanned_slash_16());
+
+            if by == SubnetMask::Slash16 {
+                print_row(slash_16, None);
+            }
+
+            continue;
+        };
+
+        let diff = diff_slash_16(&before_view, &after_view);
+
+        if let Some(ref root) = output {
+            save_slash_16_diff(root, slash_16, &diff)?;
+        }
+
+        let summary = DiffSummary::of_slash_16(&diff);
+
+        slash_8_summary.merge(&summary);
+        total.merge(&summary);
+
+        match by {
+            SubnetMask::Slash16 => print_row(slash_16, Some(&summary)),
+            SubnetMask::Slash24 => {
+                for (slash_24, slash_24_diff) in slash_16.iter_subnets().zip(diff.iter()) {
+                    let summary = slash_24_diff.as_ref().map(DiffSummary::of_slash_24);
+                    print_row(slash_24, summary.as_ref());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((s, summary)) = slash_8 {
+        if by == SubnetMask::Slash8 {
+            print_slash_8_row(s, &summary);
+        }
+    }
+
+    if !csv {
+        println!(
+            "Total: {} appeared, {} disappeared, {} still alive ({:+.2} ms mean RTT change)",
+            total.appeared,
+            total.disappeared,
+            total.still_alive,
+            total.mean_rtt_delta()
+        );
+        println!("Not in both runs: {} addresses", total.unscanned);
+    }
+
+    Ok(())
+}
+
+/// Refuses to save diffs over one of the runs being compared or over any other /16 data
+fn check_output(root: &Path, runs: [&String; 2]) -> Result<(), Box<dyn Error>> {
+    for run in runs {
+        let same = match (root.canonicalize(), Path::new(run).canonicalize()) {
+            (Ok(root), Ok(run)) => root == run,
+            _ => false,
+        };
+
+        if same {
+            return Err(format!("{} is one of the runs being compared", root.display()).into());
+        }
+    }
+
+    if holds_other_data(root)? {
+        return Err(format!("{} already holds /16 data", root.display()).into());
+    }
+
+    Ok(())
+}
+
+/// Reads a /16 of a run, treating damaged files as missing
+fn read_view(
+    reader: &mut SyncReader,
+    slash_16: Subnet,
+) -> Result<Option<Slash16View<'_>>, StorageError> {
+    match reader.read_slash_16(slash_16) {
+        Ok(view) => Ok(view),
+        Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+            eprintln!("Skipping {slash_16}: {e}");
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, ErrorKind, Write},
+    sync::Arc,
+    time::Instant,
+};
+
+use ping_the_internet::{
+    export::{ExportFormat, Exporter},
+    file::{default_data_root, mapped::SyncReader, StorageError},
+    geoip::{GeoIpDatabase, Locator},
+    subnet::Subnet,
+};
+
+const USAGE: &str = "Usage: export <csv|ndjson> [subnet] [--source <data root or archive>] [--output <file>] [--responsive-only] [--geoip <mmdb>]";
+
+/// Streams the per-address results of a subnet (the whole run by default) as CSV or NDJSON
+///
+/// Rows go to stdout unless `--output` is given, progress and damaged files go to stderr
+///
+/// With `--geoip`, every row also gets the country of its address from a local
+/// MaxMind DB file such as GeoLite2-Country.mmdb
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut format = None;
+    let mut subnet = Subnet::default();
+    let mut source = default_data_root();
+    let mut output = None;
+    let mut responsive_only = false;
+    let mut geoip = None;
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => source = args.next().expect(USAGE).into(),
+            "--output" => output = Some(args.next().expect(USAGE)),
+            "--responsive-only" => responsive_only = true,
+            "--geoip" => geoip = Some(args.next().expect(USAGE)),
+            arg if format.is_none() => format = Some(arg.parse::<ExportFormat>().expect(USAGE)),
+            arg => subnet = arg.parse().ok().expect("Invalid subnet"),
+        }
+    }
+
+    let format = format.expect(USAGE);
+
+    let locator = match geoip {
+        Some(path) => Some(Locator::new(Arc::new(GeoIpDatabase::open(path)?))),
+        None => None,
+    };
+
+    let writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut reader = SyncReader::open(&source)?;
+    let mut exporter = Exporter::new(
+        BufWriter::new(writer),
+        format,
+        subnet,
+        responsive_only,
+        locator,
+    )?;
+
+    let start_time = Instant::now();
+    let mut exported_slash_16s = 0;
+
+    for slash_16 in subnet.iter_slash_16s() {
+        let view = match reader.read_slash_16(slash_16) {
+            Ok(Some(view)) => view,
+            Ok(None) => continue,
+            Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                eprintln!("Skipping {slash_16}: {e}");
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        match exporter.write_slash_16(slash_16, &view) {
+            Ok(()) => {}
+            // The consumer stopped reading, e.g. `export csv | head`
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        exported_slash_16s += 1;
+    }
+
+    let rows = exporter.rows();
+    exporter.finish()?;
+
+    eprintln!(
+        "Exported {rows} rows from {exported_slash_16s} /16s in {:.2?}",
+        start_time.elapsed()
+    );
+
+    Ok(())
+}
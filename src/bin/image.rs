@@ -1,36 +1,327 @@
-use image::{ImageBuffer, Rgb};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use image::{ImageFormat, RgbImage};
 use ping_the_internet::{
-    file::read_slash_16,
-    ping::PingResult,
+    diff::read_slash_16_diff,
+    file::{
+        summary::{summarize_slash_16, summarize_slash_8},
+        DataSource, StorageError,
+    },
+    progress::Progress,
+    render::{draw_rtt_legend, Curve, MapLayout, MapStyle, RttColoring, RttScale, MAX_MAP_SIZE},
     subnet::{Subnet, SubnetMask},
 };
 
+const USAGE: &str = "Usage: image [subnet] [--source <data root or archive>] [--pixel <16|24|32>] [--curve <hilbert|z-order>] [--palette <default|grayscale|colorblind|light>] [--output <file>] [--format <png|bmp|ppm>] [--rtt [--scale <log|linear>] [--min-rtt <ms>] [--max-rtt <ms>] [--legend <file>]] [--tiles <directory>] [--jobs <n>] [--diff <diff root> (with a /8 or smaller subnet, or --tiles)] [--labels]";
+
+/// Renders a subnet (the whole internet by default) along a space filling curve
+///
+/// Every pixel is a /16, /24 or address, the finest that keeps the map within
+/// 4096x4096 unless `--pixel` says otherwise. Blocks get brighter the more of
+/// them replied, addresses are coloured by their result. The map goes to
+/// `output.png` unless `--output` is given, in the format of its extension or
+/// of `--format`
+///
+/// `--tiles` renders each /8 of the subnet on its own instead, named
+/// `<column>-<row>` after its place on the map of the whole internet, which at
+/// a pixel per address is too big for one image
+///
+/// `--rtt` colours what replied by its RTT instead, blocks by their median, on
+/// a log scale from 1ms to 1s unless `--scale`, `--min-rtt` and `--max-rtt`
+/// say otherwise. `--legend` writes the colour bar of that scale to a file
+///
+/// `--diff` renders a diff saved by `diff --output`, always a pixel per address,
+/// so it takes a /8 or smaller subnet unless rendered with `--tiles`. `--labels`
+/// writes each /8 and who IANA gave it to over the map
 #[tokio::main]
 async fn main() {
-    let results = read_slash_16(Subnet::new([8, 0, 0, 0].into(), SubnetMask::Slash16))
-        .await
-        .expect("Failed to read file")
-        .expect("Subnet not found");
-
-    let mut map = ImageBuffer::new(256, 256);
-
-    for i in 0..=u16::MAX {
-        let (x, y) = hilbert_curve::convert_1d_to_2d(i as usize, 256);
-
-        let a = (i / 256) as usize;
-        let b = (i % 256) as usize;
-
-        let pixel_color: [u8; 3] = match &results[a] {
-            Some(slash_24) => match slash_24[b] {
-                PingResult::Success(_) => [0x40, 0xFF, 0x40],
-                PingResult::Timeout => [0xA3, 0xB3, 0xC0],
-                PingResult::Error => [0xFF, 0x50, 0x50]
-            },
-            None => [0x50, 0x50, 0x50],
+    let mut subnet = Subnet::default();
+    let mut source = None;
+    let mut pixel = None;
+    let mut curve = Curve::default();
+    let mut style = MapStyle::default();
+    let mut rtt = false;
+    let mut rtt_scale = RttScale::default();
+    let mut min_rtt = None;
+    let mut max_rtt = None;
+    let mut legend: Option<PathBuf> = None;
+    let mut output = PathBuf::from("output.png");
+    let mut format = None;
+    let mut tiles: Option<PathBuf> = None;
+    let mut diff_root: Option<PathBuf> = None;
+    let mut labels = false;
+    let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => source = Some(args.next().expect(USAGE)),
+            "--pixel" => {
+                pixel = match args.next().as_deref() {
+                    Some("16") => Some(SubnetMask::Slash16),
+                    Some("24") => Some(SubnetMask::Slash24),
+                    Some("32") => Some(SubnetMask::Slash32),
+                    _ => panic!("{USAGE}"),
+                }
+            }
+            "--curve" => {
+                let value = args.next().and_then(|curve| curve.parse().ok());
+                curve = value.expect(USAGE);
+            }
+            "--palette" => {
+                let value = args.next().and_then(|palette| palette.parse().ok());
+                style.palette = value.expect(USAGE);
+            }
+            "--rtt" => rtt = true,
+            "--scale" => {
+                let value = args.next().and_then(|scale| scale.parse().ok());
+                rtt_scale = value.expect(USAGE);
+            }
+            "--min-rtt" => min_rtt = Some(parse_ms(args.next())),
+            "--max-rtt" => max_rtt = Some(parse_ms(args.next())),
+            "--legend" => legend = Some(args.next().expect(USAGE).into()),
+            "--output" => output = args.next().expect(USAGE).into(),
+            "--format" => {
+                let value = args.next().and_then(ImageFormat::from_extension);
+                format = Some(value.expect(USAGE));
+            }
+            "--tiles" => tiles = Some(args.next().expect(USAGE).into()),
+            "--jobs" => {
+                let value = args.next().and_then(|jobs| jobs.parse().ok());
+                jobs = value.filter(|jobs| *jobs > 0).expect(USAGE);
+            }
+            "--diff" => diff_root = Some(args.next().expect("Missing diff root").into()),
+            "--labels" => labels = true,
+            arg => subnet = arg.parse().ok().expect("Invalid subnet"),
+        }
+    }
+
+    if rtt {
+        let default = RttColoring::default();
+        let coloring = RttColoring::new(
+            rtt_scale,
+            min_rtt.unwrap_or(default.min),
+            max_rtt.unwrap_or(default.max),
+        );
+
+        style.rtt = Some(coloring);
+
+        if let Some(path) = &legend {
+            draw_rtt_legend(&coloring, 768)
+                .save(path)
+                .expect("Failed to save the legend");
+        }
+    }
+
+    let source = match source {
+        Some(path) => DataSource::open(path)
+            .await
+            .expect("Failed to open the data source"),
+        None => DataSource::default(),
+    };
+
+    let render = |layout: MapLayout| {
+        let source = source.clone();
+        let diff_root = diff_root.clone();
+
+        async move {
+            let mut map = match diff_root {
+                Some(root) => render_diff(&root, layout, &style),
+                None if layout.pixel == SubnetMask::Slash32 => {
+                    render_addresses(&source, layout, &style, jobs).await
+                }
+                None => render_summaries(&source, layout, &style, jobs).await,
+            };
+
+            if labels {
+                layout.draw_labels(&mut map);
+            }
+
+            map
+        }
+    };
+
+    let Some(directory) = tiles else {
+        let layout = match pixel {
+            Some(pixel) => MapLayout::new(subnet, pixel, curve),
+            None if diff_root.is_some() => MapLayout::new(subnet, SubnetMask::Slash32, curve),
+            None => MapLayout::finest(subnet, curve),
         };
 
-        map.put_pixel(x as u32, y as u32, Rgb(pixel_color));
+        assert!(
+            layout.side() <= MAX_MAP_SIZE,
+            "A {}x{} map is too big for one image, render it with --tiles",
+            layout.side(),
+            layout.side()
+        );
+
+        let map = render(layout).await;
+        let format = format
+            .or_else(|| ImageFormat::from_path(&output).ok())
+            .expect("Unknown image format");
+
+        return map
+            .save_with_format(&output, format)
+            .expect("Failed to save output image");
+    };
+
+    assert!(
+        matches!(subnet.mask(), SubnetMask::Slash0 | SubnetMask::Slash8),
+        "--tiles renders the /8s of the whole internet or of a /8"
+    );
+
+    std::fs::create_dir_all(&directory).expect("Failed to create the tile directory");
+
+    let format = format.unwrap_or(ImageFormat::Png);
+    let extension = format.extensions_str()[0];
+
+    for slash_8 in slash_8s_of(subnet) {
+        let layout = MapLayout::new(slash_8, pixel.unwrap_or(SubnetMask::Slash32), curve);
+        let (column, row) = layout.tile_position();
+
+        render(layout)
+            .await
+            .save_with_format(
+                directory.join(format!("{column}-{row}.{extension}")),
+                format,
+            )
+            .expect("Failed to save tile");
+    }
+}
+
+fn parse_ms(value: Option<String>) -> Duration {
+    let ms = value.and_then(|ms| ms.parse::<f64>().ok());
+
+    Duration::from_secs_f64(ms.filter(|ms| *ms >= 0.0).expect(USAGE) / 1000.0)
+}
+
+fn slash_8s_of(subnet: Subnet) -> Vec<Subnet> {
+    match subnet.mask() {
+        SubnetMask::Slash0 => subnet.iter_subnets().collect(),
+        _ => vec![Subnet::new(
+            [subnet.octets()[0], 0, 0, 0].into(),
+            SubnetMask::Slash8,
+        )],
+    }
+}
+
+/* A pixel per /16 or /24, from the summaries so only changed files are decoded */
+
+async fn render_summaries(
+    source: &DataSource,
+    layout: MapLayout,
+    style: &MapStyle,
+    jobs: usize,
+) -> RgbImage {
+    let mut map = layout.new_map(style);
+    let mut damaged: u32 = 0;
+
+    let mut draw = |b: Subnet, result: Result<_, StorageError>| match result {
+        Ok(Some(summary)) => layout.draw_slash_16_summary(&mut map, style, b, &summary),
+        Ok(None) => {}
+        Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+            damaged += 1;
+        }
+        Err(e) => panic!("Failed to read {b}: {e}"),
+    };
+
+    match layout.subnet.mask() {
+        SubnetMask::Slash0 | SubnetMask::Slash8 => {
+            let slash_16s = layout.subnet.iter_slash_16s().count() as u64;
+            let mut progress = Progress::new("/16s", slash_16s);
+
+            for slash_8 in slash_8s_of(layout.subnet) {
+                summarize_slash_8(source, slash_8, jobs, |b, result| {
+                    progress.tick(1);
+                    draw(b, result);
+                })
+                .await
+                .unwrap_or_else(|e| panic!("Failed to summarize {slash_8}: {e}"));
+            }
+
+            progress.finish();
+        }
+        _ => {
+            let b = layout.subnet.iter_slash_16s().next().unwrap();
+
+            draw(b, summarize_slash_16(source, b).await);
+        }
+    }
+
+    if damaged != 0 {
+        eprintln!("Left out {damaged} unreadable /16s");
+    }
+
+    map
+}
+
+/* A pixel per address */
+
+async fn render_addresses(
+    source: &DataSource,
+    layout: MapLayout,
+    style: &MapStyle,
+    jobs: usize,
+) -> RgbImage {
+    let mut map = layout.new_map(style);
+    let mut damaged: u32 = 0;
+
+    let slash_16s = layout.subnet.iter_slash_16s().count() as u64;
+    let mut progress = Progress::new("/16s", slash_16s);
+
+    let mut results = futures::stream::iter(layout.subnet.iter_slash_16s())
+        .map(|b| {
+            let source = source.clone();
+            let task = tokio::spawn(async move { source.read_slash_16(b).await });
+
+            async move { (b, task.await.expect("Image worker panicked")) }
+        })
+        .buffer_unordered(jobs);
+
+    while let Some((b, result)) = results.next().await {
+        progress.tick(1);
+
+        match result {
+            Ok(Some(results)) => layout.draw_slash_16_addresses(&mut map, style, b, &results),
+            Ok(None) => {}
+            Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                damaged += 1;
+            }
+            Err(e) => panic!("Failed to read {b}: {e}"),
+        }
+    }
+
+    progress.finish();
+
+    if damaged != 0 {
+        eprintln!("Left out {damaged} unreadable /16s");
+    }
+
+    map
+}
+
+fn render_diff(root: &Path, layout: MapLayout, style: &MapStyle) -> RgbImage {
+    assert_eq!(
+        layout.pixel,
+        SubnetMask::Slash32,
+        "Diffs are rendered a pixel per address"
+    );
+
+    let mut map = layout.new_map(style);
+
+    for b in layout.subnet.iter_slash_16s() {
+        let diff =
+            read_slash_16_diff(root, b).unwrap_or_else(|e| panic!("Failed to read {b}: {e}"));
+
+        if let Some(diff) = diff {
+            layout.draw_slash_16_diff(&mut map, style, b, &diff);
+        }
     }
 
-    map.save("output.png").expect("Failed to save output image");
+    map
 }
@@ -0,0 +1,154 @@
+use std::{error::Error, fs::File, io::BufReader, time::Instant};
+
+use ping_the_internet::{
+    file::{decode_slash_16, save_slash_16_with, DataSource, Header, SaveOptions, StorageError},
+    import::{ImportFormat, ImportSet},
+    subnet::{Subnet, SubnetMask},
+};
+
+const USAGE: &str = "Usage: import <zmap|masscan-list|masscan-binary> <file>... [--scope <subnet>]... [--overwrite]";
+
+/// Imports zmap or masscan ICMP scans into `./data`
+///
+/// Every /24 in the scope is written with the addresses that were not listed as timeouts,
+/// the /24s outside of it are left unscanned. Without `--scope`, only the /24s with at
+/// least one listed address are considered scanned. Existing /16 files are kept unless
+/// `--overwrite` is given, which replaces only the /24s in the scope and keeps the rest
+///
+/// Files are dated by the earliest record of the scans, and their provenance says what
+/// they were imported from
+///
+/// masscan gives no RTTs, nor does zmap without its timestamp fields, so those
+/// replies count as alive but are left out of RTT stats and maps
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut format = None;
+    let mut paths = Vec::new();
+    let mut scopes: Vec<Subnet> = Vec::new();
+    let mut overwrite = false;
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scope" => {
+                let scope: Subnet = args
+                    .next()
+                    .and_then(|scope| scope.parse().ok())
+                    .expect(USAGE);
+
+                assert_ne!(
+                    scope.mask(),
+                    SubnetMask::Slash32,
+                    "Scopes can't be smaller than a /24"
+                );
+
+                scopes.push(scope);
+            }
+            "--overwrite" => overwrite = true,
+            arg if format.is_none() => format = Some(arg.parse::<ImportFormat>().expect(USAGE)),
+            arg => paths.push(arg.to_string()),
+        }
+    }
+
+    let format = format.expect(USAGE);
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    }
+
+    let start_time = Instant::now();
+
+    /* Read the scans */
+
+    let mut set = ImportSet::default();
+
+    for path in &paths {
+        set.read(format, BufReader::new(File::open(path)?))?;
+    }
+
+    println!(
+        "Read {} records ({} skipped) in {:.2?}",
+        set.records,
+        set.skipped,
+        start_time.elapsed()
+    );
+
+    /* Write every scanned /16 */
+
+    let mut slash_16s: Vec<_> = match scopes.is_empty() {
+        true => set.iter_slash_16s().collect(),
+        false => scopes
+            .iter()
+            .flat_map(|scope| scope.iter_slash_16s())
+            .collect(),
+    };
+
+    slash_16s.sort_by_key(|slash_16| slash_16.octets());
+    slash_16s.dedup_by_key(|slash_16| slash_16.octets());
+
+    let in_scope = |slash_24: Subnet| match scopes.is_empty() {
+        true => set.has_slash_24(slash_24),
+        false => scopes.iter().any(|scope| scope.contains(*slash_24)),
+    };
+
+    let source = DataSource::default();
+    let imported_from = format!("imported from {}", format.name());
+
+    let mut written = 0;
+
+    for slash_16 in slash_16s {
+        let existing = match source.read_raw_slash_16(slash_16).await? {
+            Some(_) if !overwrite => {
+                println!("Skipping {slash_16}, it already exists");
+                continue;
+            }
+            Some(data) => {
+                let decoded = async {
+                    let (_, header) = Header::read(&data)?;
+                    Ok::<_, StorageError>((header, decode_slash_16(&data).await?))
+                };
+
+                match decoded.await {
+                    Ok(decoded) => Some(decoded),
+                    Err(e)
+                        if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) =>
+                    {
+                        println!("Replacing {slash_16} whole, it is unreadable: {e}");
+                        None
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            None => None,
+        };
+
+        let existing_results = existing.as_ref().map(|(_, results)| results);
+
+        let Some(results) = set.slash_16_results(slash_16, in_scope, existing_results) else {
+            continue;
+        };
+
+        /* Merged files keep where the rest of their /24s came from */
+
+        let mut provenance = existing
+            .map(|(header, _)| header.provenance)
+            .unwrap_or_default();
+        provenance.push(imported_from.clone());
+
+        let options = SaveOptions {
+            created: set.scanned_at,
+            provenance,
+            ..Default::default()
+        };
+
+        save_slash_16_with(slash_16, results, &options).await?;
+
+        written += 1;
+    }
+
+    println!("Imported {written} /16s in {:.2?}", start_time.elapsed());
+
+    Ok(())
+}
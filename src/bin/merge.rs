@@ -0,0 +1,92 @@
+use std::{error::Error, path::PathBuf, time::Instant};
+
+use ping_the_internet::{
+    file::{
+        merge::{merge_runs_slash_16, MergePolicy},
+        DataSource, SaveOptions,
+    },
+    subnet::Subnet,
+};
+
+const USAGE: &str = "Usage: merge <output root> <run>... [--policy <prefer-success|prefer-newest|majority>] [--subnet <subnet>] [--compact]";
+
+/// Merges several runs (data roots or archives) into a single data root
+///
+/// Every /16 found in at least one run is written, with conflicting addresses
+/// resolved by the policy (prefer-success by default)
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut output: Option<PathBuf> = None;
+    let mut paths = Vec::new();
+    let mut policy = MergePolicy::PreferSuccess;
+    let mut subnet = Subnet::default();
+    let mut options = SaveOptions::default();
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--policy" => {
+                let value = args.next().and_then(|policy| policy.parse().ok());
+                policy = value.expect(USAGE);
+            }
+            "--subnet" => {
+                let value = args.next().and_then(|subnet| subnet.parse().ok());
+                subnet = value.expect("Invalid subnet");
+            }
+            "--compact" => options = SaveOptions::compact(),
+            arg if output.is_none() => output = Some(arg.into()),
+            arg => paths.push(arg.to_string()),
+        }
+    }
+
+    let output = output.expect(USAGE);
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    }
+
+    let mut runs = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let run = DataSource::open(&path).await?;
+        runs.push((path, run));
+    }
+
+    let start_time = Instant::now();
+
+    let mut merged_slash_16s = 0;
+    let mut total_conflicts: u64 = 0;
+
+    for slash_16 in subnet.iter_slash_16s() {
+        let (merged, skipped) =
+            merge_runs_slash_16(&runs, &output, slash_16, policy, &options).await?;
+
+        for (name, e) in skipped {
+            eprintln!("Leaving {slash_16} of {name} out: {e}");
+        }
+
+        let Some(merged) = merged else {
+            continue;
+        };
+
+        println!(
+            "| {:>13} | {:>6} conflicts |",
+            format!("{slash_16}"),
+            merged.conflicts
+        );
+
+        merged_slash_16s += 1;
+        total_conflicts += merged.conflicts as u64;
+    }
+
+    println!(
+        "Merged {merged_slash_16s} /16s from {} runs with {} ({total_conflicts} conflicts) in {:.2?}",
+        runs.len(),
+        policy.name(),
+        start_time.elapsed()
+    );
+
+    Ok(())
+}
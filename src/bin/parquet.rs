@@ -0,0 +1,98 @@
+use std::{error::Error, sync::Arc, time::Instant};
+
+use ping_the_internet::{
+    export::parquet::ParquetExporter,
+    file::{default_data_root, mapped::SyncReader, StorageError},
+    geoip::{GeoIpDatabase, Locator},
+    subnet::Subnet,
+};
+
+const USAGE: &str =
+    "Usage: parquet <output dir> [subnet] [--source <data root or archive>] [--responsive-only] [--geoip <mmdb>]";
+
+/// Converts the /16 files of a subnet (the whole run by default) into Parquet partitioned per /8
+///
+/// Every /8 file keeps when its /16s were scanned and their provenance as key/value metadata
+///
+/// With `--geoip`, a country column is added from a local MaxMind DB file such as
+/// GeoLite2-Country.mmdb
+///
+/// Query it with e.g. `SELECT * FROM read_parquet('<output dir>/*/*.parquet', hive_partitioning = true)`
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut output = None;
+    let mut subnet = Subnet::default();
+    let mut source = default_data_root();
+    let mut responsive_only = false;
+    let mut geoip = None;
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => source = args.next().expect(USAGE).into(),
+            "--responsive-only" => responsive_only = true,
+            "--geoip" => geoip = Some(args.next().expect(USAGE)),
+            arg if output.is_none() => output = Some(arg.to_string()),
+            arg => subnet = arg.parse().ok().expect("Invalid subnet"),
+        }
+    }
+
+    let output = output.expect(USAGE);
+
+    let metadata = vec![
+        ("ping_the_internet.source", source.display().to_string()),
+        ("ping_the_internet.subnet", subnet.to_string()),
+        (
+            "ping_the_internet.exported_at",
+            chrono::Utc::now().to_rfc3339(),
+        ),
+        // RTTs are stored with millisecond precision, `rtt_us` is only scaled up
+        ("ping_the_internet.rtt_precision", "ms".to_string()),
+        (
+            "ping_the_internet.responsive_only",
+            responsive_only.to_string(),
+        ),
+    ];
+
+    let metadata = metadata
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+    let mut reader = SyncReader::open(&source)?;
+    let locator = match geoip {
+        Some(path) => Some(Locator::new(Arc::new(GeoIpDatabase::open(path)?))),
+        None => None,
+    };
+
+    let mut exporter = ParquetExporter::new(&output, metadata, subnet, responsive_only, locator);
+
+    let start_time = Instant::now();
+    let mut exported_slash_16s = 0;
+
+    for slash_16 in subnet.iter_slash_16s() {
+        let view = match reader.read_slash_16(slash_16) {
+            Ok(Some(view)) => view,
+            Ok(None) => continue,
+            Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                eprintln!("Skipping {slash_16}: {e}");
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        exporter.write_slash_16(slash_16, &view)?;
+
+        exported_slash_16s += 1;
+    }
+
+    let rows = exporter.rows();
+    exporter.finish()?;
+
+    println!(
+        "Exported {rows} rows from {exported_slash_16s} /16s into {output} in {:.2?}",
+        start_time.elapsed()
+    );
+
+    Ok(())
+}
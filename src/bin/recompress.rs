@@ -0,0 +1,154 @@
+use std::{error::Error, sync::Arc};
+
+use async_compression::Level;
+use ping_the_internet::{
+    file::{
+        create_file_path, decode_slash_16, save_slash_16_with, serialize_body, Codec, Dictionary,
+        Header, SaveOptions, StorageError,
+    },
+    stats::Slash16Result,
+    subnet::Subnet,
+};
+
+const USAGE: &str =
+    "Usage: recompress <zlib|zstd> [subnet] [--level <n>] [--train-dictionary | --dictionary <id>]";
+
+/// The number of /16 files sampled when training a dictionary
+const TRAINING_SAMPLES: usize = 2048;
+
+/// Large enough to capture the common structure of /16 files, small enough to stay cached
+const DICTIONARY_SIZE: usize = 112 * 1024;
+
+/// Migrates every /16 file in a subnet to the given codec, keeping its encoding
+///
+/// With `--train-dictionary` a new zstd dictionary is trained on a sample of the
+/// existing files and saved to `./data/dictionaries` before anything is rewritten
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut codec = None;
+    let mut subnet = Subnet::default();
+    let mut level = Level::Best;
+    let mut train_dictionary = false;
+    let mut dictionary_id = None;
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--level" => {
+                let value = args.next().and_then(|level| level.parse().ok());
+                level = Level::Precise(value.expect(USAGE));
+            }
+            "--train-dictionary" => train_dictionary = true,
+            "--dictionary" => {
+                let value = args.next().and_then(|id| u32::from_str_radix(&id, 16).ok());
+                dictionary_id = Some(value.expect(USAGE));
+            }
+            arg if codec.is_none() => codec = Some(arg.parse::<Codec>().expect(USAGE)),
+            arg => subnet = arg.parse().ok().expect("Invalid subnet"),
+        }
+    }
+
+    let codec = codec.expect(USAGE);
+
+    /* Find the files to migrate */
+
+    let slash_16s: Vec<_> = subnet
+        .iter_slash_16s()
+        .filter(|slash_16| create_file_path(*slash_16).exists())
+        .collect();
+
+    /* Prepare the dictionary */
+
+    let dictionary = match (codec, train_dictionary, dictionary_id) {
+        (Codec::Zstd, true, _) => {
+            let dictionary = Arc::new(train(&slash_16s).await?);
+            dictionary.save().await?;
+
+            println!("Trained dictionary {:08x}", dictionary.id());
+
+            Some(dictionary)
+        }
+        (Codec::Zstd, false, Some(id)) => Some(Dictionary::load(id).await?),
+        _ => None,
+    };
+
+    /* Rewrite every file */
+
+    let mut total_before: u64 = 0;
+    let mut total_after: u64 = 0;
+
+    for slash_16 in slash_16s {
+        let file_path = create_file_path(slash_16);
+        let data = tokio::fs::read(&file_path).await?;
+
+        let (header, results) = match decode(&data).await {
+            Ok(decoded) => decoded,
+            Err(e) if e.is_damaged() => {
+                println!("| {:>13} | {:^29} |", format!("{slash_16}"), format!("{e}"));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let options = SaveOptions {
+            encoding: header.encoding,
+            codec,
+            level,
+            dictionary: dictionary.clone(),
+            created: Some(header.created),
+            provenance: header.provenance,
+        };
+
+        save_slash_16_with(slash_16, results, &options).await?;
+
+        let before = data.len() as u64;
+        let after = tokio::fs::metadata(&file_path).await?.len();
+
+        total_before += before;
+        total_after += after;
+
+        println!(
+            "| {:>13} | {:>9} B -> {:>9} B |",
+            format!("{slash_16}"),
+            before,
+            after
+        );
+    }
+
+    println!(
+        "Recompressed {} B -> {} B ({:.2}%)",
+        total_before,
+        total_after,
+        total_after as f32 / total_before.max(1) as f32 * 100.0
+    );
+
+    Ok(())
+}
+
+/// Trains a dictionary on an evenly spread sample of the given /16 files
+async fn train(slash_16s: &[Subnet]) -> Result<Dictionary, Box<dyn Error>> {
+    let step = (slash_16s.len() / TRAINING_SAMPLES).max(1);
+
+    let mut samples = Vec::with_capacity(TRAINING_SAMPLES);
+
+    for slash_16 in slash_16s.iter().step_by(step) {
+        let data = tokio::fs::read(create_file_path(*slash_16)).await?;
+
+        let (header, results) = match decode(&data).await {
+            Ok(decoded) => decoded,
+            Err(e) if e.is_damaged() => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        samples.push(serialize_body(&results, header.encoding).await?);
+    }
+
+    Ok(Dictionary::train(&samples, DICTIONARY_SIZE)?)
+}
+
+async fn decode(data: &[u8]) -> Result<(Header, Slash16Result), StorageError> {
+    let (_, header) = Header::read(data)?;
+
+    Ok((header, decode_slash_16(data).await?))
+}
@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+
+use ping_the_internet::{
+    file::{summary::summarize_slash_8, DataSource, StorageError},
+    iana::slash_8_registration,
+    progress::Progress,
+    render::{Curve, MapLayout, MapStyle},
+    report::ReportPage,
+    stats::Analysis,
+    subnet::{Subnet, SubnetMask},
+};
+
+const USAGE: &str = "Usage: report <output directory> [--source <data root or archive>] [--title <title>] [--curve <hilbert|z-order>] [--palette <default|grayscale|colorblind|light>] [--jobs <n>]";
+
+/// Writes a static site about a run, for sharing with people who will never
+/// run any of this
+///
+/// `index.html` has the totals, the RTTs of every reply, a map of the whole
+/// internet and a row per /8 with who IANA gave it to. Every /8 with results
+/// gets a page of its own under `slash-8/`, with its map and a row per /16.
+/// Pages only link to each other and the maps next to them, so the directory
+/// can be copied anywhere or opened straight from disk
+#[tokio::main]
+async fn main() {
+    let mut root = None;
+    let mut source = None;
+    let mut title = "Ping the internet".to_string();
+    let mut curve = Curve::default();
+    let mut style = MapStyle::default();
+    let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => source = Some(args.next().expect(USAGE)),
+            "--title" => title = args.next().expect(USAGE),
+            "--curve" => {
+                let value = args.next().and_then(|curve| curve.parse().ok());
+                curve = value.expect(USAGE);
+            }
+            "--palette" => {
+                let value = args.next().and_then(|palette| palette.parse().ok());
+                style.palette = value.expect(USAGE);
+            }
+            "--jobs" => {
+                let value = args.next().and_then(|jobs| jobs.parse().ok());
+                jobs = value.filter(|jobs| *jobs > 0).expect(USAGE);
+            }
+            arg if root.is_none() => root = Some(PathBuf::from(arg)),
+            _ => panic!("{USAGE}"),
+        }
+    }
+
+    let root = root.expect(USAGE);
+
+    std::fs::create_dir_all(root.join("slash-8")).expect("Failed to create the report directory");
+
+    let source = match source {
+        Some(path) => DataSource::open(path)
+            .await
+            .expect("Failed to open the data source"),
+        None => DataSource::default(),
+    };
+
+    let overview_layout = MapLayout::new(Subnet::default(), SubnetMask::Slash24, curve);
+    let mut overview = overview_layout.new_map(&style);
+    let mut total = Analysis::new(SubnetMask::Slash0);
+    let mut total_damaged: u32 = 0;
+
+    /* A page per /8 with results, summarised into a row of the index */
+
+    let mut slash_8s = Vec::new();
+    let mut progress = Progress::new("/16s", 65536);
+
+    for slash_8 in Subnet::default().iter_subnets() {
+        let layout = MapLayout::new(slash_8, SubnetMask::Slash24, curve);
+        let mut map = layout.new_map(&style);
+        let mut slash_8_anal = Analysis::new(SubnetMask::Slash8);
+        let mut slash_16s = Vec::new();
+        let mut damaged: u32 = 0;
+
+        summarize_slash_8(&source, slash_8, jobs, |b, result| {
+            progress.tick(1);
+
+            let row = match result {
+                Ok(Some(summary)) => {
+                    layout.draw_slash_16_summary(&mut map, &style, b, &summary);
+                    overview_layout.draw_slash_16_summary(&mut overview, &style, b, &summary);
+
+                    Ok(Some(summary.analysis))
+                }
+                Ok(None) => Ok(None),
+                Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                    damaged += 1;
+
+                    Err(e)
+                }
+                Err(e) => panic!("Failed to read {b}: {e}"),
+            };
+
+            match &row {
+                Ok(Some(anal)) => slash_8_anal.merge(anal),
+                _ => slash_8_anal.merge(&Analysis::unscanned(SubnetMask::Slash16)),
+            }
+
+            slash_16s.push((b, row));
+        })
+        .await
+        .unwrap_or_else(|e| panic!("Failed to summarize {slash_8}: {e}"));
+
+        total.merge(&slash_8_anal);
+        total_damaged += damaged;
+
+        if slash_8_anal.scanned() == 0 && damaged == 0 {
+            slash_8s.push((slash_8, None));
+            continue;
+        }
+
+        let a = slash_8.octets()[0];
+
+        layout.draw_labels(&mut map);
+        map.save(root.join(format!("slash-8/{a}.png")))
+            .expect("Failed to save a /8 map");
+
+        let registration = slash_8_registration(a);
+        let mut page = ReportPage::new(
+            &format!("{slash_8} {}", registration.designation),
+            Some(("../index.html", &title)),
+        );
+
+        page.push_paragraph(&format!(
+            "{}, given out by IANA in {}.",
+            registration.status, registration.date
+        ));
+        page.push_summary(&slash_8_anal, damaged);
+        page.push_map(
+            &format!("{a}.png"),
+            "A pixel per /24, brighter the more of it replied",
+        );
+        page.push_heading("RTT");
+        page.push_rtt_histogram(&slash_8_anal.rtt);
+        page.push_heading("/16s");
+        page.begin_subnet_table();
+
+        slash_16s.sort_by_key(|(b, _)| b.octets()[1]);
+
+        for (b, row) in &slash_16s {
+            match row {
+                Ok(Some(anal)) => page.push_subnet_row(*b, anal, None),
+                Ok(None) => page.push_subnet_message(*b, "Not scanned"),
+                Err(e) => page.push_subnet_message(*b, &format!("Unreadable: {e}")),
+            }
+        }
+
+        page.end_subnet_table();
+
+        std::fs::write(root.join(format!("slash-8/{a}.html")), page.finish())
+            .expect("Failed to save a /8 page");
+
+        slash_8s.push((slash_8, Some(slash_8_anal)));
+    }
+
+    progress.finish();
+
+    /* The index, linking to the pages of the /8s */
+
+    overview_layout.draw_labels(&mut overview);
+    overview
+        .save(root.join("overview.png"))
+        .expect("Failed to save the overview map");
+
+    let link = |a: u8| {
+        let scanned = slash_8s[a as usize].1.is_some();
+        scanned.then(|| format!("slash-8/{a}.html"))
+    };
+
+    let mut page = ReportPage::new(&title, None);
+
+    page.push_paragraph(&format!(
+        "Generated on {}.",
+        chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")
+    ));
+    page.push_summary(&total, total_damaged);
+    page.push_overview(
+        "overview.png",
+        curve,
+        link,
+        "The whole internet, a pixel per /24 and brighter the more of it replied. Click a /8 to see its page",
+    );
+    page.push_heading("RTT");
+    page.push_rtt_histogram(&total.rtt);
+    page.push_heading("/8s");
+    page.begin_subnet_table();
+
+    for (slash_8, anal) in &slash_8s {
+        let a = slash_8.octets()[0];
+
+        match anal {
+            Some(anal) => page.push_subnet_row(*slash_8, anal, link(a).as_deref()),
+            None => page.push_subnet_message(*slash_8, "Not scanned"),
+        }
+    }
+
+    page.end_subnet_table();
+
+    std::fs::write(root.join("index.html"), page.finish()).expect("Failed to save the index");
+
+    if total_damaged != 0 {
+        eprintln!("Left out {total_damaged} unreadable /16s");
+    }
+}
@@ -1,48 +1,644 @@
 #![forbid(unsafe_code)]
 
+use std::sync::Arc;
+
+use futures::StreamExt;
 use ping_the_internet::{
-    file::read_slash_16, stats::{print_stats_table_header, print_stats_table_row, Analysis, SubnetResults}, subnet::Subnet
+    attribution::{attribute_subnet, print_ranked, print_unattributed},
+    delegation::{
+        print_delegation_csv_header, print_delegation_csv_row, print_delegation_table_header,
+        print_delegation_table_row, DelegationGroup, DelegationReport, DelegationTable,
+    },
+    file::{
+        summary::{summarize_slash_16, summarize_slash_16_in, update_summary_index, SummaryIndex},
+        DataSource, StorageError,
+    },
+    geoip::{
+        print_country_csv_header, print_country_csv_row, print_country_table_header,
+        print_country_table_row, CountryReport, GeoIpDatabase, Locator,
+    },
+    progress::Progress,
+    routing::{
+        print_origin_csv_header, print_origin_csv_row, print_origin_table_header,
+        print_origin_table_row, print_route_csv_header, print_route_csv_row,
+        print_route_table_header, print_route_table_row, RoutingReport, RoutingTable,
+    },
+    stats::{
+        rank::{
+            print_ranking_csv_header, print_ranking_csv_row, print_ranking_table_header,
+            print_ranking_table_row, RankFilter, RankKey, Ranking,
+        },
+        Analysis, StatsFormat, StatsPrinter,
+    },
+    subnet::{Subnet, SubnetMask},
 };
 
+const USAGE: &str =
+    "Usage: stats [data root or archive] [--subnet <subnet>] [--by <8|16>] [--jobs <n>] [--no-cache] [--format <table|json|csv|markdown>] [--rank <16|24> [--sort <alive|alive-percent|median-rtt>] [--ascending] [--top <n|all>] [--min-alive <n>] [--min-alive-percent <p>] [--max-alive-percent <p>]] [--routes <pfx2as or MRT file> [--per <as|prefix>] [--top <n|all>]] [--geoip <mmdb> [--top <n|all>]] [--delegations <delegated stats file or directory>... [--per <rir|country|year>] [--top <n|all>]]";
+
+/// Prints the stats of a data root or archive (`./data` by default)
+///
+/// Every /8 is summarised by default, and every /16 of it when given a /8.
+/// Either way the whole subnet is summarised last, with the space that has
+/// no results counted as unscanned rather than as errors.
+///
+/// /16s are decoded by as many workers as there are cores (or `--jobs`), while
+/// rows are still printed in order. The counts of every /16 are cached in a
+/// summary index per /8 under `<root>/.summary`, so only the /16s that changed since
+/// they were last saved or summarised are decoded again, unless `--no-cache` is given.
+///
+/// With `--rank`, the /16s or /24s of the subnet are ranked instead, e.g. the
+/// 100 /24s with the most replies (the default) or every /16 above 90% alive
+/// with `--rank 16 --sort alive-percent --min-alive-percent 90 --top all`.
+///
+/// With `--routes`, every scanned address is attributed to its origin AS (or
+/// announced prefix with `--per prefix`) by longest-prefix match against a
+/// CAIDA pfx2as file or an MRT RIB dump, along with how much was unrouted.
+/// The subnet can be as small as a /24 or a single address then, and only
+/// the part of each route inside it counts towards its routed space
+///
+/// With `--geoip`, every scanned address is attributed to its country instead,
+/// looked up in a local MaxMind DB file such as GeoLite2-Country.mmdb
+///
+/// With `--delegations`, every scanned address is attributed to the block a RIR
+/// allocated or assigned it in, by the RIRs' `delegated-*-extended` files, and the
+/// delegated and responsive space is summed up per RIR (or per country or year
+/// of delegation with `--per`). It can be given more than once, or a directory
+/// holding all five files. Only the part of each block inside the subnet counts
+/// towards its delegated space
 #[tokio::main]
 async fn main() {
-    print_stats_table_header();
-
-    let mut total_pinged: u32 = 0;
-    let mut total_alive: u32 = 0;
+    let mut source = None;
+    let mut subnet = Subnet::default();
+    let mut by = None;
+    let mut no_cache = false;
+    let mut format = StatsFormat::Table;
+    let mut rank = None;
+    let mut rank_key = RankKey::Alive;
+    let mut ascending = false;
+    let mut top = Some(100);
+    let mut filter = RankFilter::default();
+    let mut routes = None;
+    let mut per = None;
+    let mut geoip = None;
+    let mut delegations = Vec::new();
+    let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
 
-    // a.0.0.0
-    for a in Subnet::default().iter_subnets() {
-        for b in a.iter_subnets() {
-            let anal = analyze_slash_16(b).await.unwrap();
+    let mut args = std::env::args().skip(1);
 
-            if let Some(ref anal) = anal {
-                total_pinged += 65536;
-                total_alive += anal.alive;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--subnet" => {
+                let value = args.next().and_then(|subnet| subnet.parse().ok());
+                subnet = value.expect("Invalid subnet");
+            }
+            "--by" => {
+                by = match args.next().as_deref() {
+                    Some("8") => Some(SubnetMask::Slash8),
+                    Some("16") => Some(SubnetMask::Slash16),
+                    _ => panic!("{USAGE}"),
+                }
+            }
+            "--jobs" => {
+                let value = args.next().and_then(|jobs| jobs.parse().ok());
+                jobs = value.filter(|jobs| *jobs > 0).expect(USAGE);
+            }
+            "--no-cache" => no_cache = true,
+            "--format" => {
+                let value = args.next().and_then(|format| format.parse().ok());
+                format = value.expect(USAGE);
+            }
+            "--rank" => {
+                rank = match args.next().as_deref() {
+                    Some("16") => Some(SubnetMask::Slash16),
+                    Some("24") => Some(SubnetMask::Slash24),
+                    _ => panic!("{USAGE}"),
+                }
+            }
+            "--sort" => {
+                let value = args.next().and_then(|key| key.parse().ok());
+                rank_key = value.expect(USAGE);
+            }
+            "--ascending" => ascending = true,
+            "--top" => {
+                top = match args.next().as_deref() {
+                    Some("all") => None,
+                    Some(top) => Some(top.parse().expect(USAGE)),
+                    None => panic!("{USAGE}"),
+                }
+            }
+            "--min-alive" => {
+                let value = args.next().and_then(|alive| alive.parse().ok());
+                filter.min_alive = value.expect(USAGE);
             }
+            "--min-alive-percent" => {
+                let value = args.next().and_then(|percent| percent.parse().ok());
+                filter.min_alive_percent = value.expect(USAGE);
+            }
+            "--max-alive-percent" => {
+                let value = args.next().and_then(|percent| percent.parse().ok());
+                filter.max_alive_percent = value.expect(USAGE);
+            }
+            "--routes" => routes = Some(args.next().expect(USAGE)),
+            "--per" => per = Some(args.next().expect(USAGE)),
+            "--geoip" => geoip = Some(args.next().expect(USAGE)),
+            "--delegations" => delegations.push(args.next().expect(USAGE)),
+            arg if source.is_none() => source = Some(arg.to_string()),
+            _ => panic!("{USAGE}"),
+        }
+    }
+
+    let by = by.unwrap_or(match subnet.mask() {
+        SubnetMask::Slash0 => SubnetMask::Slash8,
+        _ => SubnetMask::Slash16,
+    });
+
+    let source = match source {
+        Some(path) => DataSource::open(path)
+            .await
+            .expect("Failed to open data source"),
+        None => DataSource::default(),
+    };
+
+    let attributions =
+        routes.is_some() as u8 + geoip.is_some() as u8 + !delegations.is_empty() as u8;
+
+    assert!(
+        (rank.is_none() && attributions == 0)
+            || matches!(format, StatsFormat::Table | StatsFormat::Csv),
+        "Rankings can only be printed as a table or CSV"
+    );
+
+    assert!(
+        attributions <= 1,
+        "Addresses can only be attributed to one of routes, countries or delegations"
+    );
+
+    /* Addresses are attributed one by one, any other stats are only kept down to /16s */
+
+    assert!(
+        attributions != 0
+            || matches!(
+                subnet.mask(),
+                SubnetMask::Slash0 | SubnetMask::Slash8 | SubnetMask::Slash16
+            ),
+        "Stats are only kept down to /16s"
+    );
+
+    if let Some(path) = routes {
+        let per_prefix = match per.as_deref() {
+            None | Some("as") => false,
+            Some("prefix") => true,
+            _ => panic!("{USAGE}"),
+        };
+
+        let table = RoutingTable::open(&path).expect("Failed to read the routing table");
+
+        report_routes(
+            &source,
+            subnet,
+            Arc::new(table),
+            jobs,
+            per_prefix,
+            top,
+            format,
+        )
+        .await;
+
+        return;
+    }
+
+    if let Some(path) = geoip {
+        let database = GeoIpDatabase::open(&path).expect("Failed to read the GeoIP database");
+
+        report_countries(&source, subnet, Arc::new(database), jobs, top, format).await;
+
+        return;
+    }
+
+    if !delegations.is_empty() {
+        let per = per.as_deref().unwrap_or("rir").parse().expect(USAGE);
+        let table = DelegationTable::open(&delegations).expect("Failed to read the delegations");
+
+        report_delegations(&source, subnet, Arc::new(table), jobs, per, top, format).await;
+
+        return;
+    }
+
+    let mut ranking = rank.map(|mask| (mask, Ranking::new(rank_key, ascending, top, filter)));
+    let print_rows = ranking.is_none();
+
+    let mut printer = StatsPrinter::new(format);
+
+    if print_rows {
+        printer.print_header().expect("Failed to print the stats");
+    }
+
+    /* Summaries are only cached next to data roots, archives are always decoded */
+
+    let cache = match &source {
+        DataSource::Directory(root) if !no_cache => Some(root.clone()),
+        _ => None,
+    };
+
+    let mut total = Analysis::new(subnet.mask());
+    let mut total_damaged: u32 = 0;
+
+    let mut progress = Progress::new("/16s", subnet.iter_slash_16s().count() as u64);
+
+    let slash_8s: Vec<Subnet> = match subnet.mask() {
+        SubnetMask::Slash0 => subnet.iter_subnets().collect(),
+        _ => vec![Subnet::new(
+            [subnet.octets()[0], 0, 0, 0].into(),
+            SubnetMask::Slash8,
+        )],
+    };
+
+    for slash_8 in slash_8s {
+        let index = match &cache {
+            Some(root) => match SummaryIndex::read(root, slash_8) {
+                Ok(index) => index,
+                Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                    SummaryIndex::default()
+                }
+                Err(e) => panic!("Failed to read the summary index of {slash_8}: {e}"),
+            },
+            None => SummaryIndex::default(),
+        };
+
+        let slash_16s = match subnet.mask() {
+            SubnetMask::Slash16 => subnet.iter_slash_16s(),
+            _ => slash_8.iter_slash_16s(),
+        };
+
+        /* Decode /16s on every core, buffered so they still come out in order */
+
+        let mut summaries = futures::stream::iter(slash_16s)
+            .map(|b| {
+                let source = source.clone();
+                let cache = cache.clone();
+                let cached = index.get(b).cloned();
+
+                let task = tokio::spawn(async move {
+                    match cache {
+                        Some(root) => summarize_slash_16_in(&root, b, cached).await,
+                        None => summarize_slash_16(&source, b).await,
+                    }
+                });
+
+                async move { (b, task.await.expect("Stats worker panicked")) }
+            })
+            .buffered(jobs);
+
+        let mut slash_8_anal = Analysis::new(SubnetMask::Slash8);
+        let mut updates = Vec::new();
+
+        while let Some((b, result)) = summaries.next().await {
+            progress.tick(1);
+
+            let cached_stamp = index.get(b).map(|summary| summary.stamp);
+
+            let anal = match result {
+                Ok(summary) => {
+                    let anal = summary.as_ref().map(|summary| summary.analysis.clone());
+
+                    if print_rows && by == SubnetMask::Slash16 {
+                        progress.clear();
+                        printer
+                            .print_row(b, anal.as_ref())
+                            .expect("Failed to print the stats");
+                    }
+
+                    if let (Some((mask, ranking)), Some(summary)) = (&mut ranking, &summary) {
+                        ranking.push_summary(b, summary, *mask);
+                    }
+
+                    if summary.as_ref().map(|summary| summary.stamp) != cached_stamp {
+                        updates.push((b, summary));
+                    }
+
+                    anal
+                }
+                Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                    total_damaged += 1;
+
+                    if print_rows && by == SubnetMask::Slash16 {
+                        progress.clear();
+                        printer
+                            .print_message(b, &format!("{e}"))
+                            .expect("Failed to print the stats");
+                    }
+
+                    if cached_stamp.is_some() {
+                        updates.push((b, None));
+                    }
+
+                    None
+                }
+                Err(e) => panic!("Failed to read {b}: {e}"),
+            };
+
+            let anal = anal.unwrap_or_else(|| Analysis::unscanned(SubnetMask::Slash16));
+
+            slash_8_anal.merge(&anal);
+            total.merge(&anal);
+        }
+
+        if print_rows && by == SubnetMask::Slash8 {
+            progress.clear();
+            print_slash_8_row(&mut printer, slash_8, slash_8_anal);
+        }
+
+        /* Remember what had to be decoded for next time */
+
+        if let Some(root) = cache
+            .as_ref()
+            .filter(|_| !updates.is_empty() || index.has_journal())
+        {
+            update_summary_index(root, slash_8, updates)
+                .expect("Failed to update the summary index");
+        }
+    }
 
-            print_stats_table_row(b, anal, true);
+    progress.finish();
+
+    if let Some((_, ranking)) = ranking {
+        print_ranking(ranking, format);
+
+        if total_damaged != 0 {
+            eprintln!("Left out {total_damaged} unreadable /16s");
         }
+
+        return;
+    }
+
+    /* Summarise the whole subnet */
+
+    if subnet.mask() != by {
+        printer
+            .print_row(subnet, Some(&total))
+            .expect("Failed to print the stats");
+    }
+
+    printer.finish().expect("Failed to print the stats");
+
+    /* The totals are already in the rows of the machine readable formats */
+
+    match format {
+        StatsFormat::Json | StatsFormat::Csv => return,
+        StatsFormat::Markdown => println!(),
+        StatsFormat::Table => {}
     }
 
     println!(
-        "Total Pinged: {} ({:.2}%)",
-        total_pinged,
-        total_pinged as f32 / u32::MAX as f32 * 100.0
+        "Total Pinged: {} of {} ({:.2}%)",
+        total.scanned(),
+        total.get_max(),
+        100.0 - total.unscanned_percent()
     );
     println!(
-        "Total Alive: {} ({:.2}%)",
-        total_alive,
-        total_alive as f32 / total_pinged as f32 * 100.0
+        "Total Alive: {} ({:.2}% of pinged)",
+        total.alive,
+        total.alive_percent_of_scanned()
     );
+    println!("Total Unreadable: {} /16s", total_damaged);
 }
 
-pub async fn analyze_slash_16(subnet: Subnet) -> Result<Option<Analysis>, std::io::Error> {
-    let Some(results) = read_slash_16(subnet).await? else {
-        return Ok(None);
+fn print_ranking(ranking: Ranking, format: StatsFormat) {
+    let ranked = ranking.finish();
+
+    match format {
+        StatsFormat::Csv => print_ranking_csv_header(),
+        _ => print_ranking_table_header(),
+    }
+
+    for (i, entry) in ranked.iter().enumerate() {
+        match format {
+            StatsFormat::Csv => print_ranking_csv_row(i + 1, entry),
+            _ => print_ranking_table_row(i + 1, entry),
+        }
+    }
+}
+
+/// Attributes the scanned addresses of the subnet to routes and prints the top origins or prefixes
+async fn report_routes(
+    source: &DataSource,
+    subnet: Subnet,
+    table: Arc<RoutingTable>,
+    jobs: usize,
+    per_prefix: bool,
+    top: Option<usize>,
+    format: StatsFormat,
+) {
+    let mut report = RoutingReport::new(&table, subnet);
+
+    /* Routes are summed up, so the /16s can finish in any order */
+
+    let attribute = {
+        let table = table.clone();
+        move |b, scope, results: &_| table.attribute_slash_16(b, scope, results)
     };
 
-    let anal = Analysis::of_subnet(SubnetResults::Slash16(results));
+    let total_damaged = attribute_subnet(source, subnet, jobs, attribute, |attribution| {
+        report.add(&table, &attribution)
+    })
+    .await
+    .unwrap_or_else(|e| panic!("Failed to read {subnet}: {e}"));
+
+    /* Rank by the number of replies, ties go to the lower prefix or AS */
+
+    let csv = format == StatsFormat::Csv;
+
+    if per_prefix {
+        match csv {
+            true => print_route_csv_header(),
+            false => print_route_table_header(),
+        }
+
+        let rows = report
+            .routes
+            .iter()
+            .enumerate()
+            .filter(|(_, totals)| totals.counts.scanned() != 0);
+
+        print_ranked(rows, true, top, |rank, i, totals| {
+            let route = &table.routes()[i];
+            let origin = &table.origins()[route.origin];
+
+            match csv {
+                true => print_route_csv_row(rank, route, origin, totals, report.routed[i]),
+                false => print_route_table_row(rank, route, origin, totals, report.routed[i]),
+            }
+        });
+    } else {
+        match csv {
+            true => print_origin_csv_header(),
+            false => print_origin_table_header(),
+        }
+
+        let mut rows: Vec<_> = table
+            .origins()
+            .iter()
+            .zip(&report.origins)
+            .filter(|(_, totals)| totals.counts.scanned() != 0)
+            .collect();
+
+        rows.sort_by_key(|(origin, _)| *origin);
+
+        print_ranked(rows, true, top, |rank, origin, totals| match csv {
+            true => print_origin_csv_row(rank, origin, totals),
+            false => print_origin_table_row(rank, origin, totals),
+        });
+    }
+
+    if csv {
+        return;
+    }
+
+    println!(
+        "Routed: {} addresses in {} prefixes from {} origins",
+        report.routed_addresses(),
+        report
+            .origins
+            .iter()
+            .map(|origin| origin.routes)
+            .sum::<u32>(),
+        report
+            .origins
+            .iter()
+            .filter(|origin| origin.routes != 0)
+            .count()
+    );
+    print_unattributed("Unrouted", &report.unrouted, report.scanned());
+    println!("Total Unreadable: {} /16s", total_damaged);
+}
+
+async fn report_countries(
+    source: &DataSource,
+    subnet: Subnet,
+    database: Arc<GeoIpDatabase>,
+    jobs: usize,
+    top: Option<usize>,
+    format: StatsFormat,
+) {
+    let mut report = CountryReport::default();
+
+    /* Countries are summed up, so the /16s can finish in any order */
+
+    let attribute = {
+        let database = database.clone();
+        move |b, scope, results: &_| {
+            Locator::new(database.clone())
+                .attribute_slash_16(b, scope, results)
+                .expect("Failed to look up an address in the GeoIP database")
+        }
+    };
+
+    let total_damaged = attribute_subnet(source, subnet, jobs, attribute, |attribution| {
+        report.add(&attribution)
+    })
+    .await
+    .unwrap_or_else(|e| panic!("Failed to read {subnet}: {e}"));
+
+    /* Rank by the number of replies, ties go to the lower country code */
+
+    let csv = format == StatsFormat::Csv;
+
+    match csv {
+        true => print_country_csv_header(),
+        false => print_country_table_header(),
+    }
+
+    let rows = report
+        .countries
+        .iter()
+        .filter(|(_, totals)| totals.counts.scanned() != 0);
+
+    print_ranked(rows, true, top, |rank, country, totals| match csv {
+        true => print_country_csv_row(rank, country, totals),
+        false => print_country_table_row(rank, country, totals),
+    });
+
+    if csv {
+        return;
+    }
+
+    println!(
+        "Located: {} countries in {}",
+        report.countries.len(),
+        database.database_type()
+    );
+    print_unattributed("Unlocated", &report.unlocated.counts, report.scanned());
+    println!("Total Unreadable: {} /16s", total_damaged);
+}
+
+async fn report_delegations(
+    source: &DataSource,
+    subnet: Subnet,
+    table: Arc<DelegationTable>,
+    jobs: usize,
+    per: DelegationGroup,
+    top: Option<usize>,
+    format: StatsFormat,
+) {
+    let mut report = DelegationReport::new(&table, per, subnet);
+
+    /* Delegations are summed up, so the /16s can finish in any order */
+
+    let attribute = {
+        let table = table.clone();
+        move |b, scope, results: &_| table.attribute_slash_16(b, scope, results, per)
+    };
+
+    let total_damaged = attribute_subnet(source, subnet, jobs, attribute, |attribution| {
+        report.add(&attribution)
+    })
+    .await
+    .unwrap_or_else(|e| panic!("Failed to read {subnet}: {e}"));
+
+    /* Years are listed in order, RIRs and countries by the number of replies */
+
+    let csv = format == StatsFormat::Csv;
+
+    match csv {
+        true => print_delegation_csv_header(per),
+        false => print_delegation_table_header(per),
+    }
+
+    let by_alive = per != DelegationGroup::Year;
+
+    print_ranked(
+        &report.groups,
+        by_alive,
+        top,
+        |rank, key, totals| match csv {
+            true => print_delegation_csv_row(rank, key, totals),
+            false => print_delegation_table_row(rank, key, totals),
+        },
+    );
+
+    if csv {
+        return;
+    }
+
+    println!(
+        "Delegated: {} addresses in {} blocks",
+        report
+            .groups
+            .values()
+            .map(|totals| totals.delegated)
+            .sum::<u64>(),
+        table.delegations_in(subnet).len()
+    );
+    print_unattributed("Undelegated", &report.undelegated.counts, report.scanned());
+    println!("Total Unreadable: {} /16s", total_damaged);
+}
+
+/// A /8 with none of its /16s scanned is reported like a missing /16
+fn print_slash_8_row(printer: &mut StatsPrinter, subnet: Subnet, anal: Analysis) {
+    let anal = (anal.scanned() != 0).then_some(anal);
 
-    Ok(Some(anal))
+    printer
+        .print_row(subnet, anal.as_ref())
+        .expect("Failed to print the stats");
 }
@@ -0,0 +1,263 @@
+use std::path::PathBuf;
+
+use futures::StreamExt;
+use image::RgbImage;
+use ping_the_internet::{
+    file::{summary::summarize_slash_8, DataSource, StorageError},
+    progress::Progress,
+    render::{Curve, MapLayout, MapStyle},
+    subnet::{Subnet, SubnetMask},
+    tiles::{
+        assemble_level, save_tile, tile_path, tile_position, CountTile, MAX_ZOOM, SLASH_8_ZOOM,
+        TILE_SIZE,
+    },
+};
+
+const USAGE: &str = "Usage: tiles <output directory> [--source <data root or archive>] [--max-zoom <0-8>] [--curve <hilbert|z-order>] [--palette <default|grayscale|colorblind|light>] [--jobs <n>]";
+
+/// Renders a run as a pyramid of 256x256 tiles laid out as `z/x/y.png`, for
+/// any web map viewer
+///
+/// Zoom 0 is the whole internet at a pixel per /16, zoom 4 a /8 per tile at a
+/// pixel per /24 and zoom 8 a /16 per tile at a pixel per address. Deeper
+/// tiles of space without results are left out
+///
+/// Zoom levels down to 3 are rendered from the summaries every time. Deeper
+/// ones are rendered a /8 at a time, ending with its zoom 4 tile, so /8s that
+/// have one are skipped and an interrupted run picks up where it left off.
+/// Delete the directory to render results that changed since
+#[tokio::main]
+async fn main() {
+    let mut root = None;
+    let mut source = None;
+    let mut max_zoom = MAX_ZOOM;
+    let mut curve = Curve::default();
+    let mut style = MapStyle::default();
+    let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => source = Some(args.next().expect(USAGE)),
+            "--max-zoom" => {
+                let value = args.next().and_then(|zoom| zoom.parse().ok());
+                max_zoom = value.filter(|zoom| *zoom <= MAX_ZOOM).expect(USAGE);
+            }
+            "--curve" => {
+                let value = args.next().and_then(|curve| curve.parse().ok());
+                curve = value.expect(USAGE);
+            }
+            "--palette" => {
+                let value = args.next().and_then(|palette| palette.parse().ok());
+                style.palette = value.expect(USAGE);
+            }
+            "--jobs" => {
+                let value = args.next().and_then(|jobs| jobs.parse().ok());
+                jobs = value.filter(|jobs| *jobs > 0).expect(USAGE);
+            }
+            arg if root.is_none() => root = Some(PathBuf::from(arg)),
+            _ => panic!("{USAGE}"),
+        }
+    }
+
+    let pyramid = Pyramid {
+        root: root.expect(USAGE),
+        curve,
+        style,
+        max_zoom,
+    };
+
+    let source = match source {
+        Some(path) => DataSource::open(path)
+            .await
+            .expect("Failed to open the data source"),
+        None => DataSource::default(),
+    };
+
+    /* Deep levels a /8 at a time, skipping the /8s an earlier run finished */
+
+    if max_zoom > SLASH_8_ZOOM {
+        let slash_8s: Vec<Subnet> = Subnet::default()
+            .iter_subnets()
+            .filter(|slash_8| {
+                let index = slash_8.octets()[0] as u64;
+                let position = tile_position(curve, SLASH_8_ZOOM, index);
+
+                !tile_path(&pyramid.root, SLASH_8_ZOOM, position).exists()
+            })
+            .collect();
+
+        let mut progress = Progress::new("/16s", slash_8s.len() as u64 * 256);
+
+        for slash_8 in slash_8s {
+            render_slash_8(&source, &pyramid, slash_8, jobs, &mut progress).await;
+        }
+
+        progress.finish();
+    }
+
+    /* Shallow levels from the summaries, with a /8 per tile at zoom 4 */
+
+    let mut damaged: u32 = 0;
+    let mut progress = Progress::new("/16s", 65536);
+    let mut level = Vec::new();
+
+    for slash_8 in Subnet::default().iter_subnets() {
+        let mut tile = CountTile::new(TILE_SIZE);
+
+        summarize_slash_8(&source, slash_8, jobs, |b, result| {
+            progress.tick(1);
+
+            match result {
+                Ok(Some(summary)) => tile.add_slash_16_summary(curve, b, &summary),
+                Ok(None) => {}
+                Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                    damaged += 1;
+                }
+                Err(e) => panic!("Failed to read {b}: {e}"),
+            }
+        })
+        .await
+        .unwrap_or_else(|e| panic!("Failed to summarize {slash_8}: {e}"));
+
+        let index = slash_8.octets()[0] as u64;
+
+        if max_zoom == SLASH_8_ZOOM {
+            pyramid.save(SLASH_8_ZOOM, index, &tile.render(&style));
+        }
+
+        level.push((index, tile.downsample()));
+    }
+
+    progress.finish();
+
+    for zoom in (0..SLASH_8_ZOOM).rev() {
+        let tiles = assemble_level(curve, zoom + 1, level);
+
+        if zoom <= max_zoom {
+            for (index, tile) in &tiles {
+                pyramid.save(zoom, *index, &tile.render(&style));
+            }
+        }
+
+        level = tiles
+            .into_iter()
+            .map(|(index, tile)| (index, tile.downsample()))
+            .collect();
+    }
+
+    if damaged != 0 {
+        eprintln!("Left out {damaged} unreadable /16s");
+    }
+}
+
+/// Where and how tiles are rendered
+#[derive(Clone)]
+struct Pyramid {
+    root: PathBuf,
+    curve: Curve,
+    style: MapStyle,
+    max_zoom: u8,
+}
+
+impl Pyramid {
+    /// Saves the nth tile of a zoom level
+    fn save(&self, zoom: u8, index: u64, tile: &RgbImage) {
+        let position = tile_position(self.curve, zoom, index);
+
+        save_tile(&self.root, zoom, position, tile).expect("Failed to save tile");
+    }
+}
+
+/// Renders the tiles under the zoom 4 tile of a /8, and then that tile
+///
+/// Unreadable /16s are left out, the pass over the summaries counts them
+async fn render_slash_8(
+    source: &DataSource,
+    pyramid: &Pyramid,
+    slash_8: Subnet,
+    jobs: usize,
+    progress: &mut Progress,
+) {
+    let Pyramid {
+        curve,
+        style,
+        max_zoom,
+        ..
+    } = *pyramid;
+
+    /* A /16 per tile at the deepest level, read on every core */
+
+    let mut slash_16s = futures::stream::iter(slash_8.iter_slash_16s())
+        .map(|b| {
+            let source = source.clone();
+            let pyramid = pyramid.clone();
+
+            let task = tokio::spawn(async move {
+                let Some(results) = source.read_slash_16(b).await? else {
+                    return Ok(None);
+                };
+
+                let counts = CountTile::of_slash_16(curve, b, &results);
+                let index = u32::from(b.base_address()) as u64 >> 16;
+
+                if max_zoom == MAX_ZOOM && counts.is_scanned() {
+                    let layout = MapLayout::new(b, SubnetMask::Slash32, curve);
+                    let mut tile = layout.new_map(&style);
+
+                    layout.draw_slash_16_addresses(&mut tile, &style, b, &results);
+
+                    pyramid.save(MAX_ZOOM, index, &tile);
+                }
+
+                Ok::<_, StorageError>(Some((index, counts.downsample())))
+            });
+
+            async move { (b, task.await.expect("Tile worker panicked")) }
+        })
+        .buffer_unordered(jobs);
+
+    let mut level = Vec::new();
+
+    while let Some((b, result)) = slash_16s.next().await {
+        progress.tick(1);
+
+        match result {
+            Ok(Some(quarter)) => level.push(quarter),
+            Ok(None) => {}
+            Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {}
+            Err(e) => panic!("Failed to read {b}: {e}"),
+        }
+    }
+
+    /* Sum them up to the /8, which is saved last to mark it done */
+
+    for zoom in (SLASH_8_ZOOM..MAX_ZOOM).rev() {
+        let tiles = assemble_level(curve, zoom + 1, level);
+
+        for (index, tile) in &tiles {
+            if zoom == SLASH_8_ZOOM || (zoom <= max_zoom && tile.is_scanned()) {
+                pyramid.save(zoom, *index, &tile.render(&style));
+            }
+        }
+
+        level = tiles
+            .into_iter()
+            .map(|(index, tile)| (index, tile.downsample()))
+            .collect();
+    }
+
+    /* A /8 with no results at all still gets its zoom 4 tile */
+
+    let index = slash_8.octets()[0] as u64;
+    let position = tile_position(curve, SLASH_8_ZOOM, index);
+
+    if !tile_path(&pyramid.root, SLASH_8_ZOOM, position).exists() {
+        pyramid.save(
+            SLASH_8_ZOOM,
+            index,
+            &CountTile::new(TILE_SIZE).render(&style),
+        );
+    }
+}
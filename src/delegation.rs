@@ -0,0 +1,644 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind},
+    net::Ipv4Addr,
+    path::Path,
+};
+
+use crate::{
+    attribution::{density_percent, AddressCounts, Attributed},
+    ping::PingResult,
+    stats::{RttStats, Slash16Result},
+    subnet::{Subnet, SubnetMask},
+};
+
+/// The five Regional Internet Registries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rir {
+    Afrinic,
+    Apnic,
+    Arin,
+    Lacnic,
+    RipeNcc,
+}
+
+impl std::str::FromStr for Rir {
+    type Err = ();
+
+    /// Parses the registry field of a delegated stats file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "afrinic" => Ok(Self::Afrinic),
+            "apnic" => Ok(Self::Apnic),
+            "arin" => Ok(Self::Arin),
+            "lacnic" => Ok(Self::Lacnic),
+            "ripencc" => Ok(Self::RipeNcc),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Rir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Afrinic => "AFRINIC",
+            Self::Apnic => "APNIC",
+            Self::Arin => "ARIN",
+            Self::Lacnic => "LACNIC",
+            Self::RipeNcc => "RIPE NCC",
+        })
+    }
+}
+
+/// A block of addresses a RIR allocated or assigned to an organisation
+#[derive(Debug, Clone)]
+pub struct Delegation {
+    pub registry: Rir,
+    /// The ISO 3166-1 code of the country the holder is in
+    pub country: [u8; 2],
+    pub start: u32,
+    /// Inclusive, blocks need not be a power of two in size
+    pub end: u32,
+    /// None for the blocks with no date, mostly those delegated before the RIRs existed
+    pub year: Option<u16>,
+}
+
+impl Delegation {
+    pub fn addresses(&self) -> u64 {
+        (self.end - self.start) as u64 + 1
+    }
+
+    pub fn group(&self, per: DelegationGroup) -> DelegationKey {
+        match per {
+            DelegationGroup::Rir => DelegationKey::Rir(self.registry),
+            DelegationGroup::Country => DelegationKey::Country(self.country),
+            DelegationGroup::Year => DelegationKey::Year(self.year),
+        }
+    }
+}
+
+/// What delegations are summed up by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelegationGroup {
+    Rir,
+    Country,
+    /// The year of the delegation
+    Year,
+}
+
+impl std::str::FromStr for DelegationGroup {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rir" => Ok(Self::Rir),
+            "country" => Ok(Self::Country),
+            "year" => Ok(Self::Year),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The RIR, country or year a group of delegations shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DelegationKey {
+    Rir(Rir),
+    Country([u8; 2]),
+    Year(Option<u16>),
+}
+
+impl Display for DelegationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rir(rir) => rir.fmt(f),
+            Self::Country(code) => f.pad(std::str::from_utf8(code).unwrap_or("??")),
+            Self::Year(Some(year)) => f.pad(&year.to_string()),
+            Self::Year(None) => f.pad("-"),
+        }
+    }
+}
+
+/// The allocated and assigned IPv4 blocks of the RIRs' delegated stats files
+///
+/// Delegations are kept sorted and non-overlapping so that a run of addresses
+/// is attributed with a walk rather than a search
+#[derive(Debug, Clone, Default)]
+pub struct DelegationTable {
+    delegations: Vec<Delegation>,
+}
+
+impl DelegationTable {
+    /// Reads delegated stats files, or every `delegated-*` file of the directories given
+    pub fn open<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> std::io::Result<Self> {
+        let mut delegations = Vec::new();
+
+        for path in paths {
+            let path = path.as_ref();
+
+            if !path.is_dir() {
+                read_delegated(BufReader::new(File::open(path)?), &mut delegations)?;
+                continue;
+            }
+
+            let mut files = Vec::new();
+
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+
+                if entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("delegated-")
+                {
+                    files.push(entry.path());
+                }
+            }
+
+            files.sort();
+
+            for file in files {
+                read_delegated(BufReader::new(File::open(file)?), &mut delegations)?;
+            }
+        }
+
+        Ok(Self::from_delegations(delegations))
+    }
+
+    /// Builds a table from delegations in any order
+    ///
+    /// The RIRs never delegate the same space twice, but should two blocks
+    /// overlap anyway the addresses they share go to the one that starts first
+    pub fn from_delegations(mut delegations: Vec<Delegation>) -> Self {
+        delegations.sort_by_key(|delegation| (delegation.start, delegation.end));
+
+        let mut table = Self::default();
+
+        for mut delegation in delegations {
+            if let Some(last) = table.delegations.last() {
+                if delegation.end <= last.end {
+                    continue;
+                }
+
+                delegation.start = delegation.start.max(last.end + 1);
+            }
+
+            table.delegations.push(delegation);
+        }
+
+        table
+    }
+
+    pub fn delegations(&self) -> &[Delegation] {
+        &self.delegations
+    }
+
+    /// The delegations that overlap a subnet
+    pub fn delegations_in(&self, scope: Subnet) -> &[Delegation] {
+        let scope = scope.address_range();
+
+        let start = self
+            .delegations
+            .partition_point(|delegation| delegation.end < *scope.start());
+        let end = self
+            .delegations
+            .partition_point(|delegation| delegation.start <= *scope.end());
+
+        &self.delegations[start..end.max(start)]
+    }
+
+    /// The number of addresses of a subnet in every group of delegations
+    pub fn delegated_addresses(
+        &self,
+        per: DelegationGroup,
+        scope: Subnet,
+    ) -> BTreeMap<DelegationKey, u64> {
+        let mut delegated = BTreeMap::new();
+        let range = scope.address_range();
+
+        for delegation in self.delegations_in(scope) {
+            let start = delegation.start.max(*range.start());
+            let end = delegation.end.min(*range.end());
+
+            *delegated.entry(delegation.group(per)).or_default() += (end - start) as u64 + 1;
+        }
+
+        delegated
+    }
+
+    /// Returns the delegation an address is in, if any
+    pub fn lookup(&self, address: Ipv4Addr) -> Option<&Delegation> {
+        let address = u32::from(address);
+        let i = self
+            .delegations
+            .partition_point(|delegation| delegation.end < address);
+
+        self.delegations
+            .get(i)
+            .filter(|delegation| delegation.start <= address)
+    }
+
+    /// Attributes every scanned address of a /16 inside the scope to the group of its delegation
+    pub fn attribute_slash_16(
+        &self,
+        subnet: Subnet,
+        scope: Subnet,
+        results: &Slash16Result,
+        per: DelegationGroup,
+    ) -> Slash16Delegations {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "attribute_slash_16 only takes /16 subnets"
+        );
+
+        let base = u32::from(subnet.base_address());
+
+        let mut attribution = Slash16Delegations::default();
+        let mut i = self
+            .delegations
+            .partition_point(|delegation| delegation.end < base);
+
+        for (c, slash_24) in results.iter().enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            for (d, ping_result) in slash_24.iter().enumerate() {
+                let address = base | (c as u32) << 8 | d as u32;
+
+                if !scope.contains(address.into()) {
+                    continue;
+                }
+
+                while self
+                    .delegations
+                    .get(i)
+                    .is_some_and(|delegation| delegation.end < address)
+                {
+                    i += 1;
+                }
+
+                let delegation = self
+                    .delegations
+                    .get(i)
+                    .filter(|delegation| delegation.start <= address);
+
+                let totals = match delegation {
+                    Some(delegation) => {
+                        attribution.groups.entry(delegation.group(per)).or_default()
+                    }
+                    None => &mut attribution.undelegated,
+                };
+
+                totals.count(ping_result);
+            }
+        }
+
+        attribution
+    }
+}
+
+/// The counts and RTTs of the scanned addresses of a group of delegations
+#[derive(Debug, Clone, Default)]
+pub struct DelegationTotals {
+    pub counts: AddressCounts,
+    pub rtt: RttStats,
+    /// The addresses of the delegations in the group inside the scope, scanned or not
+    pub delegated: u64,
+}
+
+impl DelegationTotals {
+    fn count(&mut self, ping_result: &PingResult) {
+        self.counts.count(ping_result);
+
+        if let Some(rtt) = ping_result.rtt() {
+            self.rtt.record(rtt);
+        }
+    }
+
+    pub fn merge(&mut self, other: &DelegationTotals) {
+        self.counts.merge(&other.counts);
+        self.rtt.merge(&other.rtt);
+    }
+
+    /// The share of the delegated space that replied
+    pub fn density_percent(&self) -> f32 {
+        density_percent(self.counts.alive, self.delegated)
+    }
+}
+
+impl Attributed for DelegationTotals {
+    fn counts(&self) -> &AddressCounts {
+        &self.counts
+    }
+}
+
+/// The groups the scanned addresses of a single /16 were attributed to
+#[derive(Debug, Clone, Default)]
+pub struct Slash16Delegations {
+    pub groups: HashMap<DelegationKey, DelegationTotals>,
+    /// The addresses in no allocated or assigned block
+    pub undelegated: DelegationTotals,
+}
+
+/// The attribution of a whole run, summed up from [`Slash16Delegations`]
+#[derive(Debug, Clone)]
+pub struct DelegationReport {
+    pub groups: BTreeMap<DelegationKey, DelegationTotals>,
+    pub undelegated: DelegationTotals,
+}
+
+impl DelegationReport {
+    /// Starts with every group of the table in the scope, so groups with
+    /// nothing scanned are still reported, and only their delegated space
+    /// inside the scope counts towards their density
+    pub fn new(table: &DelegationTable, per: DelegationGroup, scope: Subnet) -> Self {
+        let groups = table
+            .delegated_addresses(per, scope)
+            .into_iter()
+            .map(|(key, delegated)| {
+                let totals = DelegationTotals {
+                    delegated,
+                    ..Default::default()
+                };
+
+                (key, totals)
+            })
+            .collect();
+
+        Self {
+            groups,
+            undelegated: DelegationTotals::default(),
+        }
+    }
+
+    pub fn add(&mut self, attribution: &Slash16Delegations) {
+        for (key, totals) in &attribution.groups {
+            self.groups.entry(*key).or_default().merge(totals);
+        }
+
+        self.undelegated.merge(&attribution.undelegated);
+    }
+
+    /// The number of scanned addresses, delegated or not
+    pub fn scanned(&self) -> u64 {
+        self.groups
+            .values()
+            .map(|totals| totals.counts.scanned())
+            .sum::<u64>()
+            + self.undelegated.counts.scanned()
+    }
+}
+
+pub fn print_delegation_table_header(per: DelegationGroup) {
+    let group = match per {
+        DelegationGroup::Rir => "RIR",
+        DelegationGroup::Country => "COUNTRY",
+        DelegationGroup::Year => "YEAR",
+    };
+
+    println!(
+        "| {:^4} | {:^8} | {:^10} | {:^10} | {:^10} | {:^9} | {:^9} | {:^6} | {:^6} | {:^6} |",
+        "RANK",
+        group,
+        "DELEGATED",
+        "SCANNED",
+        "SUCCEEDED",
+        "ALIVE %",
+        "DENSITY",
+        "MED ms",
+        "P90 ms",
+        "P99 ms",
+    );
+    println!(
+        "|{:->6}|{:->10}|{:->12}|{:->12}|{:->12}|{:->11}|{:->11}|{:->8}|{:->8}|{:->8}|",
+        "", "", "", "", "", "", "", "", "", ""
+    );
+}
+
+pub fn print_delegation_table_row(rank: usize, key: &DelegationKey, totals: &DelegationTotals) {
+    let ms = |rtt: Option<std::time::Duration>| {
+        rtt.map_or("-".to_string(), |rtt| rtt.as_millis().to_string())
+    };
+
+    println!(
+        "| {:>4} | {:>8} | {:>10} | {:>10} | {:>10} | {:>9} | {:>9} | {:>6} | {:>6} | {:>6} |",
+        rank,
+        format!("{key}"),
+        totals.delegated,
+        totals.counts.scanned(),
+        totals.counts.alive,
+        format!("{:.2}%", totals.counts.alive_percent()),
+        format!("{:.2}%", totals.density_percent()),
+        ms(totals.rtt.median()),
+        ms(totals.rtt.p90()),
+        ms(totals.rtt.p99()),
+    );
+}
+
+pub fn print_delegation_csv_header(per: DelegationGroup) {
+    let group = match per {
+        DelegationGroup::Rir => "rir",
+        DelegationGroup::Country => "country",
+        DelegationGroup::Year => "year",
+    };
+
+    println!("rank,{group},delegated,scanned,alive,alive_percent,density_percent,rtt_median_ms,rtt_p90_ms,rtt_p99_ms");
+}
+
+pub fn print_delegation_csv_row(rank: usize, key: &DelegationKey, totals: &DelegationTotals) {
+    let ms = |rtt: Option<std::time::Duration>| {
+        rtt.map_or(String::new(), |rtt| rtt.as_millis().to_string())
+    };
+
+    println!(
+        "{},{},{},{},{},{:.2},{:.2},{},{},{}",
+        rank,
+        key,
+        totals.delegated,
+        totals.counts.scanned(),
+        totals.counts.alive,
+        totals.counts.alive_percent(),
+        totals.density_percent(),
+        ms(totals.rtt.median()),
+        ms(totals.rtt.p90()),
+        ms(totals.rtt.p99()),
+    );
+}
+
+/// Reads the allocated and assigned IPv4 blocks of a delegated stats file, e.g.
+/// `delegated-ripencc-extended-latest`
+///
+/// Records are `registry|cc|type|start|value|date|status[|opaque-id[|extensions...]]`,
+/// after a version line and summary lines, which are skipped along with
+/// available and reserved space
+fn read_delegated(reader: impl BufRead, delegations: &mut Vec<Delegation>) -> std::io::Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<_> = line.split('|').collect();
+
+        let [registry, country, record_type, start, value, date, status, ..] = fields.as_slice()
+        else {
+            /* Summary lines have a field less */
+
+            continue;
+        };
+
+        /* The version line has as many fields, with the serial in place of the type */
+
+        if *record_type != "ipv4" || !matches!(*status, "allocated" | "assigned") {
+            continue;
+        }
+
+        let invalid = |field: &str| invalid_data(format!("line {}: invalid {field}", i + 1));
+
+        let registry: Rir = registry.parse().map_err(|_| invalid("registry"))?;
+        let start: Ipv4Addr = start.parse().map_err(|_| invalid("start"))?;
+        let count: u64 = value
+            .parse()
+            .ok()
+            .filter(|count| *count != 0)
+            .ok_or_else(|| invalid("value"))?;
+
+        let start = u32::from(start);
+        let end = u32::try_from(start as u64 + count - 1).map_err(|_| invalid("value"))?;
+
+        let country = country.as_bytes().try_into().unwrap_or(*b"ZZ");
+
+        /* Dates are YYYYMMDD, with 00000000 or nothing when unknown */
+
+        let year = date
+            .get(..4)
+            .and_then(|year| year.parse().ok())
+            .filter(|year| *year != 0);
+
+        delegations.push(Delegation {
+            registry,
+            country,
+            start,
+            end,
+            year,
+        });
+    }
+
+    Ok(())
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELEGATED: &str = "\
+2.3|ripencc|20240101|5|19830705|20240101|+0100
+# A comment
+ripencc|*|ipv4|*|4|summary
+ripencc|NL|ipv4|2.56.128.0|65536|20190417|allocated|a1
+ripencc|FR|ipv4|2.57.128.0|768|00000000|assigned
+ripencc|DE|ipv4|2.58.0.0|256||allocated|a2
+ripencc||ipv4|2.59.0.0|256|20200101|available
+ripencc||ipv4|2.60.0.0|256|20200101|reserved
+ripencc|NL|ipv6|2001:db8::|32|20100101|allocated|a1
+ripencc|NL|asn|64500|1|20100101|allocated|a1
+";
+
+    fn read(input: &str) -> std::io::Result<Vec<Delegation>> {
+        let mut delegations = Vec::new();
+        read_delegated(input.as_bytes(), &mut delegations)?;
+
+        Ok(delegations)
+    }
+
+    #[test]
+    fn reads_delegated_ipv4_blocks() {
+        let delegations: Vec<_> = read(DELEGATED)
+            .unwrap()
+            .into_iter()
+            .map(|delegation| {
+                (
+                    delegation.registry,
+                    delegation.country,
+                    Ipv4Addr::from(delegation.start),
+                    delegation.addresses(),
+                    delegation.year,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            delegations,
+            [
+                (
+                    Rir::RipeNcc,
+                    *b"NL",
+                    Ipv4Addr::new(2, 56, 128, 0),
+                    65536,
+                    Some(2019)
+                ),
+                (
+                    Rir::RipeNcc,
+                    *b"FR",
+                    Ipv4Addr::new(2, 57, 128, 0),
+                    768,
+                    None
+                ),
+                (Rir::RipeNcc, *b"DE", Ipv4Addr::new(2, 58, 0, 0), 256, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_delegated_lines() {
+        for (line, message) in [
+            (
+                "nic|NL|ipv4|2.56.0.0|1024|20190417|allocated",
+                "invalid registry",
+            ),
+            (
+                "ripencc|NL|ipv4|2.56.0|1024|20190417|allocated",
+                "invalid start",
+            ),
+            (
+                "ripencc|NL|ipv4|2.56.0.0|0|20190417|allocated",
+                "invalid value",
+            ),
+            (
+                "ripencc|NL|ipv4|255.255.255.0|512|20190417|allocated",
+                "invalid value",
+            ),
+        ] {
+            let error = read(&format!("# header\n{line}\n")).unwrap_err();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), format!("line 2: {message}"));
+        }
+    }
+
+    #[test]
+    fn clips_delegations_to_a_scope() {
+        let table = DelegationTable::from_delegations(read(DELEGATED).unwrap());
+        let scope = "2.57.x.x".parse().ok().unwrap();
+
+        assert_eq!(table.delegations_in(scope).len(), 2);
+
+        let delegated = table.delegated_addresses(DelegationGroup::Country, scope);
+
+        assert_eq!(
+            delegated.into_iter().collect::<Vec<_>>(),
+            [
+                (DelegationKey::Country(*b"FR"), 768),
+                (DelegationKey::Country(*b"NL"), 32768),
+            ]
+        );
+    }
+}
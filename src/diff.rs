@@ -0,0 +1,545 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression};
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    multi::count,
+    number::complete::{le_i16, u8 as parse_u8},
+    IResult,
+};
+
+use crate::{
+    file::{
+        create_file_path_in,
+        mapped::{Slash16View, Slash24View},
+        StorageError,
+    },
+    ping::PingResult,
+    subnet::{Subnet, SubnetMask},
+};
+
+const MAGIC: &[u8; 4] = b"PTID";
+
+const DIFF_VERSION: u8 = 1;
+
+/// How a single address changed between two runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressDiff {
+    /// It replied in the second run only
+    Appeared,
+    /// It replied in the first run only
+    Disappeared,
+    /// It replied in both runs, with the change in RTT in ms unless either RTT is unknown
+    StillAlive(Option<i16>),
+    /// It replied in neither run
+    StillDown,
+}
+
+pub type Slash16Diff = Arc<[Option<Slash24Diff>; 256]>;
+pub type Slash24Diff = Arc<[AddressDiff; 256]>;
+
+impl AddressDiff {
+    pub fn of(before: &PingResult, after: &PingResult) -> Self {
+        match (before, after) {
+            (PingResult::Success(_), PingResult::Success(_)) => {
+                let delta = match (before.rtt(), after.rtt()) {
+                    (Some(before), Some(after)) => {
+                        let delta = after.as_millis() as i64 - before.as_millis() as i64;
+                        Some(delta.clamp(i16::MIN as i64, i16::MAX as i64) as i16)
+                    }
+                    _ => None,
+                };
+
+                Self::StillAlive(delta)
+            }
+            (PingResult::Success(_), _) => Self::Disappeared,
+            (_, PingResult::Success(_)) => Self::Appeared,
+            _ => Self::StillDown,
+        }
+    }
+}
+
+/// Compares every address of a /16 in two runs, /24s missing from either run are left out
+pub fn diff_slash_16(before: &Slash16View, after: &Slash16View) -> Slash16Diff {
+    let slash_16: Vec<_> = before
+        .iter()
+        .zip(after.iter())
+        .map(|(before, after)| Some(diff_slash_24(before?, after?)))
+        .collect();
+
+    Arc::new(slash_16.try_into().unwrap())
+}
+
+fn diff_slash_24(before: Slash24View, after: Slash24View) -> Slash24Diff {
+    let slash_24: Vec<_> = before
+        .iter()
+        .zip(after.iter())
+        .map(|(before, after)| AddressDiff::of(&before, &after))
+        .collect();
+
+    Arc::new(slash_24.try_into().unwrap())
+}
+
+/// The counts of changes in a subnet
+#[derive(Debug, Clone, Default)]
+pub struct DiffSummary {
+    pub appeared: u64,
+    pub disappeared: u64,
+    pub still_alive: u64,
+    pub still_down: u64,
+    /// The addresses which were not scanned in both runs
+    pub unscanned: u64,
+    /// The sum of the RTT changes of the addresses still alive, in ms
+    pub rtt_delta_total: i64,
+    /// The addresses still alive whose RTT is known in both runs
+    pub rtt_deltas: u64,
+}
+
+impl DiffSummary {
+    pub fn of_slash_16(diff: &Slash16Diff) -> Self {
+        let mut summary = Self::default();
+
+        for slash_24 in &**diff {
+            match slash_24 {
+                Some(slash_24) => summary.merge(&Self::of_slash_24(slash_24)),
+                None => summary.unscanned += 256,
+            }
+        }
+
+        summary
+    }
+
+    pub fn of_slash_24(diff: &Slash24Diff) -> Self {
+        let mut summary = Self::default();
+
+        for address in &**diff {
+            match address {
+                AddressDiff::Appeared => summary.appeared += 1,
+                AddressDiff::Disappeared => summary.disappeared += 1,
+                AddressDiff::StillAlive(delta) => {
+                    summary.still_alive += 1;
+
+                    if let Some(delta) = delta {
+                        summary.rtt_delta_total += *delta as i64;
+                        summary.rtt_deltas += 1;
+                    }
+                }
+                AddressDiff::StillDown => summary.still_down += 1,
+            }
+        }
+
+        summary
+    }
+
+    /// A /16 that is missing from either run
+    pub fn unscanned_slash_16() -> Self {
+        Self {
+            unscanned: 65536,
+            ..Default::default()
+        }
+    }
+
+    /// Adds the counts of another subnet, e.g. to sum /16s into a /8
+    pub fn merge(&mut self, other: &Self) {
+        self.appeared += other.appeared;
+        self.disappeared += other.disappeared;
+        self.still_alive += other.still_alive;
+        self.still_down += other.still_down;
+        self.unscanned += other.unscanned;
+        self.rtt_delta_total += other.rtt_delta_total;
+        self.rtt_deltas += other.rtt_deltas;
+    }
+
+    /// The mean RTT change of the addresses still alive with a known RTT, in ms
+    pub fn mean_rtt_delta(&self) -> f32 {
+        if self.rtt_deltas == 0 {
+            return 0.0;
+        }
+
+        self.rtt_delta_total as f32 / self.rtt_deltas as f32
+    }
+}
+
+pub fn print_diff_table_header() {
+    println!(
+        "| {:^13} | {:^9} | {:^11} | {:^11} | {:^9} |",
+        "IP ADDRESS", "APPEARED", "DISAPPEARED", "STILL ALIVE", "RTT DELTA",
+    );
+    println!(
+        "|{:->15}|{:->11}|{:->13}|{:->13}|{:->11}|",
+        "", "", "", "", ""
+    );
+}
+
+pub fn print_diff_table_row(subnet: Subnet, summary: Option<&DiffSummary>) {
+    match summary {
+        Some(summary) => println!(
+            "| {:>13} | {:>9} | {:>11} | {:>11} | {:>9} |",
+            format!("{subnet}"),
+            summary.appeared,
+            summary.disappeared,
+            summary.still_alive,
+            format!("{:+.1} ms", summary.mean_rtt_delta()),
+        ),
+        None => println!(
+            "| {:>13} | {:^49} |",
+            format!("{subnet}"),
+            "NOT IN BOTH RUNS"
+        ),
+    }
+}
+
+pub fn print_diff_csv_header() {
+    println!("subnet,appeared,disappeared,still_alive,still_down,unscanned,mean_rtt_delta_ms");
+}
+
+pub fn print_diff_csv_row(subnet: Subnet, summary: &DiffSummary) {
+    println!(
+        "{},{},{},{},{},{},{:.2}",
+        subnet,
+        summary.appeared,
+        summary.disappeared,
+        summary.still_alive,
+        summary.still_down,
+        summary.unscanned,
+        summary.mean_rtt_delta(),
+    );
+}
+
+/// Saves the diff of a /16 under a root, in the same `a/b` layout as the data
+///
+/// The body mirrors the tagged /16 encoding: a tag per /24, then a byte per
+/// address, followed by the RTT change for addresses still alive
+pub fn save_slash_16_diff(
+    root: &Path,
+    subnet: Subnet,
+    diff: &Slash16Diff,
+) -> Result<(), StorageError> {
+    assert_eq!(
+        subnet.mask(),
+        SubnetMask::Slash16,
+        "save_slash_16_diff only takes /16 subnets"
+    );
+
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.push(DIFF_VERSION);
+
+    let mut encoder = ZlibEncoder::new(data, Compression::best());
+
+    for slash_24 in &**diff {
+        let Some(slash_24) = slash_24 else {
+            encoder.write_all(&[0x00])?;
+            continue;
+        };
+
+        encoder.write_all(&[0x01])?;
+
+        for address in &**slash_24 {
+            match address {
+                AddressDiff::Appeared => encoder.write_all(&[0x00])?,
+                AddressDiff::Disappeared => encoder.write_all(&[0x01])?,
+                AddressDiff::StillDown => encoder.write_all(&[0x02])?,
+                AddressDiff::StillAlive(Some(delta)) => {
+                    encoder.write_all(&[0x03])?;
+                    encoder.write_all(&delta.to_le_bytes())?;
+                }
+                AddressDiff::StillAlive(None) => encoder.write_all(&[0x04])?,
+            }
+        }
+    }
+
+    let data = encoder.finish()?;
+
+    let file_path = create_file_path_in(root, subnet);
+
+    std::fs::create_dir_all(file_path.parent().unwrap())?;
+    std::fs::write(file_path, data)?;
+
+    Ok(())
+}
+
+/// Reads the diff of a /16 saved by [`save_slash_16_diff`], None if there is none
+pub fn read_slash_16_diff(
+    root: &Path,
+    subnet: Subnet,
+) -> Result<Option<Slash16Diff>, StorageError> {
+    let data = match std::fs::read(create_file_path_in(root, subnet)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let body = match data.strip_prefix(MAGIC).and_then(|rest| rest.split_first()) {
+        Some((&DIFF_VERSION, body)) => body,
+        Some((&version, _)) => return Err(StorageError::UnsupportedVersion(version)),
+        _ => return Err(StorageError::Corrupt("not a diff file".to_string())),
+    };
+
+    let mut decompressed = Vec::new();
+
+    if let Err(e) = ZlibDecoder::new(body).read_to_end(&mut decompressed) {
+        return Err(match e.kind() {
+            ErrorKind::UnexpectedEof => StorageError::Truncated,
+            _ => StorageError::Corrupt(format!("invalid zlib stream ({e})")),
+        });
+    }
+
+    let (input, diff) = parse_slash_16_diff(&decompressed)
+        .map_err(|e| StorageError::from_parse_error(e, "/16 diff"))?;
+
+    if !input.is_empty() {
+        return Err(StorageError::Corrupt(format!(
+            "{} bytes of trailing data",
+            input.len()
+        )));
+    }
+
+    Ok(Some(diff))
+}
+
+/// Whether a directory holds anything but diffs in the `a/b` layout, such as the /16s of a run
+///
+/// Saving diffs there would overwrite that data, while a root of earlier diffs can be reused
+pub fn holds_other_data(root: &Path) -> std::io::Result<bool> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+
+        if entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<u8>().ok())
+            .is_none()
+        {
+            continue;
+        }
+
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        for file in std::fs::read_dir(entry.path())? {
+            let file = file?;
+
+            if file
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<u8>().ok())
+                .is_none()
+            {
+                continue;
+            }
+
+            let mut magic = [0; MAGIC.len()];
+            let read = std::fs::File::open(file.path())?.read_exact(&mut magic);
+
+            if read.is_err() || &magic != MAGIC {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+fn parse_slash_16_diff(input: &[u8]) -> IResult<&[u8], Slash16Diff> {
+    let (input, slash_16) = count(parse_optional_slash_24_diff, 256)(input)?;
+
+    Ok((input, Arc::new(slash_16.try_into().unwrap())))
+}
+
+fn parse_optional_slash_24_diff(input: &[u8]) -> IResult<&[u8], Option<Slash24Diff>> {
+    let (input, enum_tag) = alt((tag(&[0x00]), tag(&[0x01])))(input)?;
+
+    match enum_tag {
+        [0x00] => Ok((input, None)),
+        [0x01] => {
+            let (input, slash_24) = count(parse_address_diff, 256)(input)?;
+
+            Ok((input, Some(Arc::new(slash_24.try_into().unwrap()))))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn parse_address_diff(input: &[u8]) -> IResult<&[u8], AddressDiff> {
+    let (rest, enum_tag) = parse_u8(input)?;
+
+    match enum_tag {
+        0x00 => Ok((rest, AddressDiff::Appeared)),
+        0x01 => Ok((rest, AddressDiff::Disappeared)),
+        0x02 => Ok((rest, AddressDiff::StillDown)),
+        0x03 => {
+            let (rest, delta) = le_i16(rest)?;
+
+            Ok((rest, AddressDiff::StillAlive(Some(delta))))
+        }
+        0x04 => Ok((rest, AddressDiff::StillAlive(None))),
+        _ => Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file::{
+        encode_slash_16, mapped::Decoder, save_slash_16_in, tests::sample_slash_16, SaveOptions,
+    };
+
+    use super::*;
+
+    async fn diff_samples(before: u32, after: u32) -> Slash16Diff {
+        let before = encode_slash_16(&sample_slash_16(before), &SaveOptions::default())
+            .await
+            .unwrap();
+        let after = encode_slash_16(&sample_slash_16(after), &SaveOptions::compact())
+            .await
+            .unwrap();
+
+        let (mut before_decoder, mut after_decoder) = (Decoder::default(), Decoder::default());
+
+        diff_slash_16(
+            &before_decoder.decode(&before).unwrap(),
+            &after_decoder.decode(&after).unwrap(),
+        )
+    }
+
+    #[test]
+    fn diffs_addresses() {
+        let fast = PingResult::Success(std::time::Duration::from_millis(20));
+        let slow = PingResult::Success(std::time::Duration::from_millis(50));
+        let unknown = PingResult::Success(crate::ping::UNKNOWN_RTT);
+
+        assert_eq!(
+            AddressDiff::of(&fast, &slow),
+            AddressDiff::StillAlive(Some(30))
+        );
+        assert_eq!(
+            AddressDiff::of(&slow, &fast),
+            AddressDiff::StillAlive(Some(-30))
+        );
+        assert_eq!(
+            AddressDiff::of(&unknown, &fast),
+            AddressDiff::StillAlive(None)
+        );
+        assert_eq!(
+            AddressDiff::of(&PingResult::Timeout, &fast),
+            AddressDiff::Appeared
+        );
+        assert_eq!(
+            AddressDiff::of(&fast, &PingResult::Error),
+            AddressDiff::Disappeared
+        );
+        assert_eq!(
+            AddressDiff::of(&PingResult::Error, &PingResult::Timeout),
+            AddressDiff::StillDown
+        );
+    }
+
+    #[tokio::test]
+    async fn diffs_and_summarises_slash_16s() {
+        let diff = diff_samples(7, 8).await;
+        let (before, after) = (sample_slash_16(7), sample_slash_16(8));
+
+        let mut expected = DiffSummary::default();
+
+        for c in 0..256 {
+            let (Some(before), Some(after)) = (&before[c], &after[c]) else {
+                assert!(diff[c].is_none());
+                expected.unscanned += 256;
+                continue;
+            };
+
+            let slash_24 = diff[c].as_ref().unwrap();
+
+            for d in 0..256 {
+                assert_eq!(slash_24[d], AddressDiff::of(&before[d], &after[d]));
+            }
+
+            expected.merge(&DiffSummary::of_slash_24(slash_24));
+        }
+
+        let summary = DiffSummary::of_slash_16(&diff);
+
+        assert_eq!(summary.unscanned, expected.unscanned);
+        assert_eq!(
+            summary.appeared + summary.disappeared + summary.still_alive + summary.still_down,
+            65536 - summary.unscanned
+        );
+        assert_eq!(summary.still_alive, expected.still_alive);
+        assert_eq!(summary.rtt_delta_total, expected.rtt_delta_total);
+
+        /* The first address of every sample /24 replied with an unknown RTT */
+
+        assert!(summary.rtt_deltas < summary.still_alive);
+    }
+
+    #[tokio::test]
+    async fn round_trips_diff_files() {
+        let root = tempfile::tempdir().unwrap();
+        let subnet = "10.20.x.x".parse().ok().unwrap();
+
+        let diff = diff_samples(9, 10).await;
+
+        assert!(diff
+            .iter()
+            .flatten()
+            .flat_map(|s| s.iter())
+            .any(|address| { matches!(address, AddressDiff::StillAlive(None)) }));
+
+        save_slash_16_diff(root.path(), subnet, &diff).unwrap();
+
+        assert_eq!(read_slash_16_diff(root.path(), subnet).unwrap(), Some(diff));
+
+        let missing = "10.21.x.x".parse().ok().unwrap();
+        assert_eq!(read_slash_16_diff(root.path(), missing).unwrap(), None);
+
+        /* Newer versions are refused rather than misread */
+
+        let path = create_file_path_in(root.path(), subnet);
+
+        let mut data = std::fs::read(&path).unwrap();
+        data[MAGIC.len()] = DIFF_VERSION + 1;
+        std::fs::write(&path, data).unwrap();
+
+        assert!(matches!(
+            read_slash_16_diff(root.path(), subnet),
+            Err(StorageError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tells_diff_roots_from_runs() {
+        let root = tempfile::tempdir().unwrap();
+        let subnet = "10.20.x.x".parse().ok().unwrap();
+
+        assert!(!holds_other_data(&root.path().join("missing")).unwrap());
+
+        save_slash_16_diff(root.path(), subnet, &diff_samples(1, 2).await).unwrap();
+        assert!(!holds_other_data(root.path()).unwrap());
+
+        let run = "10.21.x.x".parse().ok().unwrap();
+        save_slash_16_in(
+            root.path(),
+            run,
+            sample_slash_16(3),
+            &SaveOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert!(holds_other_data(root.path()).unwrap());
+    }
+}
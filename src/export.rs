@@ -0,0 +1,271 @@
+use std::{io::Write, net::Ipv4Addr};
+
+use serde::Serialize;
+
+use crate::{
+    file::mapped::Slash16View,
+    geoip::{Country, Locator},
+    ping::PingResult,
+    subnet::{Subnet, SubnetMask},
+};
+
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+/// The formats results can be exported as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The result of a single address, as it is exported
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportRow {
+    pub address: Ipv4Addr,
+    pub state: &'static str,
+    /// Only present for successes with a known RTT
+    pub rtt_ms: Option<u16>,
+    /// Only present when exporting with a GeoIP database that has the address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<Country>,
+}
+
+impl ExportRow {
+    pub fn new(address: Ipv4Addr, result: &PingResult) -> Self {
+        let (state, rtt_ms) = match result {
+            PingResult::Success(_) => ("success", result.rtt().map(|time| time.as_millis() as u16)),
+            PingResult::Timeout => ("timeout", None),
+            PingResult::Error => ("error", None),
+        };
+
+        Self {
+            address,
+            state,
+            rtt_ms,
+            country: None,
+        }
+    }
+
+    pub fn is_responsive(&self) -> bool {
+        self.state == "success"
+    }
+}
+
+/// Streams per-address rows of /16 results to a writer
+pub struct Exporter<W: Write> {
+    writer: W,
+    format: ExportFormat,
+    /// Only addresses inside this subnet are written
+    scope: Subnet,
+    responsive_only: bool,
+    /// Adds a country column when given
+    locator: Option<Locator>,
+    rows: u64,
+}
+
+impl<W: Write> Exporter<W> {
+    /// Creates an exporter, writing the CSV header straight away
+    pub fn new(
+        mut writer: W,
+        format: ExportFormat,
+        scope: Subnet,
+        responsive_only: bool,
+        locator: Option<Locator>,
+    ) -> std::io::Result<Self> {
+        if format == ExportFormat::Csv {
+            match locator {
+                Some(_) => writeln!(writer, "address,state,rtt_ms,country")?,
+                None => writeln!(writer, "address,state,rtt_ms")?,
+            }
+        }
+
+        Ok(Self {
+            writer,
+            format,
+            scope,
+            responsive_only,
+            locator,
+            rows: 0,
+        })
+    }
+
+    /// Writes the rows of every address of a /16 that passes the filters
+    pub fn write_slash_16(&mut self, subnet: Subnet, view: &Slash16View) -> std::io::Result<()> {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "write_slash_16 only takes /16 subnets"
+        );
+
+        let [a, b, _, _] = subnet.octets();
+
+        for (c, slash_24) in view.iter().enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            for (d, result) in slash_24.iter().enumerate() {
+                let address = Ipv4Addr::new(a, b, c as u8, d as u8);
+
+                if !self.scope.contains(address) {
+                    continue;
+                }
+
+                let mut row = ExportRow::new(address, &result);
+
+                if self.responsive_only && !row.is_responsive() {
+                    continue;
+                }
+
+                if let Some(locator) = &mut self.locator {
+                    row.country = locator.locate(address)?;
+                }
+
+                self.write_row(&row)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write_row(&mut self, row: &ExportRow) -> std::io::Result<()> {
+        match self.format {
+            ExportFormat::Csv => {
+                write!(self.writer, "{},{},", row.address, row.state)?;
+
+                if let Some(rtt) = row.rtt_ms {
+                    write!(self.writer, "{rtt}")?;
+                }
+
+                if self.locator.is_some() {
+                    write!(self.writer, ",")?;
+
+                    if let Some(country) = row.country {
+                        write!(self.writer, "{country}")?;
+                    }
+                }
+
+                writeln!(self.writer)?;
+            }
+            ExportFormat::Ndjson => {
+                serde_json::to_writer(&mut self.writer, row)?;
+                writeln!(self.writer)?;
+            }
+        }
+
+        self.rows += 1;
+
+        Ok(())
+    }
+
+    /// The number of rows written so far
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Flushes the writer and returns it
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::{
+        file::{encode_slash_16, mapped::Decoder, SaveOptions},
+        ping::UNKNOWN_RTT,
+        stats::Slash16Result,
+    };
+
+    use super::*;
+
+    /// A /16 with only 10.20.0.x scanned, starting with one address of each kind
+    async fn sample_file() -> Vec<u8> {
+        let mut slash_24 = vec![PingResult::Timeout; 256];
+        slash_24[0] = PingResult::Success(Duration::from_millis(12));
+        slash_24[1] = PingResult::Success(UNKNOWN_RTT);
+        slash_24[3] = PingResult::Error;
+
+        let mut slash_16 = vec![None; 256];
+        slash_16[0] = Some(Arc::new(slash_24.try_into().unwrap()));
+
+        let results: Slash16Result = Arc::new(slash_16.try_into().unwrap());
+
+        encode_slash_16(&results, &SaveOptions::default())
+            .await
+            .unwrap()
+    }
+
+    async fn export(format: ExportFormat, scope: &str, responsive_only: bool) -> (String, u64) {
+        let data = sample_file().await;
+
+        let mut decoder = Decoder::default();
+        let scope = scope.parse().ok().unwrap();
+
+        let mut exporter = Exporter::new(Vec::new(), format, scope, responsive_only, None).unwrap();
+        exporter
+            .write_slash_16(
+                "10.20.x.x".parse().ok().unwrap(),
+                &decoder.decode(&data).unwrap(),
+            )
+            .unwrap();
+
+        let rows = exporter.rows();
+
+        (String::from_utf8(exporter.finish().unwrap()).unwrap(), rows)
+    }
+
+    #[tokio::test]
+    async fn writes_csv_rows() {
+        let (csv, rows) = export(ExportFormat::Csv, "10.20.0.x", false).await;
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(rows, 256);
+        assert_eq!(lines.len(), 257);
+        assert_eq!(
+            lines[..5],
+            [
+                "address,state,rtt_ms",
+                "10.20.0.0,success,12",
+                "10.20.0.1,success,",
+                "10.20.0.2,timeout,",
+                "10.20.0.3,error,",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_ndjson_rows() {
+        let (ndjson, rows) = export(ExportFormat::Ndjson, "10.20.x.x", true).await;
+
+        assert_eq!(rows, 2);
+        assert_eq!(
+            ndjson,
+            "{\"address\":\"10.20.0.0\",\"state\":\"success\",\"rtt_ms\":12}\n\
+             {\"address\":\"10.20.0.1\",\"state\":\"success\",\"rtt_ms\":null}\n"
+        );
+
+        let (ndjson, rows) = export(ExportFormat::Ndjson, "10.20.1.x", false).await;
+
+        assert_eq!((ndjson.as_str(), rows), ("", 0));
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    fs::File,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ::parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    errors::ParquetError,
+    file::properties::WriterProperties,
+    format::KeyValue,
+};
+use arrow_array::{
+    builder::{StringDictionaryBuilder, UInt32Builder},
+    types::{Int16Type, Int8Type},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use crate::{
+    file::mapped::Slash16View,
+    geoip::Locator,
+    ping::PingResult,
+    subnet::{Subnet, SubnetMask},
+};
+
+/// Writes /16 results as Parquet, partitioned per /8 the way Hive and DuckDB expect
+///
+/// Every /8 gets its own `slash_8=<a>/results.parquet` file under the output root,
+/// with a row group per /16 and the given export settings as file-level key/values.
+/// The run metadata of the /16 headers is added per file as `ping_the_internet.created`,
+/// an RFC 3339 time or a `<first>/<last>` interval when the /16s were scanned at
+/// different times, and `ping_the_internet.provenance`, one line per distinct entry
+///
+/// A `country` column is added when exporting with a GeoIP database. There is no
+/// TTL column, as /16 files do not keep TTLs even for imports that had them
+pub struct ParquetExporter {
+    root: PathBuf,
+    schema: SchemaRef,
+    properties: WriterProperties,
+    /// Only addresses inside this subnet are written
+    scope: Subnet,
+    responsive_only: bool,
+    /// Adds a country column when given
+    locator: Option<Locator>,
+    /// The /8 currently being written and its writer
+    current: Option<(u8, ArrowWriter<File>)>,
+    /// The number of /16s in the current /8
+    slash_16s: u32,
+    /// The earliest and latest creation time of the /16s in the current /8
+    created: Option<(u64, u64)>,
+    /// The distinct provenance entries of the /16s in the current /8
+    provenance: Vec<String>,
+    rows: u64,
+}
+
+impl ParquetExporter {
+    pub fn new(
+        root: impl AsRef<Path>,
+        metadata: Vec<(String, String)>,
+        scope: Subnet,
+        responsive_only: bool,
+        locator: Option<Locator>,
+    ) -> Self {
+        let mut fields = vec![
+            Field::new("address", DataType::UInt32, false),
+            Field::new(
+                "state",
+                DataType::Dictionary(Box::new(DataType::Int8), Box::new(DataType::Utf8)),
+                false,
+            ),
+            Field::new("rtt_us", DataType::UInt32, true),
+        ];
+
+        if locator.is_some() {
+            // Null for the addresses the database has no country for
+            fields.push(Field::new(
+                "country",
+                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+                true,
+            ));
+        }
+
+        let schema = Schema::new(fields);
+
+        let metadata = metadata
+            .into_iter()
+            .map(|(key, value)| KeyValue::new(key, value))
+            .collect();
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(65536)
+            .set_key_value_metadata(Some(metadata))
+            .build();
+
+        Self {
+            root: root.as_ref().to_path_buf(),
+            schema: Arc::new(schema),
+            properties,
+            scope,
+            responsive_only,
+            locator,
+            current: None,
+            slash_16s: 0,
+            created: None,
+            provenance: Vec::new(),
+            rows: 0,
+        }
+    }
+
+    /// Writes a /16 as its own row group, /16s have to be written in order
+    pub fn write_slash_16(
+        &mut self,
+        subnet: Subnet,
+        view: &Slash16View,
+    ) -> Result<(), ParquetError> {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "write_slash_16 only takes /16 subnets"
+        );
+
+        let [a, b, _, _] = subnet.octets();
+
+        if self.current.as_ref().map(|(current, _)| *current) != Some(a) {
+            self.close_partition()?;
+
+            let directory = self.root.join(format!("slash_8={a}"));
+            std::fs::create_dir_all(&directory)?;
+
+            let file = File::create(directory.join("results.parquet"))?;
+            let writer =
+                ArrowWriter::try_new(file, self.schema.clone(), Some(self.properties.clone()))?;
+
+            self.current = Some((a, writer));
+        }
+
+        /* Build the columns */
+
+        let mut addresses = UInt32Builder::with_capacity(65536);
+        let mut states = StringDictionaryBuilder::<Int8Type>::new();
+        let mut rtts = UInt32Builder::with_capacity(65536);
+        let mut countries = StringDictionaryBuilder::<Int16Type>::new();
+
+        for (c, slash_24) in view.iter().enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            for (d, result) in slash_24.iter().enumerate() {
+                let address = Ipv4Addr::new(a, b, c as u8, d as u8);
+
+                if !self.scope.contains(address) {
+                    continue;
+                }
+
+                let (state, rtt) = match result {
+                    PingResult::Success(_) => {
+                        ("success", result.rtt().map(|time| time.as_micros() as u32))
+                    }
+                    PingResult::Timeout => ("timeout", None),
+                    PingResult::Error => ("error", None),
+                };
+
+                if self.responsive_only && !matches!(result, PingResult::Success(_)) {
+                    continue;
+                }
+
+                addresses.append_value(u32::from(address));
+                states.append_value(state);
+                rtts.append_option(rtt);
+
+                if let Some(locator) = &mut self.locator {
+                    let country = locator.locate(address)?;
+
+                    countries.append_option(country.as_ref().map(|country| country.code()));
+                }
+            }
+        }
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(addresses.finish()),
+            Arc::new(states.finish()),
+            Arc::new(rtts.finish()),
+        ];
+
+        if self.locator.is_some() {
+            columns.push(Arc::new(countries.finish()));
+        }
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+
+        /* Write it out as a row group */
+
+        let (_, writer) = self.current.as_mut().unwrap();
+
+        writer.write(&batch)?;
+        writer.flush()?;
+
+        /* Keep the run metadata for the footer, 0 is an unknown creation time */
+
+        let header = view.header();
+
+        if header.created != 0 {
+            let (first, last) = self.created.unwrap_or((header.created, header.created));
+            self.created = Some((first.min(header.created), last.max(header.created)));
+        }
+
+        for entry in &header.provenance {
+            if !self.provenance.contains(entry) {
+                self.provenance.push(entry.clone());
+            }
+        }
+
+        self.slash_16s += 1;
+        self.rows += batch.num_rows() as u64;
+
+        Ok(())
+    }
+
+    /// The number of rows written so far
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Closes the last partition, writing its footer
+    pub fn finish(mut self) -> Result<(), ParquetError> {
+        self.close_partition()
+    }
+
+    fn close_partition(&mut self) -> Result<(), ParquetError> {
+        let Some((_, mut writer)) = self.current.take() else {
+            return Ok(());
+        };
+
+        writer.append_key_value_metadata(KeyValue::new(
+            "ping_the_internet.slash_16s".to_string(),
+            self.slash_16s.to_string(),
+        ));
+
+        if let Some((first, last)) = self.created.take() {
+            let created = match first == last {
+                true => format_time(first),
+                false => format!("{}/{}", format_time(first), format_time(last)),
+            };
+
+            writer.append_key_value_metadata(KeyValue::new(
+                "ping_the_internet.created".to_string(),
+                created,
+            ));
+        }
+
+        if !self.provenance.is_empty() {
+            writer.append_key_value_metadata(KeyValue::new(
+                "ping_the_internet.provenance".to_string(),
+                std::mem::take(&mut self.provenance).join("\n"),
+            ));
+        }
+
+        writer.close()?;
+
+        self.slash_16s = 0;
+
+        Ok(())
+    }
+}
+
+fn format_time(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map_or_else(|| timestamp.to_string(), |time| time.to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use ::parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use crate::file::{encode_slash_16, mapped::Decoder, tests::sample_slash_16, SaveOptions};
+
+    use super::*;
+
+    async fn sample_file(seed: u32, created: u64, provenance: &[&str]) -> Vec<u8> {
+        let options = SaveOptions {
+            created: Some(created),
+            provenance: provenance.iter().map(|entry| entry.to_string()).collect(),
+            ..Default::default()
+        };
+
+        encode_slash_16(&sample_slash_16(seed), &options)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn writes_the_run_metadata_of_each_partition() {
+        let root = tempfile::tempdir().unwrap();
+
+        let files = [
+            (
+                "10.1.x.x",
+                sample_file(1, 1_700_000_000, &["zmap", "merged"]).await,
+            ),
+            ("10.2.x.x", sample_file(2, 1_700_086_400, &["merged"]).await),
+            ("11.1.x.x", sample_file(3, 1_700_000_000, &[]).await),
+        ];
+
+        let metadata = vec![("ping_the_internet.source".to_string(), "test".to_string())];
+        let mut exporter =
+            ParquetExporter::new(root.path(), metadata, Subnet::default(), false, None);
+
+        let mut decoder = Decoder::default();
+
+        for (subnet, data) in &files {
+            let view = decoder.decode(data).unwrap();
+            exporter
+                .write_slash_16(subnet.parse().ok().unwrap(), &view)
+                .unwrap();
+        }
+
+        assert_eq!(exporter.rows(), 3 * 65536 - 3 * 51 * 256);
+        exporter.finish().unwrap();
+
+        let key_values = |a: u8| {
+            let file = File::open(root.path().join(format!("slash_8={a}/results.parquet")));
+            let reader = SerializedFileReader::new(file.unwrap()).unwrap();
+
+            let key_values = reader
+                .metadata()
+                .file_metadata()
+                .key_value_metadata()
+                .cloned();
+
+            key_values
+                .unwrap()
+                .into_iter()
+                .map(|key_value| (key_value.key, key_value.value.unwrap_or_default()))
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+
+        let first = key_values(10);
+
+        assert_eq!(first["ping_the_internet.source"], "test");
+        assert_eq!(first["ping_the_internet.slash_16s"], "2");
+        assert_eq!(
+            first["ping_the_internet.created"],
+            "2023-11-14T22:13:20+00:00/2023-11-15T22:13:20+00:00"
+        );
+        assert_eq!(first["ping_the_internet.provenance"], "zmap\nmerged");
+
+        let second = key_values(11);
+
+        assert_eq!(second["ping_the_internet.slash_16s"], "1");
+        assert_eq!(
+            second["ping_the_internet.created"],
+            "2023-11-14T22:13:20+00:00"
+        );
+        assert!(!second.contains_key("ping_the_internet.provenance"));
+    }
+}
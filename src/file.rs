@@ -1,16 +1,361 @@
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use async_compression::{
     tokio::write::{ZlibDecoder, ZlibEncoder},
     Level,
 };
-use nom::{branch::alt, bytes::complete::tag, multi::count, IResult};
-use tokio::{fs::File, io::AsyncWriteExt};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take},
+    multi::{count, length_count, length_data},
+    number::complete::{le_u16, le_u32, le_u64, u8 as parse_u8},
+    IResult,
+};
+use once_cell::sync::Lazy;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    ping::PingResult,
+    stats::{Slash16Result, Slash24Result},
+    subnet::{Subnet, SubnetMask},
+};
+
+use self::archive::Archive;
+
+pub use self::error::StorageError;
+
+pub mod archive;
+mod error;
+pub mod mapped;
+pub mod merge;
+pub mod summary;
+
+/// Marks a /16 file that starts with a header. Files without it are legacy
+/// headerless zlib streams of the tagged encoding
+const MAGIC: &[u8; 4] = b"PTI\x16";
+
+const FORMAT_VERSION: u8 = 3;
+
+/// The largest a decompressed body can be (the tagged encoding with every address a success)
+const MAX_BODY_SIZE: usize = 256 + 256 * 256 * 3;
+
+static DICTIONARIES: Lazy<Mutex<HashMap<u32, Arc<Dictionary>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Describes how the body of a /16 file is laid out and compressed, and where it came from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Header {
+    pub version: u8,
+    pub encoding: Encoding,
+    pub codec: Codec,
+    /// The ID of the zstd dictionary the body was compressed with, or 0 if there is none
+    pub dictionary_id: u32,
+    /// When the results were scanned as a unix timestamp, or 0 if it is unknown
+    pub created: u64,
+    /// Free-form notes on where the results came from, e.g. the runs they were merged from
+    pub provenance: Vec<String>,
+}
+
+impl Header {
+    /// The implied header of files written before headers existed
+    const LEGACY: Header = Header {
+        version: 0,
+        encoding: Encoding::Tagged,
+        codec: Codec::Zlib,
+        dictionary_id: 0,
+        created: 0,
+        provenance: Vec::new(),
+    };
+
+    /// Reads the header of a /16 file, returning the compressed body and the header
+    pub fn read(data: &[u8]) -> Result<(&[u8], Header), StorageError> {
+        if let Some(&version) = data.strip_prefix(MAGIC).and_then(|rest| rest.first()) {
+            if version > FORMAT_VERSION {
+                return Err(StorageError::UnsupportedVersion(version));
+            }
+        }
+
+        Self::parse(data).map_err(|e| StorageError::from_parse_error(e, "header"))
+    }
+
+    /// Parses the header of a /16 file, returning the compressed body as the remaining input
+    pub fn parse(input: &[u8]) -> IResult<&[u8], Header> {
+        let Some(input) = input.strip_prefix(MAGIC) else {
+            return Ok((input, Self::LEGACY));
+        };
+
+        let (input, version) = alt((tag(&[0x01]), tag(&[0x02]), tag(&[FORMAT_VERSION])))(input)?;
+        let (input, encoding) = parse_encoding(input)?;
+
+        let mut header = Header {
+            version: version[0],
+            encoding,
+            ..Self::LEGACY
+        };
+
+        if header.version == 1 {
+            return Ok((input, header));
+        }
+
+        let (input, codec) = parse_codec(input)?;
+        let (input, dictionary_id) = le_u32(input)?;
+
+        header.codec = codec;
+        header.dictionary_id = dictionary_id;
+
+        if header.version == 2 {
+            return Ok((input, header));
+        }
+
+        let (input, created) = le_u64(input)?;
+        let (input, provenance) = length_count(parse_u8, parse_provenance_entry)(input)?;
+
+        header.created = created;
+        header.provenance = provenance;
+
+        Ok((input, header))
+    }
+
+    fn serialize_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(MAGIC);
+        data.push(self.version);
+        data.push(self.encoding.to_byte());
+        data.push(self.codec.to_byte());
+        data.extend_from_slice(&self.dictionary_id.to_le_bytes());
+        data.extend_from_slice(&self.created.to_le_bytes());
+
+        let provenance = &self.provenance[..self.provenance.len().min(u8::MAX as usize)];
+        data.push(provenance.len() as u8);
+
+        for entry in provenance {
+            let entry = &entry.as_bytes()[..entry.len().min(u16::MAX as usize)];
+
+            data.extend_from_slice(&(entry.len() as u16).to_le_bytes());
+            data.extend_from_slice(entry);
+        }
+    }
+}
+
+/// The layout of the uncompressed body of a /16 file
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+    /// A tag byte per /24 followed by a tag byte per address and 2 bytes of
+    /// RTT for every success
+    #[default]
+    Tagged,
+    /// A presence bitmap of the /24s, a 2-bit state bitmap per present /24
+    /// and a packed RTT column holding only the successes
+    Bitmap,
+}
+
+impl Encoding {
+    fn to_byte(self) -> u8 {
+        match self {
+            Encoding::Tagged => 0x00,
+            Encoding::Bitmap => 0x01,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x00 => Some(Encoding::Tagged),
+            0x01 => Some(Encoding::Bitmap),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tagged" => Ok(Encoding::Tagged),
+            "bitmap" => Ok(Encoding::Bitmap),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The compression applied to the body of a /16 file
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Codec {
+    #[default]
+    Zlib,
+    Zstd,
+}
+
+impl Codec {
+    fn to_byte(self) -> u8 {
+        match self {
+            Codec::Zlib => 0x00,
+            Codec::Zstd => 0x01,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x00 => Some(Codec::Zlib),
+            0x01 => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Codec {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zlib" => Ok(Codec::Zlib),
+            "zstd" => Ok(Codec::Zstd),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A zstd dictionary shared by the /16 files of a data directory
+///
+/// /16 files are small and look very alike, so zstd does much better on them
+/// when it has been trained on a sample of them first
+#[derive(Debug)]
+pub struct Dictionary {
+    id: u32,
+    data: Vec<u8>,
+}
+
+impl Dictionary {
+    /// Trains a dictionary from uncompressed bodies, see [`serialize_body`]
+    pub fn train(samples: &[Vec<u8>], max_size: usize) -> Result<Self, StorageError> {
+        Self::from_bytes(zstd::dict::from_samples(samples, max_size).map_err(StorageError::Io)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, StorageError> {
+        let Some(id) = zstd::zstd_safe::get_dict_id_from_dict(&data) else {
+            return Err(StorageError::Corrupt("not a zstd dictionary".to_string()));
+        };
+
+        Ok(Self { id: id.get(), data })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Loads the dictionary with the given ID from `./data/dictionaries`, caching it
+    /// for every later file that references it
+    pub async fn load(id: u32) -> Result<Arc<Self>, StorageError> {
+        Self::load_from(&default_data_root(), id).await
+    }
 
-use crate::{ping::PingResult, stats::{Slash16Result, Slash24Result}, subnet::{Subnet, SubnetMask}};
+    /// Loads the dictionary with the given ID from the `dictionaries` directory of a data root
+    pub async fn load_from(root: &Path, id: u32) -> Result<Arc<Self>, StorageError> {
+        if let Some(dictionary) = DICTIONARIES.lock().unwrap().get(&id) {
+            return Ok(dictionary.clone());
+        }
+
+        let file_path = create_dictionary_path(root, id);
+
+        let data = tokio::fs::read(&file_path)
+            .await
+            .map_err(|e| StorageError::from_open_error(e, &file_path))?;
+
+        Self::check_and_register(id, data)
+    }
+
+    /// Loads the dictionary with the given ID from a data root without an async runtime
+    pub fn load_from_blocking(root: &Path, id: u32) -> Result<Arc<Self>, StorageError> {
+        if let Some(dictionary) = DICTIONARIES.lock().unwrap().get(&id) {
+            return Ok(dictionary.clone());
+        }
+
+        let file_path = create_dictionary_path(root, id);
+
+        let data =
+            std::fs::read(&file_path).map_err(|e| StorageError::from_open_error(e, &file_path))?;
+
+        Self::check_and_register(id, data)
+    }
+
+    fn check_and_register(id: u32, data: Vec<u8>) -> Result<Arc<Self>, StorageError> {
+        let dictionary = Arc::new(Self::from_bytes(data)?);
+
+        if dictionary.id != id {
+            return Err(StorageError::Corrupt(format!(
+                "dictionary file {id:08x} has ID {:08x}",
+                dictionary.id
+            )));
+        }
+
+        dictionary.clone().register();
+
+        Ok(dictionary)
+    }
+
+    /// Makes the dictionary available to every later decode of a file that references it
+    fn register(self: Arc<Self>) {
+        DICTIONARIES.lock().unwrap().insert(self.id, self);
+    }
+
+    /// Saves the dictionary to `./data/dictionaries` so files compressed with it can be read
+    pub async fn save(&self) -> Result<(), StorageError> {
+        self.save_in(&default_data_root()).await
+    }
+
+    /// Saves the dictionary to the `dictionaries` directory of a data root
+    pub async fn save_in(&self, root: &Path) -> Result<(), StorageError> {
+        let file_path = create_dictionary_path(root, self.id);
+
+        tokio::fs::create_dir_all(file_path.parent().unwrap()).await?;
+        tokio::fs::write(file_path, &self.data).await?;
+
+        Ok(())
+    }
+}
+
+/// Controls how [`save_slash_16_with`] lays out and compresses a /16
+#[derive(Debug, Clone)]
+pub struct SaveOptions {
+    pub encoding: Encoding,
+    pub codec: Codec,
+    pub level: Level,
+    /// Only used by [`Codec::Zstd`]
+    pub dictionary: Option<Arc<Dictionary>>,
+    /// When the results were scanned as a unix timestamp, now if None
+    pub created: Option<u64>,
+    /// Recorded in the header as is
+    pub provenance: Vec<String>,
+}
+
+impl SaveOptions {
+    /// The bitmap encoding is already dense, so it does not need the slowest
+    /// zlib setting to stay small
+    pub fn compact() -> Self {
+        Self {
+            encoding: Encoding::Bitmap,
+            level: Level::Default,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Tagged,
+            codec: Codec::Zlib,
+            level: Level::Best,
+            dictionary: None,
+            created: None,
+            provenance: Vec::new(),
+        }
+    }
+}
 
 /// Saves the results of an entire /16 subnet to a file
 ///
@@ -18,82 +363,355 @@ use crate::{ping::PingResult, stats::{Slash16Result, Slash24Result}, subnet::{Su
 /// the ping results for that full subnet. If a /24 subnet is missing it is completely omitted
 ///
 /// This allows for a very good compression ration
-pub async fn save_slash_16(subnet: Subnet, results: Slash16Result) -> Result<(), std::io::Error> {
+pub async fn save_slash_16(subnet: Subnet, results: Slash16Result) -> Result<(), StorageError> {
+    save_slash_16_with(subnet, results, &SaveOptions::default()).await
+}
+
+/// Saves the results of an entire /16 subnet using the given encoding and compression
+pub async fn save_slash_16_with(
+    subnet: Subnet,
+    results: Slash16Result,
+    options: &SaveOptions,
+) -> Result<(), StorageError> {
+    save_slash_16_in(&default_data_root(), subnet, results, options).await
+}
+
+/// Saves the results of an entire /16 subnet under the given data root
+pub async fn save_slash_16_in(
+    root: &Path,
+    subnet: Subnet,
+    results: Slash16Result,
+    options: &SaveOptions,
+) -> Result<(), StorageError> {
     assert_eq!(
         subnet.mask(),
         SubnetMask::Slash16,
         "save_slash_16 only takes /16 subnets"
     );
 
-    /* Serialize and compress data */
+    let data = encode_slash_16(&results, options).await?;
 
-    let mut encoder = ZlibEncoder::with_quality(Vec::new(), Level::Best);
+    /* Ensure parent directory exists */
 
-    for slash_24 in &*results {
-        match slash_24 {
-            None => {
-                encoder.write_all(&[0x00]).await?;
-            }
-            Some(slash_24) => {
-                encoder.write_all(&[0x01]).await?;
-                for ping_result in &**slash_24 {
-                    ping_result.serialize_into(&mut encoder).await?;
-                }
-            }
-        }
-    }
+    let file_path = create_file_path_in(root, subnet);
 
-    encoder.shutdown().await?;
+    tokio::fs::create_dir_all(file_path.parent().unwrap()).await?;
 
-    /* Ensure parent directory exists */
+    /* Write to a temporary file first so an interrupted save never loses the previous file */
 
-    let file_path = create_file_path(subnet);
+    let temp_path = file_path.with_extension("tmp");
 
-    tokio::fs::create_dir_all(file_path.parent().unwrap()).await?;
+    tokio::fs::write(&temp_path, data).await?;
+    tokio::fs::rename(temp_path, file_path).await?;
 
-    /* Write to file */
+    /* Journal the summary of the /16 so stats need not decode it again */
 
-    let mut file = File::create(file_path).await?;
-    file.write_all(&encoder.into_inner()).await?;
+    summary::record_slash_16_summary(root, subnet, &results)?;
 
     Ok(())
 }
 
+/// Serializes and compresses the results of a /16 subnet into the bytes of a /16 file
+pub async fn encode_slash_16(
+    results: &Slash16Result,
+    options: &SaveOptions,
+) -> Result<Vec<u8>, StorageError> {
+    let dictionary = match options.codec {
+        Codec::Zlib => None,
+        Codec::Zstd => options.dictionary.as_deref(),
+    };
+
+    let header = Header {
+        version: FORMAT_VERSION,
+        encoding: options.encoding,
+        codec: options.codec,
+        dictionary_id: dictionary.map(|d| d.id).unwrap_or(0),
+        created: options
+            .created
+            .unwrap_or_else(|| chrono::Utc::now().timestamp() as u64),
+        provenance: options.provenance.clone(),
+    };
+
+    let mut data = Vec::new();
+    header.serialize_into(&mut data);
+
+    let body = serialize_body(results, options.encoding).await?;
+
+    match options.codec {
+        Codec::Zlib => {
+            let mut encoder = ZlibEncoder::with_quality(data, options.level);
+            encoder.write_all(&body).await?;
+            encoder.shutdown().await?;
+
+            Ok(encoder.into_inner())
+        }
+        Codec::Zstd => {
+            let level = zstd_level(options.level);
+
+            let mut compressor = match dictionary {
+                Some(dictionary) => {
+                    zstd::bulk::Compressor::with_dictionary(level, &dictionary.data)?
+                }
+                None => zstd::bulk::Compressor::new(level)?,
+            };
+
+            data.extend_from_slice(&compressor.compress(&body)?);
+
+            Ok(data)
+        }
+    }
+}
+
+/// Serializes the results of a /16 subnet without compressing them
+pub async fn serialize_body(
+    results: &Slash16Result,
+    encoding: Encoding,
+) -> Result<Vec<u8>, StorageError> {
+    let mut body = Vec::new();
+
+    match encoding {
+        Encoding::Tagged => {
+            for slash_24 in &**results {
+                match slash_24 {
+                    None => {
+                        body.write_all(&[0x00]).await?;
+                    }
+                    Some(slash_24) => {
+                        body.write_all(&[0x01]).await?;
+                        for ping_result in &**slash_24 {
+                            ping_result.serialize_into(&mut body).await?;
+                        }
+                    }
+                }
+            }
+        }
+        Encoding::Bitmap => {
+            body = serialize_bitmap(results);
+        }
+    }
+
+    Ok(body)
+}
+
+fn zstd_level(level: Level) -> i32 {
+    match level {
+        Level::Fastest => 1,
+        Level::Best => 19,
+        Level::Precise(level) => level,
+        _ => zstd::DEFAULT_COMPRESSION_LEVEL,
+    }
+}
+
 /// Reads a /16 subnet from a file or directory of /24 subnet files.
 ///
 /// Returns None if the /16 subnet is not found on the disk at all. Otherwise,
 /// returns an array of Options of the /24 subnets
-pub async fn read_slash_16(subnet: Subnet) -> Result<Option<Slash16Result>, std::io::Error> {
-    assert_eq!(
-        subnet.mask(),
-        SubnetMask::Slash16,
-        "read_slash_16 only takes /16 subnets"
-    );
+pub async fn read_slash_16(subnet: Subnet) -> Result<Option<Slash16Result>, StorageError> {
+    DataSource::default().read_slash_16(subnet).await
+}
+
+/// Where the results of a run are read from
+#[derive(Debug, Clone)]
+pub enum DataSource {
+    /// A data root with the `a/b` layout written by [`save_slash_16`]
+    Directory(PathBuf),
+    /// A single file packed by [`archive::pack`]
+    Archive(Arc<Archive>),
+}
+
+impl DataSource {
+    /// Opens a data root directory or an archive file
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| StorageError::from_open_error(e, path))?;
+
+        if metadata.is_dir() {
+            Ok(Self::Directory(path.to_path_buf()))
+        } else {
+            Ok(Self::Archive(Arc::new(Archive::open(path).await?)))
+        }
+    }
+
+    /// Reads the raw, still compressed bytes of a /16 file
+    pub async fn read_raw_slash_16(&self, subnet: Subnet) -> Result<Option<Vec<u8>>, StorageError> {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "read_raw_slash_16 only takes /16 subnets"
+        );
+
+        match self {
+            Self::Directory(root) => {
+                /* Check file exists */
+
+                let file_path = create_file_path_in(root, subnet);
+
+                if !file_path.exists() {
+                    return Ok(None);
+                }
+
+                let data = tokio::fs::read(&file_path).await?;
+
+                /* Make sure a dictionary outside of ./data can be found by the decoder */
+
+                let (_, header) = Header::read(&data)?;
+
+                if header.dictionary_id != 0 {
+                    Dictionary::load_from(root, header.dictionary_id)
+                        .await
+                        .map_err(|e| {
+                            StorageError::from_dictionary_error(e, header.dictionary_id)
+                        })?;
+                }
 
-    /* Check directory exists */
+                Ok(Some(data))
+            }
+            Self::Archive(archive) => archive.read_raw_slash_16(subnet).await,
+        }
+    }
+
+    /// Reads a /16 subnet, returning None if it is not in this data source
+    pub async fn read_slash_16(
+        &self,
+        subnet: Subnet,
+    ) -> Result<Option<Slash16Result>, StorageError> {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "read_slash_16 only takes /16 subnets"
+        );
+
+        let Some(data) = self.read_raw_slash_16(subnet).await? else {
+            return Ok(None);
+        };
+
+        decode_slash_16(&data).await.map(Some)
+    }
+
+    /// Reads a /24 subnet, returning None if it or its /16 is not in this data source
+    ///
+    /// /16s are compressed as a whole, so this still decompresses the entire /16,
+    /// but only the /24 asked for is parsed, found through a [`mapped::Slash16View`]
+    pub async fn read_slash_24(
+        &self,
+        subnet: Subnet,
+    ) -> Result<Option<Slash24Result>, StorageError> {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash24,
+            "read_slash_24 only takes /24 subnets"
+        );
+
+        let Some(data) = self
+            .read_raw_slash_16(subnet.iter_slash_16s().next().unwrap())
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let mut decoder = mapped::Decoder::default();
+        let view = decoder.decode(&data)?;
 
-    let file_path = create_file_path(subnet);
+        Ok(view
+            .slash_24(subnet.octets()[2])
+            .map(|slash_24| slash_24.to_owned()))
+    }
+}
 
-    if !file_path.exists() {
-        return Ok(None);
+impl Default for DataSource {
+    fn default() -> Self {
+        Self::Directory(default_data_root())
     }
+}
 
-    /* Read and decompress data from file */
+/// Decompresses and parses the bytes of a /16 file, detecting its encoding from the header
+pub async fn decode_slash_16(data: &[u8]) -> Result<Slash16Result, StorageError> {
+    let (body, header) = Header::read(data)?;
 
-    let data = tokio::fs::read(&file_path).await?;
+    let data = match header.codec {
+        Codec::Zlib => {
+            let mut decoder = ZlibDecoder::new(Vec::new());
 
-    let mut decoder = ZlibDecoder::new(Vec::new());
-    decoder.write_all(&data).await?;
-    decoder.shutdown().await?;
+            let decompressed = async {
+                decoder.write_all(body).await?;
+                decoder.shutdown().await
+            };
+
+            match decompressed.await {
+                Ok(()) => decoder.into_inner(),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Err(StorageError::Truncated)
+                }
+                Err(e) => return Err(StorageError::Corrupt(format!("invalid zlib stream ({e})"))),
+            }
+        }
+        Codec::Zstd => {
+            let mut decompressor = match header.dictionary_id {
+                0 => zstd::bulk::Decompressor::new()?,
+                id => {
+                    let dictionary = Dictionary::load(id)
+                        .await
+                        .map_err(|e| StorageError::from_dictionary_error(e, id))?;
 
-    let data = decoder.into_inner();
+                    zstd::bulk::Decompressor::with_dictionary(&dictionary.data)?
+                }
+            };
 
-    let Ok((input, slash_16)) = parse_slash_16(&data) else {
-        return Ok(None);
+            decompressor
+                .decompress(body, MAX_BODY_SIZE)
+                .map_err(|e| StorageError::Corrupt(format!("invalid zstd frame ({e})")))?
+        }
     };
-    assert_eq!(input.len(), 0);
 
-    Ok(Some(slash_16))
+    let parser = match header.encoding {
+        Encoding::Tagged => parse_slash_16,
+        Encoding::Bitmap => parse_bitmap_slash_16,
+    };
+
+    let (input, slash_16) =
+        parser(&data).map_err(|e| StorageError::from_parse_error(e, "/16 body"))?;
+
+    if !input.is_empty() {
+        return Err(StorageError::Corrupt(format!(
+            "{} bytes of trailing data",
+            input.len()
+        )));
+    }
+
+    Ok(slash_16)
+}
+
+fn parse_encoding(input: &[u8]) -> IResult<&[u8], Encoding> {
+    let (rest, encoding) = parse_u8(input)?;
+
+    match Encoding::from_byte(encoding) {
+        Some(encoding) => Ok((rest, encoding)),
+        None => Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
+fn parse_codec(input: &[u8]) -> IResult<&[u8], Codec> {
+    let (rest, codec) = parse_u8(input)?;
+
+    match Codec::from_byte(codec) {
+        Some(codec) => Ok((rest, codec)),
+        None => Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
+fn parse_provenance_entry(input: &[u8]) -> IResult<&[u8], String> {
+    let (input, entry) = length_data(le_u16)(input)?;
+
+    Ok((input, String::from_utf8_lossy(entry).into_owned()))
 }
 
 fn parse_slash_16(input: &[u8]) -> IResult<&[u8], Slash16Result> {
@@ -122,11 +740,334 @@ fn parse_slash_24(input: &[u8]) -> IResult<&[u8], Slash24Result> {
     Ok((input, Arc::new(ping_results.try_into().unwrap())))
 }
 
-fn create_file_path(subnet: Subnet) -> PathBuf {
+/// Lays out a /16 as a bitmap of which /24s are present, then a 2-bit state
+/// bitmap for each present /24, then the RTT of every success in address order
+fn serialize_bitmap(results: &Slash16Result) -> Vec<u8> {
+    let mut presence = [0u8; 32];
+    let mut states = Vec::new();
+    let mut rtts = Vec::new();
+
+    for (i, slash_24) in results.iter().enumerate() {
+        let Some(slash_24) = slash_24 else {
+            continue;
+        };
+
+        presence[i / 8] |= 1 << (i % 8);
+
+        let mut slash_24_states = [0u8; 64];
+
+        for (j, ping_result) in slash_24.iter().enumerate() {
+            let state = match ping_result {
+                PingResult::Success(time) => {
+                    rtts.extend_from_slice(&(time.as_millis() as u16).to_le_bytes());
+                    0b00
+                }
+                PingResult::Timeout => 0b01,
+                PingResult::Error => 0b10,
+            };
+
+            slash_24_states[j / 4] |= state << ((j % 4) * 2);
+        }
+
+        states.extend_from_slice(&slash_24_states);
+    }
+
+    let mut data = Vec::with_capacity(presence.len() + states.len() + rtts.len());
+    data.extend_from_slice(&presence);
+    data.extend_from_slice(&states);
+    data.extend_from_slice(&rtts);
+
+    data
+}
+
+fn parse_bitmap_slash_16(input: &[u8]) -> IResult<&[u8], Slash16Result> {
+    let (input, presence) = take(32usize)(input)?;
+
+    let present = (0..256)
+        .filter(|i| presence[i / 8] & (1 << (i % 8)) != 0)
+        .count();
+
+    let (mut input, states) = take(present * 64)(input)?;
+
+    let mut states = states.chunks_exact(64);
+    let mut slash_16 = Vec::with_capacity(256);
+
+    for i in 0..256 {
+        if presence[i / 8] & (1 << (i % 8)) == 0 {
+            slash_16.push(None);
+            continue;
+        }
+
+        let slash_24_states = states.next().unwrap();
+        let mut slash_24 = Vec::with_capacity(256);
+
+        for j in 0..256 {
+            let ping_result = match (slash_24_states[j / 4] >> ((j % 4) * 2)) & 0b11 {
+                0b00 => {
+                    let (rest, time) = le_u16(input)?;
+                    input = rest;
+
+                    PingResult::Success(Duration::from_millis(time as u64))
+                }
+                0b01 => PingResult::Timeout,
+                0b10 => PingResult::Error,
+                _ => {
+                    return Err(nom::Err::Failure(nom::error::Error::new(
+                        input,
+                        nom::error::ErrorKind::Tag,
+                    )))
+                }
+            };
+
+            slash_24.push(ping_result);
+        }
+
+        slash_16.push(Some(Arc::new(slash_24.try_into().unwrap())));
+    }
+
+    Ok((input, Arc::new(slash_16.try_into().unwrap())))
+}
+
+/// The data root used when none is given, `./data`
+pub fn default_data_root() -> PathBuf {
+    Path::new(".").join("data")
+}
+
+/// Returns the path of the file holding the results of a /16 subnet
+pub fn create_file_path(subnet: Subnet) -> PathBuf {
+    create_file_path_in(&default_data_root(), subnet)
+}
+
+/// Returns the path of the file holding the results of a /16 subnet under a data root
+pub fn create_file_path_in(root: &Path, subnet: Subnet) -> PathBuf {
     let octets = subnet.octets();
 
-    Path::new(".")
-        .join("data")
-        .join(octets[0].to_string())
-        .join(octets[1].to_string())
+    root.join(octets[0].to_string()).join(octets[1].to_string())
+}
+
+fn create_dictionary_path(root: &Path, id: u32) -> PathBuf {
+    root.join("dictionaries").join(format!("{id:08x}.dict"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use crate::ping::UNKNOWN_RTT;
+
+    /// A /16 with missing /24s and every state, including the largest and unknown RTTs
+    pub(crate) fn sample_slash_16(seed: u32) -> Slash16Result {
+        let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+
+        let slash_16: Vec<_> = (0..256)
+            .map(|c| {
+                if (c + seed as usize).is_multiple_of(5) {
+                    return None;
+                }
+
+                let slash_24: Vec<_> = (0..256)
+                    .map(|d| {
+                        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+
+                        match (d, (state >> 16) % 7) {
+                            (0, _) => PingResult::Success(UNKNOWN_RTT),
+                            (1, _) => PingResult::Success(Duration::ZERO),
+                            (_, 0 | 1) => PingResult::Success(Duration::from_millis(
+                                (state >> 8) as u64 % 1000,
+                            )),
+                            (_, 2..=5) => PingResult::Timeout,
+                            _ => PingResult::Error,
+                        }
+                    })
+                    .collect();
+
+                Some(Arc::new(slash_24.try_into().unwrap()))
+            })
+            .collect();
+
+        Arc::new(slash_16.try_into().unwrap())
+    }
+
+    /// A dictionary trained on sample /16s, registered so files compressed with it decode
+    pub(crate) async fn sample_dictionary() -> Arc<Dictionary> {
+        let mut samples = Vec::new();
+
+        for seed in 0..32 {
+            let body = serialize_body(&sample_slash_16(seed), Encoding::Bitmap).await;
+            samples.push(body.unwrap());
+        }
+
+        let dictionary = Arc::new(Dictionary::train(&samples, 16 * 1024).unwrap());
+        dictionary.clone().register();
+
+        dictionary
+    }
+
+    fn slash_16(subnet: &str) -> Subnet {
+        subnet.parse().ok().unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trips_every_encoding_and_codec() {
+        let results = sample_slash_16(1);
+
+        for encoding in [Encoding::Tagged, Encoding::Bitmap] {
+            for codec in [Codec::Zlib, Codec::Zstd] {
+                let options = SaveOptions {
+                    encoding,
+                    codec,
+                    created: Some(1_700_000_000),
+                    provenance: vec!["a run".to_string(), "another run".to_string()],
+                    ..Default::default()
+                };
+
+                let data = encode_slash_16(&results, &options).await.unwrap();
+
+                let (_, header) = Header::read(&data).unwrap();
+
+                assert_eq!(
+                    header,
+                    Header {
+                        version: FORMAT_VERSION,
+                        encoding,
+                        codec,
+                        dictionary_id: 0,
+                        created: 1_700_000_000,
+                        provenance: options.provenance.clone(),
+                    }
+                );
+
+                assert_eq!(decode_slash_16(&data).await.unwrap(), results);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trips_with_a_dictionary() {
+        let dictionary = sample_dictionary().await;
+        let results = sample_slash_16(100);
+
+        let options = SaveOptions {
+            codec: Codec::Zstd,
+            dictionary: Some(dictionary.clone()),
+            ..SaveOptions::compact()
+        };
+
+        let data = encode_slash_16(&results, &options).await.unwrap();
+
+        let (_, header) = Header::read(&data).unwrap();
+        assert_eq!(header.dictionary_id, dictionary.id());
+
+        assert_eq!(decode_slash_16(&data).await.unwrap(), results);
+
+        let mut decoder = mapped::Decoder::default();
+        assert_eq!(decoder.decode(&data).unwrap().to_owned(), results);
+    }
+
+    #[tokio::test]
+    async fn reads_older_headers() {
+        let results = sample_slash_16(2);
+        let body = serialize_body(&results, Encoding::Bitmap).await.unwrap();
+        let compressed = zstd::bulk::compress(&body, 3).unwrap();
+
+        /* Version 2 has no creation time or provenance */
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&[0x02, 0x01, 0x01, 0, 0, 0, 0]);
+        data.extend_from_slice(&compressed);
+
+        let (_, header) = Header::read(&data).unwrap();
+
+        assert_eq!(header.version, 2);
+        assert_eq!(header.encoding, Encoding::Bitmap);
+        assert_eq!(header.codec, Codec::Zstd);
+        assert_eq!(decode_slash_16(&data).await.unwrap(), results);
+
+        /* Legacy files are a tagged body in a zlib stream, with no header at all */
+
+        let body = serialize_body(&results, Encoding::Tagged).await.unwrap();
+
+        let mut encoder = ZlibEncoder::new(Vec::new());
+        encoder.write_all(&body).await.unwrap();
+        encoder.shutdown().await.unwrap();
+
+        let data = encoder.into_inner();
+
+        assert_eq!(Header::read(&data).unwrap().1, Header::LEGACY);
+        assert_eq!(decode_slash_16(&data).await.unwrap(), results);
+    }
+
+    #[tokio::test]
+    async fn rejects_damaged_and_newer_files() {
+        let data = encode_slash_16(&sample_slash_16(3), &SaveOptions::default())
+            .await
+            .unwrap();
+
+        let truncated = decode_slash_16(&data[..data.len() / 2]).await.unwrap_err();
+        assert!(truncated.is_damaged(), "{truncated}");
+
+        let mut newer = data.clone();
+        newer[MAGIC.len()] = FORMAT_VERSION + 1;
+
+        assert!(matches!(
+            decode_slash_16(&newer).await,
+            Err(StorageError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reads_a_single_slash_24() {
+        let root = tempfile::tempdir().unwrap();
+        let results = sample_slash_16(4);
+
+        save_slash_16_in(
+            root.path(),
+            slash_16("10.20.x.x"),
+            results.clone(),
+            &SaveOptions::compact(),
+        )
+        .await
+        .unwrap();
+
+        let source = DataSource::open(root.path()).await.unwrap();
+
+        for c in [0u8, 1, 4, 255] {
+            let slash_24 = format!("10.20.{c}.x").parse().ok().unwrap();
+
+            assert_eq!(
+                source.read_slash_24(slash_24).await.unwrap(),
+                results[c as usize]
+            );
+        }
+
+        let missing = "10.21.0.x".parse().ok().unwrap();
+        assert_eq!(source.read_slash_24(missing).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn replaces_files_whole() {
+        let root = tempfile::tempdir().unwrap();
+        let subnet = slash_16("10.20.x.x");
+
+        for seed in [5, 6] {
+            save_slash_16_in(
+                root.path(),
+                subnet,
+                sample_slash_16(seed),
+                &SaveOptions::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let source = DataSource::open(root.path()).await.unwrap();
+
+        assert_eq!(
+            source.read_slash_16(subnet).await.unwrap(),
+            Some(sample_slash_16(6))
+        );
+
+        let files: Vec<_> = std::fs::read_dir(root.path().join("10")).unwrap().collect();
+        assert_eq!(files.len(), 1, "the temporary file is left behind");
+    }
 }
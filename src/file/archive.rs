@@ -0,0 +1,372 @@
+use std::{io::SeekFrom, path::Path, sync::Arc};
+
+use memmap2::Mmap;
+use nom::{
+    bytes::complete::{tag, take},
+    multi::{count, length_count},
+    number::complete::{le_u32, le_u64},
+    IResult,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncSeekExt, AsyncWriteExt, BufWriter},
+};
+
+use crate::subnet::{Subnet, SubnetMask};
+
+use super::{create_file_path_in, Dictionary, Header, StorageError};
+
+const MAGIC: &[u8; 4] = b"PTIA";
+
+const ARCHIVE_VERSION: u8 = 1;
+
+/// An offset and a length for each of the 65536 /16s
+const INDEX_SIZE: usize = 65536 * (8 + 4);
+
+/// The magic, the version, the offset of the dictionaries and the index
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 8 + INDEX_SIZE;
+
+/// A whole run packed into a single file
+///
+/// The header holds an index with the location of every /16 blob, so any /16
+/// can be found with a single lookup. The blobs are the /16 files exactly as
+/// they were on disk, followed by every dictionary they were compressed with
+///
+/// The file is memory-mapped rather than read through a shared handle, so any
+/// number of workers can read /16s at once
+#[derive(Debug)]
+pub struct Archive {
+    map: Mmap,
+    index: Box<[IndexEntry]>,
+    dictionaries: Vec<Arc<Dictionary>>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct IndexEntry {
+    pub(super) offset: u64,
+    pub(super) length: u32,
+}
+
+impl Archive {
+    /// Opens an archive, loading its index and registering its dictionaries
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+
+        let file = std::fs::File::open(path).map_err(|e| StorageError::from_open_error(e, path))?;
+
+        // SAFETY: `pack` writes archives to a temporary file and renames it into place,
+        // so a mapped archive is never truncated or written to
+        let map = unsafe { Mmap::map(&file)? };
+
+        let (dictionaries_offset, index) = read_header(&map)?;
+
+        /* Load the dictionaries */
+
+        let dictionaries = read_dictionaries(
+            map.get(dictionaries_offset as usize..)
+                .ok_or(StorageError::Truncated)?,
+        )?;
+
+        let mut loaded = Vec::with_capacity(dictionaries.len());
+
+        for dictionary in dictionaries {
+            let dictionary = Arc::new(Dictionary::from_bytes(dictionary.to_vec())?);
+            dictionary.clone().register();
+            loaded.push(dictionary);
+        }
+
+        Ok(Self {
+            map,
+            index: index.into_boxed_slice(),
+            dictionaries: loaded,
+        })
+    }
+
+    /// Returns whether the archive holds the given /16
+    pub fn contains(&self, subnet: Subnet) -> bool {
+        self.index[index_of(subnet)].length != 0
+    }
+
+    /// Reads the raw, still compressed bytes of a /16 file
+    pub async fn read_raw_slash_16(&self, subnet: Subnet) -> Result<Option<Vec<u8>>, StorageError> {
+        let entry = self.index[index_of(subnet)];
+
+        if entry.length == 0 {
+            return Ok(None);
+        }
+
+        let data = self
+            .map
+            .get(entry.offset as usize..entry.offset as usize + entry.length as usize)
+            .ok_or(StorageError::Truncated)?;
+
+        Ok(Some(data.to_vec()))
+    }
+}
+
+/// Packs every /16 file under a data root, and the dictionaries they use, into an archive
+///
+/// Returns the number of /16s packed
+pub async fn pack(root: &Path, path: impl AsRef<Path>) -> Result<usize, StorageError> {
+    /* Written next to its final path and renamed over it, so a mapped archive never changes */
+
+    let path = path.as_ref();
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    let mut writer = BufWriter::new(File::create(&temp_path).await?);
+
+    /* Reserve space for the header, it is written once the blobs are in place */
+
+    writer.write_all(&vec![0; HEADER_SIZE]).await?;
+
+    let mut index = vec![IndexEntry::default(); 65536];
+    let mut offset = HEADER_SIZE as u64;
+    let mut dictionary_ids = Vec::new();
+
+    for slash_16 in Subnet::default().iter_slash_16s() {
+        let file_path = create_file_path_in(root, slash_16);
+
+        if !file_path.exists() {
+            continue;
+        }
+
+        let data = tokio::fs::read(file_path).await?;
+
+        let (_, header) = Header::read(&data)?;
+
+        if header.dictionary_id != 0 && !dictionary_ids.contains(&header.dictionary_id) {
+            dictionary_ids.push(header.dictionary_id);
+        }
+
+        writer.write_all(&data).await?;
+
+        index[index_of(slash_16)] = IndexEntry {
+            offset,
+            length: data.len() as u32,
+        };
+        offset += data.len() as u64;
+    }
+
+    /* Append the dictionaries */
+
+    let dictionaries_offset = offset;
+
+    writer
+        .write_all(&(dictionary_ids.len() as u32).to_le_bytes())
+        .await?;
+
+    for id in dictionary_ids {
+        let dictionary = Dictionary::load_from(root, id)
+            .await
+            .map_err(|e| StorageError::from_dictionary_error(e, id))?;
+
+        writer
+            .write_all(&(dictionary.data.len() as u32).to_le_bytes())
+            .await?;
+        writer.write_all(&dictionary.data).await?;
+    }
+
+    /* Fill in the header */
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.push(ARCHIVE_VERSION);
+    header.extend_from_slice(&dictionaries_offset.to_le_bytes());
+
+    for entry in &index {
+        header.extend_from_slice(&entry.offset.to_le_bytes());
+        header.extend_from_slice(&entry.length.to_le_bytes());
+    }
+
+    writer.seek(SeekFrom::Start(0)).await?;
+    writer.write_all(&header).await?;
+    writer.flush().await?;
+
+    drop(writer);
+    tokio::fs::rename(temp_path, path).await?;
+
+    Ok(index.iter().filter(|entry| entry.length != 0).count())
+}
+
+/// Writes every /16 in an archive back out to the directory layout under a data root
+///
+/// Returns the number of /16s unpacked
+pub async fn unpack(path: impl AsRef<Path>, root: &Path) -> Result<usize, StorageError> {
+    let archive = Archive::open(path).await?;
+
+    let mut unpacked = 0;
+
+    for slash_16 in Subnet::default().iter_slash_16s() {
+        let Some(data) = archive.read_raw_slash_16(slash_16).await? else {
+            continue;
+        };
+
+        let file_path = create_file_path_in(root, slash_16);
+
+        let temp_path = file_path.with_extension("tmp");
+
+        tokio::fs::create_dir_all(file_path.parent().unwrap()).await?;
+        tokio::fs::write(&temp_path, data).await?;
+        tokio::fs::rename(temp_path, file_path).await?;
+
+        unpacked += 1;
+    }
+
+    for dictionary in &archive.dictionaries {
+        dictionary.save_in(root).await?;
+    }
+
+    Ok(unpacked)
+}
+
+pub(super) fn index_of(subnet: Subnet) -> usize {
+    assert_eq!(
+        subnet.mask(),
+        SubnetMask::Slash16,
+        "archives are indexed by /16 subnets"
+    );
+
+    let octets = subnet.octets();
+
+    octets[0] as usize * 256 + octets[1] as usize
+}
+
+/// Parses the header of an archive into the offset of its dictionaries and its index
+pub(super) fn read_header(data: &[u8]) -> Result<(u64, Vec<IndexEntry>), StorageError> {
+    if let Some(&version) = data.strip_prefix(MAGIC).and_then(|rest| rest.first()) {
+        if version > ARCHIVE_VERSION {
+            return Err(StorageError::UnsupportedVersion(version));
+        }
+    }
+
+    parse_header(data)
+        .map(|(_, header)| header)
+        .map_err(|e| StorageError::from_parse_error(e, "archive header"))
+}
+
+/// Parses the dictionaries section at the end of an archive
+pub(super) fn read_dictionaries(data: &[u8]) -> Result<Vec<&[u8]>, StorageError> {
+    parse_dictionaries(data)
+        .map(|(_, dictionaries)| dictionaries)
+        .map_err(|e| StorageError::from_parse_error(e, "archive dictionaries"))
+}
+
+fn parse_header(input: &[u8]) -> IResult<&[u8], (u64, Vec<IndexEntry>)> {
+    let (input, _) = tag(MAGIC)(input)?;
+    let (input, _) = tag(&[ARCHIVE_VERSION])(input)?;
+    let (input, dictionaries_offset) = le_u64(input)?;
+    let (input, index) = count(parse_index_entry, 65536)(input)?;
+
+    Ok((input, (dictionaries_offset, index)))
+}
+
+fn parse_index_entry(input: &[u8]) -> IResult<&[u8], IndexEntry> {
+    let (input, offset) = le_u64(input)?;
+    let (input, length) = le_u32(input)?;
+
+    Ok((input, IndexEntry { offset, length }))
+}
+
+fn parse_dictionaries(input: &[u8]) -> IResult<&[u8], Vec<&[u8]>> {
+    length_count(le_u32, parse_dictionary)(input)
+}
+
+fn parse_dictionary(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (input, length) = le_u32(input)?;
+
+    take(length)(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::file::{
+        create_dictionary_path, save_slash_16_in,
+        tests::{sample_dictionary, sample_slash_16},
+        Codec, DataSource, SaveOptions,
+    };
+
+    #[tokio::test]
+    async fn packs_and_reads_back_every_slash_16() {
+        let root = tempfile::tempdir().unwrap();
+        let archive_path = root.path().join("run.ptia");
+        let data_root = root.path().join("data");
+
+        let dictionary = sample_dictionary().await;
+        dictionary.save_in(&data_root).await.unwrap();
+
+        let zstd = SaveOptions {
+            codec: Codec::Zstd,
+            dictionary: Some(dictionary.clone()),
+            ..Default::default()
+        };
+
+        let slash_16s = [
+            ("1.2.x.x", sample_slash_16(1), SaveOptions::default()),
+            ("1.3.x.x", sample_slash_16(2), SaveOptions::compact()),
+            ("200.0.x.x", sample_slash_16(3), zstd),
+        ];
+
+        for (subnet, results, options) in &slash_16s {
+            let subnet = subnet.parse().ok().unwrap();
+
+            save_slash_16_in(&data_root, subnet, results.clone(), options)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(pack(&data_root, &archive_path).await.unwrap(), 3);
+
+        let archive = Archive::open(&archive_path).await.unwrap();
+        let source = DataSource::open(&archive_path).await.unwrap();
+
+        for (subnet, results, _) in &slash_16s {
+            let subnet = subnet.parse().ok().unwrap();
+
+            let file = std::fs::read(create_file_path_in(&data_root, subnet)).unwrap();
+
+            assert!(archive.contains(subnet));
+            assert_eq!(archive.read_raw_slash_16(subnet).await.unwrap(), Some(file));
+            assert_eq!(
+                source.read_slash_16(subnet).await.unwrap().as_ref(),
+                Some(results)
+            );
+        }
+
+        let missing = "1.4.x.x".parse().ok().unwrap();
+
+        assert!(!archive.contains(missing));
+        assert_eq!(archive.read_raw_slash_16(missing).await.unwrap(), None);
+
+        /* Unpacking gives back the same files */
+
+        let unpacked_root = root.path().join("unpacked");
+
+        assert_eq!(unpack(&archive_path, &unpacked_root).await.unwrap(), 3);
+
+        for (subnet, _, _) in &slash_16s {
+            let subnet = subnet.parse().ok().unwrap();
+
+            assert_eq!(
+                std::fs::read(create_file_path_in(&unpacked_root, subnet)).unwrap(),
+                std::fs::read(create_file_path_in(&data_root, subnet)).unwrap()
+            );
+        }
+
+        assert!(create_dictionary_path(&unpacked_root, dictionary.id()).exists());
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_archives() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("run.ptia");
+
+        std::fs::write(&path, &MAGIC[..]).unwrap();
+
+        let error = Archive::open(&path).await.unwrap_err();
+        assert!(matches!(error, StorageError::Truncated), "{error}");
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    fmt::Display,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can go wrong reading or writing stored results
+#[derive(Debug)]
+pub enum StorageError {
+    /// A data root, archive or dictionary that has to exist does not
+    NotFound(PathBuf),
+    /// The data is all there but it does not parse
+    Corrupt(String),
+    /// The zstd dictionary some data was compressed with is missing or unusable,
+    /// which says nothing about the data itself
+    Dictionary(u32, String),
+    /// The data was written by a newer version of the format
+    UnsupportedVersion(u8),
+    /// The data ends before it should
+    Truncated,
+    Io(std::io::Error),
+}
+
+impl StorageError {
+    /// Returns whether the stored data is damaged, so rewriting it would lose nothing
+    pub fn is_damaged(&self) -> bool {
+        matches!(self, Self::Corrupt(_) | Self::Truncated)
+    }
+
+    /// Builds the error for failing to open something that has to exist
+    pub(crate) fn from_open_error(e: std::io::Error, path: &Path) -> Self {
+        match e.kind() {
+            ErrorKind::NotFound => Self::NotFound(path.to_path_buf()),
+            _ => e.into(),
+        }
+    }
+
+    /// Builds the error for a dictionary that could not be loaded, so that a
+    /// damaged dictionary file does not pass for damaged data
+    pub(crate) fn from_dictionary_error(e: StorageError, id: u32) -> Self {
+        match e {
+            Self::Dictionary(..) => e,
+            e => Self::Dictionary(id, e.to_string()),
+        }
+    }
+
+    /// Builds the error for a failed parse, telling truncated data apart from corrupt data
+    pub(crate) fn from_parse_error(error: nom::Err<nom::error::Error<&[u8]>>, what: &str) -> Self {
+        match error {
+            nom::Err::Incomplete(_) => Self::Truncated,
+            nom::Err::Error(e) | nom::Err::Failure(e) if e.input.is_empty() => Self::Truncated,
+            nom::Err::Error(e) | nom::Err::Failure(e) => {
+                Self::Corrupt(format!("invalid {what} ({:?})", e.code))
+            }
+        }
+    }
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(path) => write!(f, "{} not found", path.display()),
+            Self::Corrupt(reason) => write!(f, "corrupt data: {reason}"),
+            Self::Dictionary(id, reason) => write!(f, "zstd dictionary {id:08x}: {reason}"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            Self::Truncated => write!(f, "truncated data"),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Only a read running out of data says something about the data, parsers
+/// build [`StorageError::Corrupt`] themselves when they reject it
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::UnexpectedEof => Self::Truncated,
+            _ => Self::Io(e),
+        }
+    }
+}
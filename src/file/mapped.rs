@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use flate2::bufread::ZlibDecoder;
+use memmap2::Mmap;
+
+use crate::{
+    ping::PingResult,
+    stats::{Slash16Result, Slash24Result},
+    subnet::{Subnet, SubnetMask},
+};
+
+use super::{
+    archive, create_file_path_in, Codec, Dictionary, Encoding, Header, StorageError, DICTIONARIES,
+    MAX_BODY_SIZE,
+};
+
+/// A synchronous reader of /16 files for bulk analysis
+///
+/// Files are memory-mapped and decompressed into a buffer owned by the reader,
+/// which is reused for every /16, and parsed lazily through [`Slash16View`]s
+/// instead of being built into [`Slash16Result`]s. Readers are cheap to clone
+/// so each rayon thread can have its own, e.g. through `map_init`
+#[derive(Clone)]
+pub struct SyncReader {
+    source: SyncSource,
+    decoder: Decoder,
+}
+
+#[derive(Clone)]
+enum SyncSource {
+    Directory(PathBuf),
+    Archive(Arc<MappedArchive>),
+}
+
+struct MappedArchive {
+    map: Mmap,
+    index: Vec<archive::IndexEntry>,
+}
+
+impl SyncReader {
+    /// Opens a data root directory or an archive file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+
+        let metadata =
+            std::fs::metadata(path).map_err(|e| StorageError::from_open_error(e, path))?;
+
+        let source = if metadata.is_dir() {
+            SyncSource::Directory(path.to_path_buf())
+        } else {
+            SyncSource::Archive(Arc::new(MappedArchive::open(path)?))
+        };
+
+        Ok(Self {
+            source,
+            decoder: Decoder::default(),
+        })
+    }
+
+    /// Reads a /16 subnet, returning None if it is not in this data source
+    ///
+    /// The view borrows the reader's buffer, so it has to be dropped before the next read
+    pub fn read_slash_16(
+        &mut self,
+        subnet: Subnet,
+    ) -> Result<Option<Slash16View<'_>>, StorageError> {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "read_slash_16 only takes /16 subnets"
+        );
+
+        match &self.source {
+            SyncSource::Directory(root) => {
+                let file_path = create_file_path_in(root, subnet);
+
+                let file = match File::open(file_path) {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+
+                // SAFETY: every writer of /16 files (`save_slash_16_in` and `archive::unpack`)
+                // writes a temporary file and renames it over the old one, so a mapped file
+                // is never truncated or written to
+                let map = unsafe { Mmap::map(&file)? };
+
+                let (_, header) = Header::read(&map)?;
+
+                if header.dictionary_id != 0 {
+                    Dictionary::load_from_blocking(root, header.dictionary_id).map_err(|e| {
+                        StorageError::from_dictionary_error(e, header.dictionary_id)
+                    })?;
+                }
+
+                self.decoder.decode(&map).map(Some)
+            }
+            SyncSource::Archive(archive) => {
+                let Some(data) = archive.slash_16(subnet)? else {
+                    return Ok(None);
+                };
+
+                self.decoder.decode(data).map(Some)
+            }
+        }
+    }
+}
+
+impl MappedArchive {
+    fn open(path: &Path) -> Result<Self, StorageError> {
+        let file = File::open(path).map_err(|e| StorageError::from_open_error(e, path))?;
+
+        // SAFETY: `archive::pack` writes archives to a temporary file and renames it into
+        // place, so a mapped archive is never truncated or written to
+        let map = unsafe { Mmap::map(&file)? };
+
+        let (dictionaries_offset, index) = archive::read_header(&map)?;
+
+        let dictionaries = archive::read_dictionaries(
+            map.get(dictionaries_offset as usize..)
+                .ok_or(StorageError::Truncated)?,
+        )?;
+
+        for dictionary in dictionaries {
+            Arc::new(Dictionary::from_bytes(dictionary.to_vec())?).register();
+        }
+
+        Ok(Self { map, index })
+    }
+
+    fn slash_16(&self, subnet: Subnet) -> Result<Option<&[u8]>, StorageError> {
+        let entry = self.index[archive::index_of(subnet)];
+
+        if entry.length == 0 {
+            return Ok(None);
+        }
+
+        self.map
+            .get(entry.offset as usize..entry.offset as usize + entry.length as usize)
+            .map(Some)
+            .ok_or(StorageError::Truncated)
+    }
+}
+
+/// Decompresses /16 files into a reusable buffer
+#[derive(Default)]
+pub struct Decoder {
+    body: Vec<u8>,
+    decompressors: HashMap<u32, zstd::bulk::Decompressor<'static>>,
+}
+
+impl Clone for Decoder {
+    /// Buffers and decompressors are per thread, so clones start out empty
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Decoder {
+    /// Decompresses and indexes the bytes of a /16 file, detecting its encoding from the header
+    pub fn decode(&mut self, data: &[u8]) -> Result<Slash16View<'_>, StorageError> {
+        let (body, header) = Header::read(data)?;
+
+        self.body.clear();
+
+        match header.codec {
+            Codec::Zlib => {
+                if let Err(e) = ZlibDecoder::new(body).read_to_end(&mut self.body) {
+                    return Err(match e.kind() {
+                        ErrorKind::UnexpectedEof => StorageError::Truncated,
+                        _ => StorageError::Corrupt(format!("invalid zlib stream ({e})")),
+                    });
+                }
+            }
+            Codec::Zstd => {
+                let decompressor = match self.decompressors.entry(header.dictionary_id) {
+                    std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                    std::collections::hash_map::Entry::Vacant(entry) => {
+                        let decompressor = match header.dictionary_id {
+                            0 => zstd::bulk::Decompressor::new()?,
+                            id => {
+                                let dictionary = DICTIONARIES.lock().unwrap().get(&id).cloned();
+                                let dictionary = dictionary.ok_or_else(|| {
+                                    StorageError::Dictionary(id, "not loaded".to_string())
+                                })?;
+
+                                zstd::bulk::Decompressor::with_dictionary(&dictionary.data)?
+                            }
+                        };
+
+                        entry.insert(decompressor)
+                    }
+                };
+
+                self.body.reserve(MAX_BODY_SIZE);
+                decompressor
+                    .decompress_to_buffer(body, &mut self.body)
+                    .map_err(|e| StorageError::Corrupt(format!("invalid zstd frame ({e})")))?;
+            }
+        }
+
+        let slash_24s = match header.encoding {
+            Encoding::Tagged => index_tagged(&self.body),
+            Encoding::Bitmap => index_bitmap(&self.body),
+        };
+
+        let slash_24s =
+            slash_24s.ok_or_else(|| StorageError::Corrupt("invalid /16 body".to_string()))?;
+
+        Ok(Slash16View { header, slash_24s })
+    }
+}
+
+/// The results of a /16 subnet, parsed lazily from a decompressed body
+pub struct Slash16View<'a> {
+    header: Header,
+    slash_24s: [Option<Slash24View<'a>>; 256],
+}
+
+impl<'a> Slash16View<'a> {
+    /// The header of the file, with when and how the /16 was scanned
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the given /24 of this /16, None if it was omitted
+    pub fn slash_24(&self, index: u8) -> Option<Slash24View<'a>> {
+        self.slash_24s[index as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<Slash24View<'a>>> + '_ {
+        self.slash_24s.iter().copied()
+    }
+
+    /// Builds the owned results, as returned by [`super::read_slash_16`]
+    pub fn to_owned(&self) -> Slash16Result {
+        let slash_16: Vec<_> = self
+            .iter()
+            .map(|slash_24| slash_24.map(|slash_24| slash_24.to_owned()))
+            .collect();
+
+        Arc::new(slash_16.try_into().unwrap())
+    }
+}
+
+/// The results of a /24 subnet, borrowed from a decompressed body
+#[derive(Debug, Clone, Copy)]
+pub enum Slash24View<'a> {
+    /// A tag byte per address, followed by 2 bytes of RTT for successes
+    Tagged(&'a [u8]),
+    /// A 2-bit state per address, and the RTTs of only the successes
+    Bitmap { states: &'a [u8], rtts: &'a [u8] },
+}
+
+impl<'a> Slash24View<'a> {
+    pub fn iter(self) -> Slash24Iter<'a> {
+        Slash24Iter {
+            view: self,
+            index: 0,
+            position: 0,
+        }
+    }
+
+    pub fn to_owned(self) -> Slash24Result {
+        let slash_24: Vec<_> = self.iter().collect();
+
+        Arc::new(slash_24.try_into().unwrap())
+    }
+}
+
+impl<'a> IntoIterator for Slash24View<'a> {
+    type Item = PingResult;
+    type IntoIter = Slash24Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterates through the 256 results of a [`Slash24View`]
+pub struct Slash24Iter<'a> {
+    view: Slash24View<'a>,
+    index: usize,
+    /// Byte offset into the tagged data or the RTT column
+    position: usize,
+}
+
+impl Iterator for Slash24Iter<'_> {
+    type Item = PingResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == 256 {
+            return None;
+        }
+
+        /* Views are validated when they are indexed, so the data is known to be well formed */
+
+        let state = match self.view {
+            Slash24View::Tagged(data) => {
+                let state = data[self.position];
+                self.position += 1;
+                state
+            }
+            Slash24View::Bitmap { states, .. } => {
+                (states[self.index / 4] >> ((self.index % 4) * 2)) & 0b11
+            }
+        };
+
+        self.index += 1;
+
+        Some(match state {
+            0 => {
+                let rtts = match self.view {
+                    Slash24View::Tagged(data) => data,
+                    Slash24View::Bitmap { rtts, .. } => rtts,
+                };
+
+                let time = u16::from_le_bytes([rtts[self.position], rtts[self.position + 1]]);
+                self.position += 2;
+
+                PingResult::Success(Duration::from_millis(time as u64))
+            }
+            1 => PingResult::Timeout,
+            _ => PingResult::Error,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (256 - self.index, Some(256 - self.index))
+    }
+}
+
+impl ExactSizeIterator for Slash24Iter<'_> {}
+
+/// Finds the data of every /24 in a tagged body, making sure it is well formed
+fn index_tagged(body: &[u8]) -> Option<[Option<Slash24View<'_>>; 256]> {
+    let mut slash_24s = [None; 256];
+    let mut position = 0;
+
+    for slash_24 in &mut slash_24s {
+        match body.get(position)? {
+            0x00 => position += 1,
+            0x01 => {
+                position += 1;
+                let start = position;
+
+                for _ in 0..256 {
+                    match body.get(position)? {
+                        0x00 => position += 3,
+                        0x01 | 0x02 => position += 1,
+                        _ => return None,
+                    }
+                }
+
+                *slash_24 = Some(Slash24View::Tagged(body.get(start..position)?));
+            }
+            _ => return None,
+        }
+    }
+
+    (position == body.len()).then_some(slash_24s)
+}
+
+/// Finds the states and RTTs of every /24 in a bitmap body, making sure it is well formed
+fn index_bitmap(body: &[u8]) -> Option<[Option<Slash24View<'_>>; 256]> {
+    let presence = body.get(..32)?;
+
+    let present = presence
+        .iter()
+        .map(|b| b.count_ones() as usize)
+        .sum::<usize>();
+
+    let mut states = body.get(32..32 + present * 64)?.chunks_exact(64);
+    let rtts = &body[32 + present * 64..];
+
+    let mut slash_24s = [None; 256];
+    let mut position = 0;
+
+    for (i, slash_24) in slash_24s.iter_mut().enumerate() {
+        if presence[i / 8] & (1 << (i % 8)) == 0 {
+            continue;
+        }
+
+        let slash_24_states = states.next()?;
+
+        /* Count the 0b00 (success) pairs and reject the unused 0b11 state */
+
+        let mut successes = 0;
+
+        for byte in slash_24_states {
+            if byte & (byte >> 1) & 0x55 != 0 {
+                return None;
+            }
+
+            let inverted = !byte;
+            successes += (inverted & (inverted >> 1) & 0x55).count_ones() as usize;
+        }
+
+        let slash_24_rtts = rtts.get(position..position + successes * 2)?;
+        position += successes * 2;
+
+        *slash_24 = Some(Slash24View::Bitmap {
+            states: slash_24_states,
+            rtts: slash_24_rtts,
+        });
+    }
+
+    (position == rtts.len()).then_some(slash_24s)
+}
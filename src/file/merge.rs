@@ -0,0 +1,454 @@
+use std::{path::Path, sync::Arc, time::UNIX_EPOCH};
+
+use crate::{
+    ping::PingResult,
+    stats::{Slash16Result, Slash24Result},
+    subnet::{Subnet, SubnetMask},
+};
+
+use super::{
+    create_file_path_in, decode_slash_16, save_slash_16_in, DataSource, Header, SaveOptions,
+    StorageError,
+};
+
+/// How to pick a result when runs disagree about an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// A success from any run wins, then a timeout, then an error
+    PreferSuccess,
+    /// The result of the most recent run wins
+    PreferNewest,
+    /// The most common state wins, ties are broken like [`MergePolicy::PreferSuccess`]
+    Majority,
+}
+
+impl MergePolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PreferSuccess => "prefer-success",
+            Self::PreferNewest => "prefer-newest",
+            Self::Majority => "majority",
+        }
+    }
+}
+
+impl std::str::FromStr for MergePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefer-success" => Ok(Self::PreferSuccess),
+            "prefer-newest" => Ok(Self::PreferNewest),
+            "majority" => Ok(Self::Majority),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A /16 as found in one of the runs being merged
+#[derive(Debug, Clone)]
+pub struct MergeSource {
+    pub name: String,
+    pub results: Slash16Result,
+    /// When the /16 was scanned as a unix timestamp, or 0 if it is unknown
+    pub created: u64,
+}
+
+/// The outcome of merging a single /16
+#[derive(Debug, Clone)]
+pub struct MergedSlash16 {
+    pub results: Slash16Result,
+    /// The number of addresses the runs did not agree on
+    pub conflicts: u32,
+}
+
+/// Merges the results of a /16 from several runs, a /24 is kept if any run has it
+///
+/// Whenever the newest result is needed, sources are compared by their creation
+/// time, with later sources winning ties
+pub fn merge_slash_16(sources: &[MergeSource], policy: MergePolicy) -> MergedSlash16 {
+    /* Oldest first, so the last candidate is always the newest */
+
+    let mut sources: Vec<_> = sources.iter().collect();
+    sources.sort_by_key(|source| source.created);
+
+    let mut conflicts = 0;
+
+    let slash_16: Vec<_> = (0..256)
+        .map(|c| {
+            let slash_24s: Vec<&Slash24Result> = sources
+                .iter()
+                .filter_map(|source| source.results[c].as_ref())
+                .collect();
+
+            if slash_24s.is_empty() {
+                return None;
+            }
+
+            let slash_24: Vec<_> = (0..256)
+                .map(|d| {
+                    let candidates: Vec<&PingResult> =
+                        slash_24s.iter().map(|slash_24| &slash_24[d]).collect();
+
+                    let disagree = candidates
+                        .iter()
+                        .any(|result| rank(result) != rank(candidates[0]));
+
+                    if disagree {
+                        conflicts += 1;
+                    }
+
+                    resolve(&candidates, policy).clone()
+                })
+                .collect();
+
+            Some(Arc::new(slash_24.try_into().unwrap()))
+        })
+        .collect();
+
+    MergedSlash16 {
+        results: Arc::new(slash_16.try_into().unwrap()),
+        conflicts,
+    }
+}
+
+/// Picks a result out of the candidates of an address, ordered oldest to newest
+fn resolve<'a>(candidates: &[&'a PingResult], policy: MergePolicy) -> &'a PingResult {
+    let newest_of_rank = |wanted: u8| {
+        candidates
+            .iter()
+            .rev()
+            .find(|result| rank(result) == wanted)
+            .copied()
+    };
+
+    match policy {
+        MergePolicy::PreferNewest => candidates[candidates.len() - 1],
+        MergePolicy::PreferSuccess => (0..3).rev().find_map(newest_of_rank).unwrap(),
+        MergePolicy::Majority => {
+            let mut counts = [0; 3];
+
+            for result in candidates {
+                counts[rank(result) as usize] += 1;
+            }
+
+            let max = counts.iter().max().unwrap();
+
+            (0..3)
+                .rev()
+                .filter(|rank| counts[*rank as usize] == *max)
+                .find_map(newest_of_rank)
+                .unwrap()
+        }
+    }
+}
+
+/// Orders states by how much they say about an address, a success says the most
+fn rank(result: &PingResult) -> u8 {
+    match result {
+        PingResult::Success(_) => 2,
+        PingResult::Timeout => 1,
+        PingResult::Error => 0,
+    }
+}
+
+/// A run left out of a merge, with the reason its /16 could not be read
+pub type SkippedSource = (String, StorageError);
+
+/// Reads a /16 from every run that has it
+///
+/// Damaged /16s are left out of the merge rather than failing it, and returned
+/// alongside the sources so the caller can report them
+pub async fn read_merge_sources(
+    runs: &[(String, DataSource)],
+    subnet: Subnet,
+) -> Result<(Vec<MergeSource>, Vec<SkippedSource>), StorageError> {
+    assert_eq!(
+        subnet.mask(),
+        SubnetMask::Slash16,
+        "read_merge_sources only takes /16 subnets"
+    );
+
+    let mut sources = Vec::new();
+    let mut skipped = Vec::new();
+
+    for (name, run) in runs {
+        let Some(data) = run.read_raw_slash_16(subnet).await? else {
+            continue;
+        };
+
+        let decoded = async {
+            let (_, header) = Header::read(&data)?;
+            Ok::<_, StorageError>((header, decode_slash_16(&data).await?))
+        };
+
+        let (header, results) = match decoded.await {
+            Ok(decoded) => decoded,
+            Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                skipped.push((name.clone(), e));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        /* Files from before headers recorded it are dated by their modification time */
+
+        let created = match (header.created, run) {
+            (0, DataSource::Directory(root)) => {
+                tokio::fs::metadata(create_file_path_in(root, subnet))
+                    .await?
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .map(|time| time.as_secs())
+                    .unwrap_or(0)
+            }
+            (created, _) => created,
+        };
+
+        sources.push(MergeSource {
+            name: name.clone(),
+            results,
+            created,
+        });
+    }
+
+    Ok((sources, skipped))
+}
+
+/// Merges a /16 from every run that has it and saves it under the output root
+///
+/// The policy and the runs that contributed are recorded in the header's provenance.
+/// The merge is None if none of the runs have a readable /16, and the runs that were
+/// left out are returned either way
+pub async fn merge_runs_slash_16(
+    runs: &[(String, DataSource)],
+    output: &Path,
+    subnet: Subnet,
+    policy: MergePolicy,
+    options: &SaveOptions,
+) -> Result<(Option<MergedSlash16>, Vec<SkippedSource>), StorageError> {
+    let (sources, skipped) = read_merge_sources(runs, subnet).await?;
+
+    if sources.is_empty() {
+        return Ok((None, skipped));
+    }
+
+    let merged = merge_slash_16(&sources, policy);
+
+    let mut provenance = vec![format!("merged with {}", policy.name())];
+
+    provenance.extend(
+        sources
+            .iter()
+            .map(|source| format!("{} (created {})", source.name, source.created)),
+    );
+
+    let options = SaveOptions {
+        created: sources.iter().map(|source| source.created).max(),
+        provenance,
+        ..options.clone()
+    };
+
+    save_slash_16_in(output, subnet, merged.results.clone(), &options).await?;
+
+    Ok((Some(merged), skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn success(ms: u64) -> PingResult {
+        PingResult::Success(Duration::from_millis(ms))
+    }
+
+    /// A run with /24 0 starting with the given results, and timeouts everywhere else
+    fn source(name: &str, created: u64, first: &[PingResult], extra_slash_24: bool) -> MergeSource {
+        let slash_24 = |first: &[PingResult]| -> Slash24Result {
+            let mut slash_24 = vec![PingResult::Timeout; 256];
+            slash_24[..first.len()].clone_from_slice(first);
+
+            Arc::new(slash_24.try_into().unwrap())
+        };
+
+        let mut slash_16 = vec![None; 256];
+        slash_16[0] = Some(slash_24(first));
+
+        if extra_slash_24 {
+            slash_16[7] = Some(slash_24(&[success(1)]));
+        }
+
+        MergeSource {
+            name: name.to_string(),
+            results: Arc::new(slash_16.try_into().unwrap()),
+            created,
+        }
+    }
+
+    /// Three runs given out of order, the last two from the same time
+    fn sources() -> Vec<MergeSource> {
+        use PingResult::{Error, Timeout};
+
+        vec![
+            source(
+                "b",
+                5,
+                &[
+                    Timeout,
+                    Timeout,
+                    success(20),
+                    Error,
+                    Timeout,
+                    Timeout,
+                    success(20),
+                ],
+                false,
+            ),
+            source(
+                "a",
+                1,
+                &[
+                    Error,
+                    success(10),
+                    success(10),
+                    Timeout,
+                    success(10),
+                    Timeout,
+                    success(10),
+                ],
+                false,
+            ),
+            source(
+                "c",
+                5,
+                &[
+                    Error,
+                    success(30),
+                    Timeout,
+                    success(30),
+                    Error,
+                    Timeout,
+                    success(30),
+                ],
+                true,
+            ),
+        ]
+    }
+
+    fn merged_first(policy: MergePolicy) -> Vec<PingResult> {
+        let merged = merge_slash_16(&sources(), policy);
+
+        assert_eq!(merged.conflicts, 5);
+
+        merged.results[0].as_ref().unwrap()[..7].to_vec()
+    }
+
+    #[test]
+    fn prefers_successes() {
+        use PingResult::Timeout;
+
+        assert_eq!(
+            merged_first(MergePolicy::PreferSuccess),
+            [
+                Timeout,
+                success(30),
+                success(20),
+                success(30),
+                success(10),
+                Timeout,
+                success(30)
+            ]
+        );
+    }
+
+    #[test]
+    fn prefers_the_newest_run() {
+        use PingResult::{Error, Timeout};
+
+        assert_eq!(
+            merged_first(MergePolicy::PreferNewest),
+            [
+                Error,
+                success(30),
+                Timeout,
+                success(30),
+                Error,
+                Timeout,
+                success(30)
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_the_majority() {
+        use PingResult::{Error, Timeout};
+
+        assert_eq!(
+            merged_first(MergePolicy::Majority),
+            [
+                Error,
+                success(30),
+                success(20),
+                success(30),
+                success(10),
+                Timeout,
+                success(30)
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_slash_24s_of_any_run() {
+        let merged = merge_slash_16(&sources(), MergePolicy::PreferNewest);
+
+        assert_eq!(merged.results[7], sources()[2].results[7]);
+        assert!(merged.results[8].is_none());
+
+        let single = merge_slash_16(&sources()[..1], MergePolicy::Majority);
+
+        assert_eq!(single.results, sources()[0].results);
+        assert_eq!(single.conflicts, 0);
+    }
+
+    #[tokio::test]
+    async fn returns_damaged_runs_instead_of_merging_them() {
+        let (good, damaged) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let subnet = "10.20.x.x".parse().ok().unwrap();
+
+        let options = SaveOptions {
+            created: Some(1_700_000_000),
+            ..SaveOptions::default()
+        };
+
+        save_slash_16_in(good.path(), subnet, sources()[0].results.clone(), &options)
+            .await
+            .unwrap();
+
+        let path = create_file_path_in(damaged.path(), subnet);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"not a /16").unwrap();
+
+        let runs = [
+            (
+                "good".to_string(),
+                DataSource::Directory(good.path().into()),
+            ),
+            (
+                "damaged".to_string(),
+                DataSource::Directory(damaged.path().into()),
+            ),
+        ];
+
+        let (read, skipped) = read_merge_sources(&runs, subnet).await.unwrap();
+
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].name, "good");
+        assert_eq!(read[0].created, 1_700_000_000);
+
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, "damaged");
+        assert!(skipped[0].1.is_damaged());
+    }
+}
@@ -0,0 +1,823 @@
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression};
+use futures::StreamExt;
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    multi::{count, length_count},
+    number::complete::{le_u16, le_u32, le_u64, u8 as parse_u8},
+    IResult,
+};
+
+use crate::{
+    stats::{Analysis, RttStats, Slash16Result, SubnetResults},
+    subnet::{Subnet, SubnetMask},
+};
+
+use super::{create_file_path_in, decode_slash_16, DataSource, StorageError};
+
+const MAGIC: &[u8; 4] = b"PTIS";
+
+const SUMMARY_VERSION: u8 = 1;
+
+/// Summary indexes are read, changed and written back whole while their journals
+/// are appended to, so concurrent updates must not interleave
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Identifies the version of a /16 file a summary was made from
+///
+/// A summary is only trusted while the file still has the same modification
+/// time and length, so files rewritten by any tool are summarised again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    /// The modification time in nanoseconds since the unix epoch
+    pub modified: u64,
+    pub length: u64,
+}
+
+impl FileStamp {
+    /// Stamps a file, returning None if it does not exist
+    pub fn of(path: &Path) -> Result<Option<Self>, StorageError> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Ok(Some(Self {
+            modified,
+            length: metadata.len(),
+        }))
+    }
+}
+
+/// The counts of a /24, small enough to keep for every /24 of the internet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slash24Summary {
+    pub alive: u16,
+    pub timed_out: u16,
+    pub errored: u16,
+    /// The median RTT of the addresses that replied, None if none did
+    pub median_rtt: Option<Duration>,
+    pub p90_rtt: Option<Duration>,
+    pub p99_rtt: Option<Duration>,
+}
+
+/// What the stats need to know about a /16 without decoding its file
+#[derive(Debug, Clone)]
+pub struct Slash16Summary {
+    pub stamp: FileStamp,
+    pub analysis: Analysis,
+    pub slash_24s: Arc<[Option<Slash24Summary>; 256]>,
+}
+
+impl Slash16Summary {
+    pub fn of(results: &Slash16Result, stamp: FileStamp) -> Self {
+        let mut analysis = Analysis::new(SubnetMask::Slash16);
+
+        let slash_24s: Vec<_> = results
+            .iter()
+            .map(|slash_24| {
+                let Some(slash_24) = slash_24 else {
+                    analysis.merge(&Analysis::unscanned(SubnetMask::Slash24));
+                    return None;
+                };
+
+                let anal = Analysis::of_subnet(SubnetResults::Slash24(slash_24.clone()));
+                analysis.merge(&anal);
+
+                Some(Slash24Summary {
+                    alive: anal.alive as u16,
+                    timed_out: anal.timed_out as u16,
+                    errored: anal.errored as u16,
+                    median_rtt: anal.rtt.median(),
+                    p90_rtt: anal.rtt.p90(),
+                    p99_rtt: anal.rtt.p99(),
+                })
+            })
+            .collect();
+
+        Self {
+            stamp,
+            analysis,
+            slash_24s: Arc::new(slash_24s.try_into().unwrap()),
+        }
+    }
+}
+
+/// The summaries of the /16s of a /8, kept in `<root>/.summary/<a>`
+///
+/// Saving a /16 appends its summary to a journal next to the index rather than
+/// rewriting the index, the journal is read on top of the index and folded
+/// into it the next time the index is saved
+#[derive(Debug, Clone, Default)]
+pub struct SummaryIndex {
+    slash_16s: BTreeMap<u8, Slash16Summary>,
+    journaled: bool,
+}
+
+impl SummaryIndex {
+    /// Reads the summary index of a /8 along with its journal, empty if there is none yet
+    pub fn read(root: &Path, slash_8: Subnet) -> Result<Self, StorageError> {
+        let mut index = match std::fs::read(create_summary_path(root, slash_8)) {
+            Ok(data) => Self::parse(&data)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let journal = match std::fs::read(create_journal_path(root, slash_8)) {
+            Ok(journal) => journal,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(index),
+            Err(e) => return Err(e.into()),
+        };
+
+        index.journaled = true;
+
+        /* Later entries win, and a torn entry at the end is a save still being written */
+
+        let mut input = &journal[..];
+
+        while let Some((version, entry, rest)) = split_journal_entry(input) {
+            input = rest;
+
+            if version != SUMMARY_VERSION {
+                continue;
+            }
+
+            match parse_entry(entry) {
+                Ok(([], (b, summary))) => {
+                    index.slash_16s.insert(b, summary);
+                }
+                _ => return Err(StorageError::Corrupt("invalid journal entry".to_string())),
+            }
+        }
+
+        Ok(index)
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, StorageError> {
+        let body = match data.strip_prefix(MAGIC).and_then(|rest| rest.split_first()) {
+            Some((&SUMMARY_VERSION, body)) => body,
+            Some((&version, _)) if version > SUMMARY_VERSION => {
+                return Err(StorageError::UnsupportedVersion(version))
+            }
+            _ => return Err(StorageError::Corrupt("not a summary index".to_string())),
+        };
+
+        let mut decompressed = Vec::new();
+
+        if let Err(e) = ZlibDecoder::new(body).read_to_end(&mut decompressed) {
+            return Err(match e.kind() {
+                ErrorKind::UnexpectedEof => StorageError::Truncated,
+                _ => StorageError::Corrupt(format!("invalid zlib stream ({e})")),
+            });
+        }
+
+        let (input, entries) = length_count(le_u16, parse_entry)(&decompressed)
+            .map_err(|e| StorageError::from_parse_error(e, "summary index"))?;
+
+        if !input.is_empty() {
+            return Err(StorageError::Corrupt(format!(
+                "{} bytes of trailing data",
+                input.len()
+            )));
+        }
+
+        Ok(Self {
+            slash_16s: entries.into_iter().collect(),
+            journaled: false,
+        })
+    }
+
+    /// Writes the summary index of a /8, replacing the previous one and its journal
+    pub fn save(&self, root: &Path, slash_8: Subnet) -> Result<(), StorageError> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.push(SUMMARY_VERSION);
+
+        let mut encoder = ZlibEncoder::new(data, Compression::default());
+
+        encoder.write_all(&(self.slash_16s.len() as u16).to_le_bytes())?;
+
+        for (b, summary) in &self.slash_16s {
+            encoder.write_all(&[*b])?;
+            serialize_summary_into(summary, &mut encoder)?;
+        }
+
+        let data = encoder.finish()?;
+
+        /* Write to a temporary file first so readers never see half an index */
+
+        let file_path = create_summary_path(root, slash_8);
+        let temp_path = file_path.with_extension("tmp");
+
+        std::fs::create_dir_all(file_path.parent().unwrap())?;
+        std::fs::write(&temp_path, data)?;
+        std::fs::rename(temp_path, file_path)?;
+
+        match std::fs::remove_file(create_journal_path(root, slash_8)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether summaries were journaled since the index was last saved, so it is worth saving
+    pub fn has_journal(&self) -> bool {
+        self.journaled
+    }
+
+    /// Returns the summary of a /16, whether or not it is still up to date
+    pub fn get(&self, subnet: Subnet) -> Option<&Slash16Summary> {
+        self.slash_16s.get(&subnet.octets()[1])
+    }
+
+    pub fn insert(&mut self, subnet: Subnet, summary: Slash16Summary) {
+        self.slash_16s.insert(subnet.octets()[1], summary);
+    }
+
+    pub fn remove(&mut self, subnet: Subnet) {
+        self.slash_16s.remove(&subnet.octets()[1]);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &Slash16Summary)> {
+        self.slash_16s.iter().map(|(b, summary)| (*b, summary))
+    }
+}
+
+/// Returns the path of the summary index of a /8 under a data root
+pub fn create_summary_path(root: &Path, slash_8: Subnet) -> PathBuf {
+    root.join(".summary").join(slash_8.octets()[0].to_string())
+}
+
+/// Returns the path of the journal of the summary index of a /8 under a data root
+pub fn create_journal_path(root: &Path, slash_8: Subnet) -> PathBuf {
+    create_summary_path(root, slash_8).with_extension("journal")
+}
+
+/// Splits the version, body and rest off of a journal entry, None once there is
+/// no whole entry left
+fn split_journal_entry(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&version, rest) = input.split_first()?;
+    let (length, rest) = rest.split_first_chunk::<4>()?;
+    let length = u32::from_le_bytes(*length) as usize;
+
+    (rest.len() >= length).then(|| (version, &rest[..length], &rest[length..]))
+}
+
+/// Summarises a /16 of a data root, reusing the cached summary if its file has not changed
+///
+/// Returns None if the /16 is not in the data root
+pub async fn summarize_slash_16_in(
+    root: &Path,
+    subnet: Subnet,
+    cached: Option<Slash16Summary>,
+) -> Result<Option<Slash16Summary>, StorageError> {
+    assert_eq!(
+        subnet.mask(),
+        SubnetMask::Slash16,
+        "summarize_slash_16_in only takes /16 subnets"
+    );
+
+    let file_path = create_file_path_in(root, subnet);
+
+    let Some(stamp) = FileStamp::of(&file_path)? else {
+        return Ok(None);
+    };
+
+    if let Some(cached) = cached.filter(|cached| cached.stamp == stamp) {
+        return Ok(Some(cached));
+    }
+
+    let source = DataSource::Directory(root.to_path_buf());
+
+    let Some(data) = source.read_raw_slash_16(subnet).await? else {
+        return Ok(None);
+    };
+
+    let results = decode_slash_16(&data).await?;
+
+    Ok(Some(Slash16Summary::of(&results, stamp)))
+}
+
+/// Summarises a /16 of any data source, consulting no summary index
+///
+/// Archives have no index, so their summaries get an empty stamp
+pub async fn summarize_slash_16(
+    source: &DataSource,
+    subnet: Subnet,
+) -> Result<Option<Slash16Summary>, StorageError> {
+    if let DataSource::Directory(root) = source {
+        return summarize_slash_16_in(root, subnet, None).await;
+    }
+
+    let Some(results) = source.read_slash_16(subnet).await? else {
+        return Ok(None);
+    };
+
+    let stamp = FileStamp {
+        modified: 0,
+        length: 0,
+    };
+
+    Ok(Some(Slash16Summary::of(&results, stamp)))
+}
+
+/// Summarises every /16 of a /8, `jobs` at a time, handing each one to `on_summary`
+/// as soon as it is done, in no particular order
+///
+/// Data roots reuse and then refresh their summary index, archives are always decoded
+pub async fn summarize_slash_8(
+    source: &DataSource,
+    slash_8: Subnet,
+    jobs: usize,
+    mut on_summary: impl FnMut(Subnet, Result<Option<Slash16Summary>, StorageError>),
+) -> Result<(), StorageError> {
+    assert_eq!(
+        slash_8.mask(),
+        SubnetMask::Slash8,
+        "summarize_slash_8 only takes /8 subnets"
+    );
+
+    let index = match source {
+        DataSource::Directory(root) => match SummaryIndex::read(root, slash_8) {
+            Ok(index) => index,
+            Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+                SummaryIndex::default()
+            }
+            Err(e) => return Err(e),
+        },
+        DataSource::Archive(_) => SummaryIndex::default(),
+    };
+
+    let mut summaries = futures::stream::iter(slash_8.iter_slash_16s())
+        .map(|b| {
+            let source = source.clone();
+            let cached = index.get(b).cloned();
+
+            let task = tokio::spawn(async move {
+                match &source {
+                    DataSource::Directory(root) => summarize_slash_16_in(root, b, cached).await,
+                    DataSource::Archive(_) => summarize_slash_16(&source, b).await,
+                }
+            });
+
+            async move { (b, task.await.expect("Summary worker panicked")) }
+        })
+        .buffer_unordered(jobs);
+
+    let mut updates = Vec::new();
+
+    while let Some((b, result)) = summaries.next().await {
+        let cached_stamp = index.get(b).map(|summary| summary.stamp);
+
+        match &result {
+            Ok(summary) if summary.as_ref().map(|summary| summary.stamp) != cached_stamp => {
+                updates.push((b, summary.clone()));
+            }
+            Err(e)
+                if (e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)))
+                    && cached_stamp.is_some() =>
+            {
+                updates.push((b, None));
+            }
+            _ => {}
+        }
+
+        on_summary(b, result);
+    }
+
+    match source {
+        DataSource::Directory(root) if !updates.is_empty() || index.has_journal() => {
+            update_summary_index(root, slash_8, updates)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the cached summary of a /16 of a data root without decoding it, None
+/// if there is none or its file has changed since
+pub fn cached_slash_16_summary(
+    root: &Path,
+    subnet: Subnet,
+) -> Result<Option<Slash16Summary>, StorageError> {
+    let slash_8 = Subnet::new([subnet.octets()[0], 0, 0, 0].into(), SubnetMask::Slash8);
+
+    let index = match SummaryIndex::read(root, slash_8) {
+        Ok(index) => index,
+        Err(e) if e.is_damaged() || matches!(e, StorageError::UnsupportedVersion(_)) => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let Some(summary) = index.get(subnet) else {
+        return Ok(None);
+    };
+
+    let stamp = FileStamp::of(&create_file_path_in(root, subnet))?;
+
+    Ok((stamp == Some(summary.stamp)).then(|| summary.clone()))
+}
+
+/// Journals the summary of a /16 that was just saved under a data root
+///
+/// Only the journal of its /8 is appended to, so saving every /16 of a /8 does
+/// not rewrite the index each time
+pub fn record_slash_16_summary(
+    root: &Path,
+    subnet: Subnet,
+    results: &Slash16Result,
+) -> Result<(), StorageError> {
+    let Some(stamp) = FileStamp::of(&create_file_path_in(root, subnet))? else {
+        return Ok(());
+    };
+
+    let mut entry = vec![subnet.octets()[1]];
+    serialize_summary_into(&Slash16Summary::of(results, stamp), &mut entry)?;
+
+    let mut data = vec![SUMMARY_VERSION];
+    data.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+    data.extend_from_slice(&entry);
+
+    let slash_8 = Subnet::new([subnet.octets()[0], 0, 0, 0].into(), SubnetMask::Slash8);
+    let journal_path = create_journal_path(root, slash_8);
+
+    let _guard = INDEX_LOCK.lock().unwrap();
+
+    std::fs::create_dir_all(journal_path.parent().unwrap())?;
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path)?
+        .write_all(&data)?;
+
+    Ok(())
+}
+
+/// Applies new summaries to the index of a /8, with None removing a /16 that is gone
+///
+/// A damaged index is started over rather than failing the update, it is only a cache
+pub fn update_summary_index(
+    root: &Path,
+    slash_8: Subnet,
+    updates: impl IntoIterator<Item = (Subnet, Option<Slash16Summary>)>,
+) -> Result<(), StorageError> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+
+    let mut index = match SummaryIndex::read(root, slash_8) {
+        Ok(index) => index,
+        Err(e) if e.is_damaged() => SummaryIndex::default(),
+        Err(e) => return Err(e),
+    };
+
+    for (subnet, summary) in updates {
+        match summary {
+            Some(summary) => index.insert(subnet, summary),
+            None => index.remove(subnet),
+        }
+    }
+
+    index.save(root, slash_8)
+}
+
+fn serialize_summary_into<W: Write>(summary: &Slash16Summary, w: &mut W) -> std::io::Result<()> {
+    let anal = &summary.analysis;
+
+    w.write_all(&summary.stamp.modified.to_le_bytes())?;
+    w.write_all(&summary.stamp.length.to_le_bytes())?;
+
+    for count in [anal.alive, anal.timed_out, anal.errored, anal.unscanned] {
+        w.write_all(&(count as u32).to_le_bytes())?;
+    }
+
+    /* Only the buckets in use, most /16s have RTTs in a few of them */
+
+    let buckets: Vec<_> = (0..anal.rtt.histogram.len())
+        .filter(|bucket| anal.rtt.histogram[*bucket] != 0)
+        .collect();
+
+    w.write_all(&[buckets.len() as u8])?;
+
+    for bucket in buckets {
+        w.write_all(&[bucket as u8])?;
+        w.write_all(&(anal.rtt.histogram[bucket] as u32).to_le_bytes())?;
+    }
+
+    if let (Some(min), Some(max)) = (anal.rtt.min, anal.rtt.max) {
+        w.write_all(&(min.as_millis() as u16).to_le_bytes())?;
+        w.write_all(&(max.as_millis() as u16).to_le_bytes())?;
+    }
+
+    for slash_24 in &*summary.slash_24s {
+        let Some(slash_24) = slash_24 else {
+            w.write_all(&[0x00])?;
+            continue;
+        };
+
+        w.write_all(&[0x01])?;
+
+        for count in [slash_24.alive, slash_24.timed_out, slash_24.errored] {
+            w.write_all(&count.to_le_bytes())?;
+        }
+
+        /* Replies can all have unknown RTTs, which leaves no quantiles */
+
+        if slash_24.alive != 0 {
+            for rtt in [slash_24.median_rtt, slash_24.p90_rtt, slash_24.p99_rtt] {
+                let rtt = rtt.map_or(u16::MAX, |rtt| rtt.as_millis() as u16);
+                w.write_all(&rtt.to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_entry(input: &[u8]) -> IResult<&[u8], (u8, Slash16Summary)> {
+    let (input, b) = parse_u8(input)?;
+    let (input, modified) = le_u64(input)?;
+    let (input, length) = le_u64(input)?;
+    let (input, counts) = count(le_u32, 4)(input)?;
+    let (input, buckets) = length_count(parse_u8, parse_bucket)(input)?;
+
+    let mut rtt = RttStats::default();
+
+    for (bucket, bucket_count) in &buckets {
+        let Some(slot) = rtt.histogram.get_mut(*bucket as usize) else {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Verify,
+            )));
+        };
+
+        *slot = *bucket_count as u64;
+    }
+
+    let input = match buckets.is_empty() {
+        true => input,
+        false => {
+            let (input, min) = le_u16(input)?;
+            let (input, max) = le_u16(input)?;
+
+            rtt.min = Some(Duration::from_millis(min as u64));
+            rtt.max = Some(Duration::from_millis(max as u64));
+
+            input
+        }
+    };
+
+    let (input, slash_24s) = count(parse_optional_slash_24_summary, 256)(input)?;
+
+    let summary = Slash16Summary {
+        stamp: FileStamp { modified, length },
+        analysis: Analysis {
+            mask: SubnetMask::Slash16,
+            alive: counts[0] as u64,
+            timed_out: counts[1] as u64,
+            errored: counts[2] as u64,
+            unscanned: counts[3] as u64,
+            rtt,
+        },
+        slash_24s: Arc::new(slash_24s.try_into().unwrap()),
+    };
+
+    Ok((input, (b, summary)))
+}
+
+fn parse_bucket(input: &[u8]) -> IResult<&[u8], (u8, u32)> {
+    let (input, bucket) = parse_u8(input)?;
+    let (input, bucket_count) = le_u32(input)?;
+
+    Ok((input, (bucket, bucket_count)))
+}
+
+fn parse_optional_slash_24_summary(input: &[u8]) -> IResult<&[u8], Option<Slash24Summary>> {
+    let (input, enum_tag) = alt((tag(&[0x00]), tag(&[0x01])))(input)?;
+
+    if enum_tag == [0x00] {
+        return Ok((input, None));
+    }
+
+    let (input, alive) = le_u16(input)?;
+    let (input, timed_out) = le_u16(input)?;
+    let (input, errored) = le_u16(input)?;
+
+    let (input, [median_rtt, p90_rtt, p99_rtt]) = match alive {
+        0 => (input, [None; 3]),
+        _ => {
+            let (input, rtts) = count(le_u16, 3)(input)?;
+            let rtt = |rtt: u16| (rtt != u16::MAX).then(|| Duration::from_millis(rtt as u64));
+
+            (input, [rtt(rtts[0]), rtt(rtts[1]), rtt(rtts[2])])
+        }
+    };
+
+    let summary = Slash24Summary {
+        alive,
+        timed_out,
+        errored,
+        median_rtt,
+        p90_rtt,
+        p99_rtt,
+    };
+
+    Ok((input, Some(summary)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        file::tests::sample_slash_16,
+        ping::{PingResult, UNKNOWN_RTT},
+    };
+
+    use super::*;
+
+    fn subnet(subnet: &str) -> Subnet {
+        subnet.parse().ok().unwrap()
+    }
+
+    fn assert_same_summary(read: &Slash16Summary, written: &Slash16Summary) {
+        let (read_anal, written_anal) = (&read.analysis, &written.analysis);
+
+        assert_eq!(read.stamp, written.stamp);
+        assert_eq!(read_anal.alive, written_anal.alive);
+        assert_eq!(read_anal.timed_out, written_anal.timed_out);
+        assert_eq!(read_anal.errored, written_anal.errored);
+        assert_eq!(read_anal.unscanned, written_anal.unscanned);
+        assert_eq!(read_anal.rtt.histogram, written_anal.rtt.histogram);
+        assert_eq!(read_anal.rtt.min, written_anal.rtt.min);
+        assert_eq!(read_anal.rtt.max, written_anal.rtt.max);
+        assert_eq!(read.slash_24s, written.slash_24s);
+    }
+
+    #[test]
+    fn round_trips_summary_indexes() {
+        let root = tempfile::tempdir().unwrap();
+        let slash_8 = subnet("10.x.x.x");
+
+        /* A /24 whose replies all have unknown RTTs has no median */
+
+        let mut results = sample_slash_16(5).to_vec();
+        let unknown_rtts: Vec<_> = (0..256).map(|_| PingResult::Success(UNKNOWN_RTT)).collect();
+
+        results[1] = Some(Arc::new(unknown_rtts.try_into().unwrap()));
+
+        let results: Slash16Result = Arc::new(results.try_into().unwrap());
+
+        let stamp = FileStamp {
+            modified: 1_700_000_000_123_456_789,
+            length: 12345,
+        };
+
+        let first = Slash16Summary::of(&results, stamp);
+        let second = Slash16Summary::of(&sample_slash_16(6), stamp);
+
+        let unknown = first.slash_24s[1].unwrap();
+
+        assert_eq!(
+            (unknown.median_rtt, unknown.p90_rtt, unknown.p99_rtt),
+            (None, None, None)
+        );
+        assert!(first.slash_24s.iter().any(Option::is_none));
+
+        for slash_24 in first
+            .slash_24s
+            .iter()
+            .flatten()
+            .filter(|s| s.median_rtt.is_some())
+        {
+            assert!(slash_24.median_rtt <= slash_24.p90_rtt);
+            assert!(slash_24.p90_rtt <= slash_24.p99_rtt);
+        }
+
+        let mut index = SummaryIndex::default();
+        index.insert(subnet("10.0.x.x"), first.clone());
+        index.insert(subnet("10.255.x.x"), second.clone());
+        index.save(root.path(), slash_8).unwrap();
+
+        let read = SummaryIndex::read(root.path(), slash_8).unwrap();
+
+        assert_eq!(read.iter().count(), 2);
+        assert_same_summary(read.get(subnet("10.0.x.x")).unwrap(), &first);
+        assert_same_summary(read.get(subnet("10.255.x.x")).unwrap(), &second);
+
+        /* Updates replace and remove single /16s */
+
+        update_summary_index(
+            root.path(),
+            slash_8,
+            [
+                (subnet("10.0.x.x"), None),
+                (subnet("10.1.x.x"), Some(first.clone())),
+            ],
+        )
+        .unwrap();
+
+        let read = SummaryIndex::read(root.path(), slash_8).unwrap();
+
+        assert!(read.get(subnet("10.0.x.x")).is_none());
+        assert_same_summary(read.get(subnet("10.1.x.x")).unwrap(), &first);
+        assert_same_summary(read.get(subnet("10.255.x.x")).unwrap(), &second);
+    }
+
+    #[test]
+    fn reads_missing_and_rejects_damaged_indexes() {
+        let root = tempfile::tempdir().unwrap();
+        let slash_8 = subnet("10.x.x.x");
+
+        let missing = SummaryIndex::read(root.path(), slash_8).unwrap();
+        assert_eq!(missing.iter().count(), 0);
+
+        let path = create_summary_path(root.path(), slash_8);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        std::fs::write(&path, b"not an index").unwrap();
+        assert!(SummaryIndex::read(root.path(), slash_8)
+            .unwrap_err()
+            .is_damaged());
+
+        std::fs::write(&path, [&MAGIC[..], &[SUMMARY_VERSION + 1]].concat()).unwrap();
+        assert!(matches!(
+            SummaryIndex::read(root.path(), slash_8),
+            Err(StorageError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn journals_saved_slash_16s() {
+        let root = tempfile::tempdir().unwrap();
+        let slash_8 = subnet("10.x.x.x");
+
+        let options = crate::file::SaveOptions::default();
+
+        for (b, seed) in [("10.1.x.x", 1), ("10.2.x.x", 2), ("10.1.x.x", 3)] {
+            crate::file::save_slash_16_in(root.path(), subnet(b), sample_slash_16(seed), &options)
+                .await
+                .unwrap();
+        }
+
+        /* Saves only append to the journal, and the last save of a /16 wins */
+
+        assert!(!create_summary_path(root.path(), slash_8).exists());
+
+        let index = SummaryIndex::read(root.path(), slash_8).unwrap();
+        assert!(index.has_journal());
+
+        let stamp = |b| FileStamp::of(&create_file_path_in(root.path(), subnet(b))).unwrap();
+
+        for (b, seed) in [("10.1.x.x", 3), ("10.2.x.x", 2)] {
+            let cached = cached_slash_16_summary(root.path(), subnet(b)).unwrap();
+            let expected = Slash16Summary::of(&sample_slash_16(seed), stamp(b).unwrap());
+
+            assert_same_summary(&cached.unwrap(), &expected);
+        }
+
+        /* A torn entry at the end is left out */
+
+        let journal_path = create_journal_path(root.path(), slash_8);
+        let mut journal = std::fs::read(&journal_path).unwrap();
+        journal.extend_from_slice(&[SUMMARY_VERSION, 0xff, 0xff, 0x00, 0x00, 0x07]);
+        std::fs::write(&journal_path, journal).unwrap();
+
+        assert_eq!(
+            SummaryIndex::read(root.path(), slash_8)
+                .unwrap()
+                .iter()
+                .count(),
+            2
+        );
+
+        /* Updating the index folds the journal into it */
+
+        update_summary_index(root.path(), slash_8, []).unwrap();
+
+        assert!(!journal_path.exists());
+
+        let index = SummaryIndex::read(root.path(), slash_8).unwrap();
+
+        assert!(!index.has_journal());
+        assert_eq!(
+            index.get(subnet("10.1.x.x")).unwrap().stamp,
+            stamp("10.1.x.x").unwrap()
+        );
+        assert_eq!(
+            index.get(subnet("10.2.x.x")).unwrap().stamp,
+            stamp("10.2.x.x").unwrap()
+        );
+    }
+}
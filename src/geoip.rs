@@ -0,0 +1,372 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    io::ErrorKind,
+    net::Ipv4Addr,
+    path::Path,
+    sync::Arc,
+};
+
+use ipnetwork::Ipv4Network;
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use serde::{de::IgnoredAny, Serialize, Serializer};
+
+use crate::{
+    attribution::{AddressCounts, Attributed},
+    ping::PingResult,
+    stats::{RttStats, Slash16Result},
+    subnet::{Subnet, SubnetMask},
+};
+
+/// A country by its ISO 3166-1 code, along with the code of its continent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Country {
+    pub code: [u8; 2],
+    /// `--` when the database has no continent for it
+    pub continent: [u8; 2],
+}
+
+impl Country {
+    fn new(code: &str, continent: Option<&str>) -> Option<Self> {
+        let code = two_letter_code(code)?;
+        let continent = continent.and_then(two_letter_code).unwrap_or(*b"--");
+
+        Some(Self { code, continent })
+    }
+
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.code).unwrap()
+    }
+
+    pub fn continent(&self) -> &str {
+        std::str::from_utf8(&self.continent).unwrap()
+    }
+}
+
+impl Display for Country {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.code())
+    }
+}
+
+impl Serialize for Country {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+/// A local GeoIP2 or GeoLite2 Country (or City) database in the MaxMind DB format
+///
+/// Lookups never leave the machine, the whole database is read into memory
+pub struct GeoIpDatabase {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIpDatabase {
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let reader = Reader::open_readfile(path).map_err(|e| match e {
+            MaxMindDBError::IoError(message) => std::io::Error::other(message),
+            e => invalid_data(e.to_string()),
+        })?;
+
+        Ok(Self { reader })
+    }
+
+    /// The type the database says it is, e.g. `GeoLite2-Country`
+    pub fn database_type(&self) -> &str {
+        &self.reader.metadata.database_type
+    }
+
+    /// Returns the country of an address, None if the database has none for it
+    pub fn lookup(&self, address: Ipv4Addr) -> std::io::Result<Option<Country>> {
+        Ok(self.lookup_network(address)?.country)
+    }
+
+    /// Returns the network the address is in along with its country, which is
+    /// None for the networks the database has nothing for
+    fn lookup_network(&self, address: Ipv4Addr) -> std::io::Result<Network> {
+        let record = self.reader.lookup_prefix::<geoip2::Country>(address.into());
+
+        let (record, length) = match record {
+            Ok(record) => record,
+            Err(MaxMindDBError::AddressNotFoundError(_)) => {
+                return Ok(Network::new(address, self.missing_prefix(address)?, None))
+            }
+            Err(e) => return Err(invalid_data(format!("{address}: {e}"))),
+        };
+
+        let continent = record.continent.and_then(|continent| continent.code);
+        let country = record
+            .country
+            .and_then(|country| country.iso_code)
+            .and_then(|code| Country::new(code, continent));
+
+        Ok(Network::new(address, length as u8, country))
+    }
+
+    /// Returns the length of the largest prefix around an address that is not in the database
+    ///
+    /// The reader does not say how far a miss extends, so the shortest prefix
+    /// with no networks in it is narrowed down instead, a /32 always being one
+    fn missing_prefix(&self, address: Ipv4Addr) -> std::io::Result<u8> {
+        let (mut shortest, mut longest) = (0, 32);
+
+        while shortest < longest {
+            let length = (shortest + longest) / 2;
+            let network = Ipv4Network::new(address, length).unwrap();
+
+            let first = self
+                .reader
+                .within::<IgnoredAny>(network.into())
+                .map_err(|e| invalid_data(format!("{network}: {e}")))?
+                .next();
+
+            match first {
+                None => longest = length,
+                Some(Ok(_)) => shortest = length + 1,
+                Some(Err(e)) => return Err(invalid_data(format!("{network}: {e}"))),
+            }
+        }
+
+        Ok(longest)
+    }
+}
+
+/// A network of the database, which every address of has the same country
+#[derive(Debug, Clone, Copy)]
+struct Network {
+    start: u32,
+    end: u32,
+    country: Option<Country>,
+}
+
+impl Network {
+    /// The network with the given prefix length that an address is in
+    fn new(address: Ipv4Addr, length: u8, country: Option<Country>) -> Self {
+        let mask = u32::MAX.checked_shl(32 - length as u32).unwrap_or(0);
+        let start = u32::from(address) & mask;
+
+        Self {
+            start,
+            end: start | !mask,
+            country,
+        }
+    }
+}
+
+/// Looks up addresses in order, reusing the network of the last one
+///
+/// Networks are mostly far larger than a single address, so walking a /16
+/// takes a handful of lookups instead of one per address. The same goes for
+/// the space the database has nothing for, such as private networks
+pub struct Locator {
+    database: Arc<GeoIpDatabase>,
+    last: Option<Network>,
+}
+
+impl Locator {
+    pub fn new(database: Arc<GeoIpDatabase>) -> Self {
+        Self {
+            database,
+            last: None,
+        }
+    }
+
+    pub fn locate(&mut self, address: Ipv4Addr) -> std::io::Result<Option<Country>> {
+        let value = u32::from(address);
+
+        if let Some(last) = self
+            .last
+            .filter(|last| (last.start..=last.end).contains(&value))
+        {
+            return Ok(last.country);
+        }
+
+        let network = self.database.lookup_network(address)?;
+
+        self.last = Some(network);
+
+        Ok(network.country)
+    }
+
+    /// Attributes every scanned address of a /16 inside the scope to its country
+    pub fn attribute_slash_16(
+        &mut self,
+        subnet: Subnet,
+        scope: Subnet,
+        results: &Slash16Result,
+    ) -> std::io::Result<Slash16Countries> {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "attribute_slash_16 only takes /16 subnets"
+        );
+
+        let [a, b, _, _] = subnet.octets();
+
+        let mut attribution = Slash16Countries::default();
+
+        for (c, slash_24) in results.iter().enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            for (d, ping_result) in slash_24.iter().enumerate() {
+                let address = Ipv4Addr::new(a, b, c as u8, d as u8);
+
+                if !scope.contains(address) {
+                    continue;
+                }
+
+                let totals = match self.locate(address)? {
+                    Some(country) => attribution.countries.entry(country).or_default(),
+                    None => &mut attribution.unlocated,
+                };
+
+                totals.count(ping_result);
+            }
+        }
+
+        Ok(attribution)
+    }
+}
+
+/// The counts and RTTs of the scanned addresses of a country
+#[derive(Debug, Clone, Default)]
+pub struct CountryTotals {
+    pub counts: AddressCounts,
+    pub rtt: RttStats,
+}
+
+impl CountryTotals {
+    fn count(&mut self, ping_result: &PingResult) {
+        self.counts.count(ping_result);
+
+        if let Some(rtt) = ping_result.rtt() {
+            self.rtt.record(rtt);
+        }
+    }
+
+    pub fn merge(&mut self, other: &CountryTotals) {
+        self.counts.merge(&other.counts);
+        self.rtt.merge(&other.rtt);
+    }
+}
+
+impl Attributed for CountryTotals {
+    fn counts(&self) -> &AddressCounts {
+        &self.counts
+    }
+}
+
+/// The countries the scanned addresses of a single /16 were attributed to
+#[derive(Debug, Clone, Default)]
+pub struct Slash16Countries {
+    pub countries: HashMap<Country, CountryTotals>,
+    /// The addresses the database has no country for
+    pub unlocated: CountryTotals,
+}
+
+/// The attribution of a whole run, summed up from [`Slash16Countries`]
+#[derive(Debug, Clone, Default)]
+pub struct CountryReport {
+    pub countries: BTreeMap<Country, CountryTotals>,
+    pub unlocated: CountryTotals,
+}
+
+impl CountryReport {
+    pub fn add(&mut self, attribution: &Slash16Countries) {
+        for (country, totals) in &attribution.countries {
+            self.countries.entry(*country).or_default().merge(totals);
+        }
+
+        self.unlocated.merge(&attribution.unlocated);
+    }
+
+    /// The number of scanned addresses, located or not
+    pub fn scanned(&self) -> u64 {
+        self.countries
+            .values()
+            .map(|totals| totals.counts.scanned())
+            .sum::<u64>()
+            + self.unlocated.counts.scanned()
+    }
+}
+
+pub fn print_country_table_header() {
+    println!(
+        "| {:^4} | {:^7} | {:^9} | {:^10} | {:^10} | {:^9} | {:^6} | {:^6} | {:^6} | {:^6} | {:^6} |",
+        "RANK",
+        "COUNTRY",
+        "CONTINENT",
+        "SCANNED",
+        "SUCCEEDED",
+        "ALIVE %",
+        "MIN ms",
+        "MED ms",
+        "P90 ms",
+        "P99 ms",
+        "MAX ms",
+    );
+    println!(
+        "|{:->6}|{:->9}|{:->11}|{:->12}|{:->12}|{:->11}|{:->8}|{:->8}|{:->8}|{:->8}|{:->8}|",
+        "", "", "", "", "", "", "", "", "", "", ""
+    );
+}
+
+pub fn print_country_table_row(rank: usize, country: &Country, totals: &CountryTotals) {
+    let ms = |rtt: Option<std::time::Duration>| {
+        rtt.map_or("-".to_string(), |rtt| rtt.as_millis().to_string())
+    };
+
+    println!(
+        "| {:>4} | {:>7} | {:>9} | {:>10} | {:>10} | {:>9} | {:>6} | {:>6} | {:>6} | {:>6} | {:>6} |",
+        rank,
+        country.code(),
+        country.continent(),
+        totals.counts.scanned(),
+        totals.counts.alive,
+        format!("{:.2}%", totals.counts.alive_percent()),
+        ms(totals.rtt.min),
+        ms(totals.rtt.median()),
+        ms(totals.rtt.p90()),
+        ms(totals.rtt.p99()),
+        ms(totals.rtt.max),
+    );
+}
+
+pub fn print_country_csv_header() {
+    println!("rank,country,continent,scanned,alive,alive_percent,rtt_min_ms,rtt_median_ms,rtt_p90_ms,rtt_p99_ms,rtt_max_ms");
+}
+
+pub fn print_country_csv_row(rank: usize, country: &Country, totals: &CountryTotals) {
+    let ms = |rtt: Option<std::time::Duration>| {
+        rtt.map_or(String::new(), |rtt| rtt.as_millis().to_string())
+    };
+
+    println!(
+        "{},{},{},{},{},{:.2},{},{},{},{},{}",
+        rank,
+        country.code(),
+        country.continent(),
+        totals.counts.scanned(),
+        totals.counts.alive,
+        totals.counts.alive_percent(),
+        ms(totals.rtt.min),
+        ms(totals.rtt.median()),
+        ms(totals.rtt.p90()),
+        ms(totals.rtt.p99()),
+        ms(totals.rtt.max),
+    );
+}
+
+fn two_letter_code(code: &str) -> Option<[u8; 2]> {
+    code.as_bytes()
+        .try_into()
+        .ok()
+        .filter(|code: &[u8; 2]| code.iter().all(u8::is_ascii_alphanumeric))
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message)
+}
@@ -8,6 +8,11 @@ use std::{
     time::{Duration, Instant},
 };
 
+use crate::{
+    iana::slash_8_registration,
+    render::{format_rtt, viridis, Palette, RttColoring, RttScale},
+};
+
 pub trait GetColor {
     fn get_color(&self) -> Color;
 }
@@ -42,7 +47,8 @@ impl GetColor for Slash16State {
 pub enum Slash32State {
     Scheduled,
     Pending,
-    Success,
+    /// Replied after the given RTT
+    Success(Duration),
     Timeout,
     Error,
 }
@@ -60,7 +66,7 @@ impl GetColor for Slash32State {
         match self {
             Self::Scheduled => Self::SCHEDULED_COLOR,
             Self::Pending => Self::PENDING_COLOR,
-            Self::Success => Self::SUCCESS_COLOR,
+            Self::Success(_) => Self::SUCCESS_COLOR,
             Self::Timeout => Self::TIMEOUT_COLOR,
             Self::Error => Self::ERROR_COLOR,
         }
@@ -93,7 +99,27 @@ pub fn gui_main() {
         .title("Ping The Internet")
         .build();
 
+    /* R switches the /16 to a heatmap of RTTs, L between a log and a linear scale */
+
+    let mut rtt_coloring: Option<RttColoring> = None;
+
     while !rl.window_should_close() {
+        if rl.is_key_pressed(KeyboardKey::KEY_R) {
+            rtt_coloring = match rtt_coloring {
+                Some(_) => None,
+                None => Some(RttColoring::default()),
+            };
+        }
+
+        if let Some(coloring) = rtt_coloring.as_mut() {
+            if rl.is_key_pressed(KeyboardKey::KEY_L) {
+                coloring.scale = match coloring.scale {
+                    RttScale::Log => RttScale::Linear,
+                    RttScale::Linear => RttScale::Log,
+                };
+            }
+        }
+
         let mut d = rl.begin_drawing(&thread);
 
         d.clear_background(Color::new(0x18, 0x18, 0x18, 0xFF));
@@ -107,7 +133,12 @@ pub fn gui_main() {
         render_slash_0(&mut d, start_location, &slash_16_states, &slash_32_states);
 
         let start_location = Vector2::new(800.0, 50.0);
-        render_slash_16(&mut d, start_location, &slash_32_states);
+        render_slash_16(
+            &mut d,
+            start_location,
+            &slash_32_states,
+            rtt_coloring.as_ref(),
+        );
     }
 }
 
@@ -117,7 +148,7 @@ fn render_slash_0(
     slash_16_states: &[[Slash16State; 256]; 256],
     slash_32_states: &[[Slash32State; 256]; 256],
 ) {
-    render_grid(d, start_location, slash_16_states);
+    render_grid(d, start_location, slash_16_states, Slash16State::get_color);
 
     /* Legend */
 
@@ -225,14 +256,57 @@ fn render_slash_0(
         TEXT_SIZE,
         Color::WHITE,
     );
+
+    /* Who IANA gave the hovered /8 to */
+
+    if let Some(a) = hovered_slash_8(d.get_mouse_position(), start_location) {
+        let registration = slash_8_registration(a);
+
+        let x = (a % 16) as f32 * (SLASH_8_BLOCK_SIZE + SLASH_8_BLOCK_SPACING);
+        let y = (a / 16) as f32 * (SLASH_8_BLOCK_SIZE + SLASH_8_BLOCK_SPACING);
+
+        d.draw_rectangle_lines(
+            (start_location.x + x) as i32 - 1,
+            (start_location.y + y) as i32 - 1,
+            SLASH_8_BLOCK_SIZE as i32 + 2,
+            SLASH_8_BLOCK_SIZE as i32 + 2,
+            Color::WHITE,
+        );
+
+        d.draw_text(
+            &format!(
+                "{}.x.x.x: {} ({}, {}){}",
+                a,
+                registration.designation,
+                registration.status,
+                registration.date,
+                registration
+                    .whois
+                    .map_or(String::new(), |whois| format!(" via {whois}")),
+            ),
+            start_location.x as i32,
+            (start_location.y + TOTAL_SIZE) as i32 + 20 + 5 * 16,
+            TEXT_SIZE,
+            Color::LIGHTGRAY,
+        );
+    }
 }
 
 fn render_slash_16(
     d: &mut RaylibDrawHandle,
     start_location: Vector2,
     states: &[[Slash32State; 256]; 256],
+    rtt_coloring: Option<&RttColoring>,
 ) {
-    render_grid(d, start_location, states);
+    render_grid(d, start_location, states, |state| {
+        match (rtt_coloring, state) {
+            (Some(coloring), Slash32State::Success(rtt)) => to_color(coloring.color(*rtt)),
+            (Some(_), Slash32State::Timeout | Slash32State::Error) => {
+                to_color(Palette::DEFAULT.dead)
+            }
+            _ => state.get_color(),
+        }
+    });
 
     let all_states = states.iter().flat_map(|s| *s).collect::<Vec<_>>();
 
@@ -246,7 +320,7 @@ fn render_slash_16(
         .count();
     let success = all_states
         .iter()
-        .filter(|s| **s == Slash32State::Success)
+        .filter(|s| matches!(s, Slash32State::Success(_)))
         .count();
     let timeout = all_states
         .iter()
@@ -308,12 +382,74 @@ fn render_slash_16(
         12,
         Slash32State::ERROR_COLOR,
     );
+
+    if let Some(coloring) = rtt_coloring {
+        render_rtt_legend(
+            d,
+            Vector2::new(
+                start_location.x + TOTAL_SIZE / 2.0,
+                start_location.y + TOTAL_SIZE + 20.0,
+            ),
+            coloring,
+        );
+    }
+
+    d.draw_text(
+        "R: RTT heatmap, L: log or linear scale",
+        start_location.x as i32,
+        (start_location.y + TOTAL_SIZE) as i32 + 20 + 6 * 16,
+        TEXT_SIZE,
+        Color::GRAY,
+    );
 }
 
-fn render_grid(
+fn render_rtt_legend(d: &mut RaylibDrawHandle, location: Vector2, coloring: &RttColoring) {
+    const WIDTH: i32 = 256;
+    const HEIGHT: i32 = 12;
+
+    let x = location.x as i32;
+    let y = location.y as i32;
+
+    for i in 0..WIDTH {
+        let color = to_color(viridis(i as f32 / (WIDTH - 1) as f32));
+
+        d.draw_rectangle(x + i, y, 1, HEIGHT, color);
+    }
+
+    for tick in coloring.ticks(4) {
+        let tick_x = x + (coloring.fraction(tick) * (WIDTH - 1) as f32).round() as i32;
+        let label = format_rtt(tick);
+        let width = d.measure_text(&label, TEXT_SIZE);
+
+        d.draw_line(tick_x, y + HEIGHT, tick_x, y + HEIGHT + 4, Color::LIGHTGRAY);
+        d.draw_text(
+            &label,
+            tick_x - width / 2,
+            y + HEIGHT + 6,
+            TEXT_SIZE,
+            Color::LIGHTGRAY,
+        );
+    }
+
+    let scale = match coloring.scale {
+        RttScale::Log => "RTT (log)",
+        RttScale::Linear => "RTT (linear)",
+    };
+
+    d.draw_text(scale, x + WIDTH + 8, y, TEXT_SIZE, Color::LIGHTGRAY);
+}
+
+fn to_color(color: image::Rgb<u8>) -> Color {
+    let [r, g, b] = color.0;
+
+    Color::new(r, g, b, 0xFF)
+}
+
+fn render_grid<T>(
     d: &mut RaylibDrawHandle,
     start_location: Vector2,
-    states: &[[impl GetColor; 256]; 256],
+    states: &[[T; 256]; 256],
+    color: impl Fn(&T) -> Color + Copy,
 ) {
     for x in 0..16 {
         for y in 0..16 {
@@ -324,6 +460,7 @@ fn render_grid(
                     start_location.y + y as f32 * (SLASH_8_BLOCK_SIZE + SLASH_8_BLOCK_SPACING),
                 ),
                 &states[y * 16 + x],
+                color,
             )
         }
     }
@@ -363,10 +500,35 @@ fn render_grid(
     }
 }
 
-fn render_block(d: &mut RaylibDrawHandle, start_location: Vector2, states: &[impl GetColor; 256]) {
+/// Returns the /8 whose block of the grid is under the mouse, if any
+fn hovered_slash_8(mouse: Vector2, start_location: Vector2) -> Option<u8> {
+    let stride = SLASH_8_BLOCK_SIZE + SLASH_8_BLOCK_SPACING;
+
+    let x = mouse.x - start_location.x;
+    let y = mouse.y - start_location.y;
+
+    if x < 0.0 || y < 0.0 || x >= TOTAL_SIZE || y >= TOTAL_SIZE {
+        return None;
+    }
+
+    /* The spacing between blocks belongs to neither */
+
+    if x % stride >= SLASH_8_BLOCK_SIZE || y % stride >= SLASH_8_BLOCK_SIZE {
+        return None;
+    }
+
+    Some((y / stride) as u8 * 16 + (x / stride) as u8)
+}
+
+fn render_block<T>(
+    d: &mut RaylibDrawHandle,
+    start_location: Vector2,
+    states: &[T; 256],
+    color: impl Fn(&T) -> Color,
+) {
     for x in 0..16 {
         for y in 0..16 {
-            let color = states[y * 16 + x].get_color();
+            let color = color(&states[y * 16 + x]);
 
             d.draw_rectangle_v(
                 Vector2::new(
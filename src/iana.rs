@@ -0,0 +1,165 @@
+use std::fmt::Display;
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::{
+    delegation::Rir,
+    subnet::{Subnet, SubnetMask},
+};
+
+/// IANA's IPv4 address space registry, one line per /8 as
+/// `prefix,designation,date,whois,status,label`
+///
+/// Taken from https://www.iana.org/assignments/ipv4-address-space with the RDAP
+/// and note columns left out, and a short label added for maps and tables.
+/// Designations may hold commas, so the fields after them are split from the end
+const REGISTRY: &str = include_str!("iana/ipv4-address-space.csv");
+
+static SLASH_8S: Lazy<Vec<Slash8Registration>> = Lazy::new(|| {
+    let registrations: Vec<_> = REGISTRY
+        .lines()
+        .skip(1)
+        .enumerate()
+        .map(|(i, line)| {
+            Slash8Registration::parse(line)
+                .unwrap_or_else(|| panic!("Invalid registry line {}", i + 2))
+        })
+        .collect();
+
+    assert!(
+        registrations
+            .iter()
+            .enumerate()
+            .all(|(i, registration)| registration.slash_8 as usize == i),
+        "The registry must list every /8 in order"
+    );
+
+    registrations
+});
+
+/// The status of a /8 in the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Slash8Status {
+    /// Allocated to a RIR
+    Allocated,
+    /// Assigned before the RIRs existed, to an organisation or for a RIR to administer
+    Legacy,
+    /// Special use, e.g. private, loopback or multicast space
+    Reserved,
+}
+
+impl Display for Slash8Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Allocated => "ALLOCATED",
+            Self::Legacy => "LEGACY",
+            Self::Reserved => "RESERVED",
+        })
+    }
+}
+
+/// Who a /8 was given to, according to IANA
+#[derive(Debug, Clone, Serialize)]
+pub struct Slash8Registration {
+    pub slash_8: u8,
+    /// e.g. `APNIC`, `Administered by ARIN` or `Apple Computer Inc.`
+    pub designation: &'static str,
+    /// The month it was given out, as `YYYY-MM`
+    pub date: &'static str,
+    /// The WHOIS server of the RIR responsible for it, if any
+    pub whois: Option<&'static str>,
+    pub status: Slash8Status,
+    /// A name of at most 16 characters, e.g. `RIPE NCC` or `Apple`
+    pub label: &'static str,
+}
+
+impl Slash8Registration {
+    fn parse(line: &'static str) -> Option<Self> {
+        let (prefix, rest) = line.split_once(',')?;
+
+        let mut fields = rest.rsplitn(5, ',');
+        let label = fields.next()?;
+        let status = fields.next()?;
+        let whois = fields.next()?;
+        let date = fields.next()?;
+        let designation = fields.next()?;
+
+        let status = match status {
+            "ALLOCATED" => Slash8Status::Allocated,
+            "LEGACY" => Slash8Status::Legacy,
+            "RESERVED" => Slash8Status::Reserved,
+            _ => return None,
+        };
+
+        Some(Self {
+            slash_8: prefix.strip_suffix("/8")?.parse().ok()?,
+            designation,
+            date,
+            whois: (!whois.is_empty()).then_some(whois),
+            status,
+            label,
+        })
+    }
+
+    /// The RIR responsible for it, which for legacy space is the one administering it
+    pub fn rir(&self) -> Option<Rir> {
+        match self.whois? {
+            "whois.afrinic.net" => Some(Rir::Afrinic),
+            "whois.apnic.net" => Some(Rir::Apnic),
+            "whois.arin.net" => Some(Rir::Arin),
+            "whois.lacnic.net" => Some(Rir::Lacnic),
+            "whois.ripe.net" => Some(Rir::RipeNcc),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the registration of the /8 with the given first octet
+pub fn slash_8_registration(slash_8: u8) -> &'static Slash8Registration {
+    &SLASH_8S[slash_8 as usize]
+}
+
+/// Returns the registration of the /8 a subnet is in, None for the whole internet
+pub fn registration_of(subnet: Subnet) -> Option<&'static Slash8Registration> {
+    match subnet.mask() {
+        SubnetMask::Slash0 => None,
+        _ => Some(slash_8_registration(subnet.octets()[0])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_designations_with_commas() {
+        let registration =
+            Slash8Registration::parse("038/8,PSINet, Inc.,1994-09,,LEGACY,PSINet").unwrap();
+
+        assert_eq!(registration.slash_8, 38);
+        assert_eq!(registration.designation, "PSINet, Inc.");
+        assert_eq!(registration.date, "1994-09");
+        assert_eq!(registration.whois, None);
+        assert_eq!(registration.status, Slash8Status::Legacy);
+        assert_eq!(registration.label, "PSINet");
+
+        assert!(Slash8Registration::parse("038/8,PSINet,1994-09,,UNKNOWN,PSINet").is_none());
+        assert!(Slash8Registration::parse("038/16,PSINet,1994-09,,LEGACY,PSINet").is_none());
+    }
+
+    #[test]
+    fn registers_every_slash_8() {
+        for slash_8 in 0..=255 {
+            assert_eq!(slash_8_registration(slash_8).slash_8, slash_8);
+        }
+
+        assert_eq!(slash_8_registration(2).rir(), Some(Rir::RipeNcc));
+        assert_eq!(slash_8_registration(9).rir(), Some(Rir::Arin));
+        assert_eq!(slash_8_registration(10).status, Slash8Status::Reserved);
+        assert_eq!(slash_8_registration(17).rir(), None);
+
+        assert!(registration_of(Subnet::default()).is_none());
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, ErrorKind, Read},
+    net::Ipv4Addr,
+    sync::Arc,
+    time::Duration,
+};
+
+use nom::{
+    number::complete::{be_u16, be_u32, u8 as be_u8},
+    IResult,
+};
+
+use crate::{
+    ping::{PingResult, UNKNOWN_RTT},
+    stats::Slash16Result,
+    subnet::{Subnet, SubnetMask},
+};
+
+/// The scan outputs that can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// `zmap --probe-module=icmp_echoscan -O csv`, with at least the `saddr` field
+    ZmapCsv,
+    /// `masscan --ping -oL`
+    MasscanList,
+    /// `masscan --ping -oB`
+    MasscanBinary,
+}
+
+impl ImportFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ZmapCsv => "zmap",
+            Self::MasscanList => "masscan-list",
+            Self::MasscanBinary => "masscan-binary",
+        }
+    }
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zmap" => Ok(Self::ZmapCsv),
+            "masscan-list" => Ok(Self::MasscanList),
+            "masscan-binary" => Ok(Self::MasscanBinary),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The size of the pseudo-record masscan starts binary files with
+const MASSCAN_HEADER_SIZE: usize = 99;
+
+/// The results read from a scan output, grouped by /16
+///
+/// zmap only gives RTTs when its output has the send and receive timestamps,
+/// and masscan never does, so other replies are stored with [`UNKNOWN_RTT`],
+/// which stats, maps and exports leave out of anything RTT based
+#[derive(Debug, Default)]
+pub struct ImportSet {
+    slash_16s: BTreeMap<u16, HashMap<u16, PingResult>>,
+    /// The number of records that were read
+    pub records: u64,
+    /// The number of records that were not ICMP echo results
+    pub skipped: u64,
+    /// When the earliest record with a timestamp was received, as a unix timestamp
+    pub scanned_at: Option<u64>,
+}
+
+impl ImportSet {
+    /// Reads every record of a scan output
+    pub fn read(&mut self, format: ImportFormat, reader: impl BufRead) -> std::io::Result<()> {
+        match format {
+            ImportFormat::ZmapCsv => self.read_zmap_csv(reader),
+            ImportFormat::MasscanList => self.read_masscan_list(reader),
+            ImportFormat::MasscanBinary => self.read_masscan_binary(reader),
+        }
+    }
+
+    /// Adds a single result, a success always wins over an earlier failure of the same address
+    pub fn insert(&mut self, address: Ipv4Addr, result: PingResult) {
+        let [a, b, c, d] = address.octets();
+
+        let slash_16 = self
+            .slash_16s
+            .entry(u16::from_be_bytes([a, b]))
+            .or_default();
+
+        let entry = slash_16.entry(u16::from_be_bytes([c, d]));
+
+        match entry {
+            std::collections::hash_map::Entry::Occupied(mut entry) => {
+                if !matches!(entry.get(), PingResult::Success(_)) {
+                    entry.insert(result);
+                }
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(result);
+            }
+        }
+
+        self.records += 1;
+    }
+
+    /// Keeps the earliest time a record was received
+    fn saw_timestamp(&mut self, timestamp: u64) {
+        self.scanned_at = Some(
+            self.scanned_at
+                .map_or(timestamp, |first| first.min(timestamp)),
+        );
+    }
+
+    /// Iterates through every /16 that has at least one record
+    pub fn iter_slash_16s(&self) -> impl Iterator<Item = Subnet> + '_ {
+        self.slash_16s.keys().map(|index| {
+            let [a, b] = index.to_be_bytes();
+            Subnet::new([a, b, 0, 0].into(), SubnetMask::Slash16)
+        })
+    }
+
+    /// Returns whether any address of the given /24 was listed
+    pub fn has_slash_24(&self, slash_24: Subnet) -> bool {
+        let [a, b, c, _] = slash_24.octets();
+
+        self.slash_16s
+            .get(&u16::from_be_bytes([a, b]))
+            .is_some_and(|slash_16| {
+                (0..=255).any(|d| slash_16.contains_key(&u16::from_be_bytes([c, d])))
+            })
+    }
+
+    /// Builds the results of a /16, None if none of it was scanned
+    ///
+    /// `in_scope` decides which /24s were scanned: their listed addresses keep their
+    /// result and every other address timed out. The /24s out of scope keep their
+    /// `existing` results, if any, and are omitted otherwise
+    pub fn slash_16_results(
+        &self,
+        subnet: Subnet,
+        in_scope: impl Fn(Subnet) -> bool,
+        existing: Option<&Slash16Result>,
+    ) -> Option<Slash16Result> {
+        let [a, b, _, _] = subnet.octets();
+        let listed = self.slash_16s.get(&u16::from_be_bytes([a, b]));
+
+        let slash_16: Vec<_> = subnet
+            .iter_subnets()
+            .map(|slash_24| {
+                let c = slash_24.octets()[2];
+
+                if !in_scope(slash_24) {
+                    return existing.and_then(|existing| existing[c as usize].clone());
+                }
+
+                let slash_24: Vec<_> = (0..=255)
+                    .map(|d| {
+                        listed
+                            .and_then(|listed| listed.get(&u16::from_be_bytes([c, d])))
+                            .cloned()
+                            .unwrap_or(PingResult::Timeout)
+                    })
+                    .collect();
+
+                Some(Arc::new(slash_24.try_into().unwrap()))
+            })
+            .collect();
+
+        if slash_16.iter().all(Option::is_none) {
+            return None;
+        }
+
+        Some(Arc::new(slash_16.try_into().unwrap()))
+    }
+
+    fn read_zmap_csv(&mut self, reader: impl BufRead) -> std::io::Result<()> {
+        let mut lines = reader.lines().enumerate();
+
+        /* Find the columns from the header */
+
+        let header = loop {
+            match lines.next() {
+                Some((_, line)) => {
+                    let line = line?;
+
+                    if !line.is_empty() && !line.starts_with('#') {
+                        break line;
+                    }
+                }
+                None => return Ok(()),
+            }
+        };
+
+        let columns: Vec<_> = header.split(',').map(str::trim).collect();
+        let column = |name: &str| columns.iter().position(|column| *column == name);
+
+        let saddr = column("saddr").ok_or_else(|| invalid_data("no saddr column".to_string()))?;
+        let success = column("success");
+        let sent = column("sent_timestamp_ts").zip(column("sent_timestamp_us"));
+        let received = column("timestamp_ts")
+            .zip(column("timestamp_us"))
+            .or(column("dtime_ts").zip(column("dtime_us")));
+        let timestamp = received.or(sent).map(|(seconds, _)| seconds);
+
+        /* Read the records */
+
+        for (i, line) in lines {
+            let line = line?;
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<_> = line.split(',').map(str::trim).collect();
+            let field = |index: usize| {
+                fields
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| invalid_data(format!("line {}: missing fields", i + 1)))
+            };
+
+            let address: Ipv4Addr = field(saddr)?
+                .parse()
+                .map_err(|_| invalid_data(format!("line {}: invalid saddr", i + 1)))?;
+
+            if let Some(timestamp) = timestamp {
+                if let Ok(timestamp) = field(timestamp)?.parse() {
+                    self.saw_timestamp(timestamp);
+                }
+            }
+
+            let succeeded = match success {
+                Some(success) => matches!(field(success)?, "1" | "true"),
+                None => true,
+            };
+
+            if !succeeded {
+                self.insert(address, PingResult::Error);
+                continue;
+            }
+
+            let result = match (sent, received) {
+                (Some((sent_s, sent_us)), Some((received_s, received_us))) => {
+                    let micros = |s: usize, us: usize| -> std::io::Result<u64> {
+                        let s: u64 = field(s)?.parse().unwrap_or(0);
+                        let us: u64 = field(us)?.parse().unwrap_or(0);
+                        Ok(s * 1_000_000 + us)
+                    };
+
+                    let sent = micros(sent_s, sent_us)?;
+                    let received = micros(received_s, received_us)?;
+
+                    success_with_rtt(Duration::from_micros(received.saturating_sub(sent)))
+                }
+                _ => PingResult::Success(UNKNOWN_RTT),
+            };
+
+            self.insert(address, result);
+        }
+
+        Ok(())
+    }
+
+    /// Reads `<status> <protocol> <port> <address> <timestamp>` lines, which
+    /// have no RTT
+    fn read_masscan_list(&mut self, reader: impl BufRead) -> std::io::Result<()> {
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<_> = line.split_whitespace().collect();
+
+            let [status, protocol, _, address, rest @ ..] = fields.as_slice() else {
+                return Err(invalid_data(format!("line {}: missing fields", i + 1)));
+            };
+
+            if *protocol != "icmp" {
+                self.skipped += 1;
+                continue;
+            }
+
+            let address: Ipv4Addr = address
+                .parse()
+                .map_err(|_| invalid_data(format!("line {}: invalid address", i + 1)))?;
+
+            if let Some(Ok(timestamp)) = rest.first().map(|timestamp| timestamp.parse()) {
+                self.saw_timestamp(timestamp);
+            }
+
+            let result = match *status {
+                "open" => PingResult::Success(UNKNOWN_RTT),
+                _ => PingResult::Error,
+            };
+
+            self.insert(address, result);
+        }
+
+        Ok(())
+    }
+
+    /// Reads a pseudo-record header then `<type> <length> <data>` records, see
+    /// masscan's `in-binary.c`, which have no RTT either
+    fn read_masscan_binary(&mut self, mut reader: impl BufRead) -> std::io::Result<()> {
+        let mut header = [0; MASSCAN_HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+
+        if !header.starts_with(b"masscan/1.1") {
+            return Err(invalid_data("not a masscan binary file".to_string()));
+        }
+
+        let mut data = Vec::new();
+
+        loop {
+            let record_type = match read_varint(&mut reader) {
+                Ok(record_type) => record_type,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+
+            let length = read_varint(&mut reader)?;
+
+            data.resize(length as usize, 0);
+            reader.read_exact(&mut data)?;
+
+            let open = match record_type {
+                // Status records with an IP protocol, older ones without can't be told apart from TCP
+                6 => true,
+                7 => false,
+                // The file header record marks the end of the results
+                0x6d => break,
+                _ => {
+                    self.skipped += 1;
+                    continue;
+                }
+            };
+
+            let (_, (timestamp, address, protocol)) = parse_masscan_status(&data)
+                .map_err(|_| invalid_data("invalid masscan status record".to_string()))?;
+
+            if protocol != 1 {
+                self.skipped += 1;
+                continue;
+            }
+
+            self.saw_timestamp(timestamp as u64);
+
+            let result = match open {
+                true => PingResult::Success(UNKNOWN_RTT),
+                false => PingResult::Error,
+            };
+
+            self.insert(address, result);
+        }
+
+        Ok(())
+    }
+}
+
+/// Stored RTTs are whole milliseconds that have to fit in 2 bytes, short of
+/// the one that means unknown
+fn success_with_rtt(rtt: Duration) -> PingResult {
+    PingResult::Success(rtt.min(UNKNOWN_RTT - Duration::from_millis(1)))
+}
+
+fn read_varint(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut value = 0;
+    let mut byte = [0x80];
+
+    while byte[0] & 0x80 != 0 {
+        reader.read_exact(&mut byte)?;
+        value = (value << 7) | (byte[0] & 0x7f) as u64;
+    }
+
+    Ok(value)
+}
+
+/// Parses a timestamp, an address, an IP protocol, a port, a reason and a TTL
+fn parse_masscan_status(input: &[u8]) -> IResult<&[u8], (u32, Ipv4Addr, u8)> {
+    let (input, timestamp) = be_u32(input)?;
+    let (input, address) = be_u32(input)?;
+    let (input, protocol) = be_u8(input)?;
+    let (input, _port) = be_u16(input)?;
+    let (input, _reason) = be_u8(input)?;
+    let (input, _ttl) = be_u8(input)?;
+
+    Ok((input, (timestamp, address.into(), protocol)))
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(format: ImportFormat, input: &[u8]) -> std::io::Result<ImportSet> {
+        let mut set = ImportSet::default();
+        set.read(format, input)?;
+
+        Ok(set)
+    }
+
+    fn result(set: &ImportSet, address: &str) -> Option<PingResult> {
+        let [a, b, c, d] = address.parse::<Ipv4Addr>().unwrap().octets();
+
+        set.slash_16s
+            .get(&u16::from_be_bytes([a, b]))?
+            .get(&u16::from_be_bytes([c, d]))
+            .cloned()
+    }
+
+    #[test]
+    fn reads_zmap_csv() {
+        let csv = "\
+saddr,sent_timestamp_ts,sent_timestamp_us,timestamp_ts,timestamp_us,success
+10.0.0.1,1700000010,500000,1700000010,512000,1
+10.0.0.2,1700000005,0,1700000005,0,0
+10.0.0.1,1700000020,0,1700000021,0,1
+10.0.1.3,1700000030,0,1700000100,0,true
+";
+
+        let set = read(ImportFormat::ZmapCsv, csv.as_bytes()).unwrap();
+
+        assert_eq!(set.records, 4);
+        assert_eq!(set.scanned_at, Some(1_700_000_005));
+        assert_eq!(
+            result(&set, "10.0.0.1"),
+            Some(PingResult::Success(Duration::from_millis(12)))
+        );
+        assert_eq!(result(&set, "10.0.0.2"), Some(PingResult::Error));
+        assert_eq!(
+            result(&set, "10.0.1.3"),
+            Some(success_with_rtt(Duration::from_secs(70)))
+        );
+
+        /* Without timestamps, replies have an unknown RTT */
+
+        let set = read(ImportFormat::ZmapCsv, b"# zmap\nsaddr\n10.0.0.1\n").unwrap();
+
+        assert_eq!(set.scanned_at, None);
+        assert_eq!(
+            result(&set, "10.0.0.1"),
+            Some(PingResult::Success(UNKNOWN_RTT))
+        );
+
+        let error = read(ImportFormat::ZmapCsv, b"daddr\n10.0.0.1\n").unwrap_err();
+        assert_eq!(error.to_string(), "no saddr column");
+
+        let error = read(ImportFormat::ZmapCsv, b"saddr\n10.0.0\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: invalid saddr");
+    }
+
+    #[test]
+    fn reads_masscan_lists() {
+        let list = "\
+#masscan
+open icmp 0 10.0.0.1 1700000100
+open tcp 80 10.0.0.2 1700000050
+closed icmp 0 10.0.0.3 1700000200
+# end
+";
+
+        let set = read(ImportFormat::MasscanList, list.as_bytes()).unwrap();
+
+        assert_eq!((set.records, set.skipped), (2, 1));
+        assert_eq!(set.scanned_at, Some(1_700_000_100));
+        assert_eq!(
+            result(&set, "10.0.0.1"),
+            Some(PingResult::Success(UNKNOWN_RTT))
+        );
+        assert_eq!(result(&set, "10.0.0.2"), None);
+        assert_eq!(result(&set, "10.0.0.3"), Some(PingResult::Error));
+
+        let error = read(ImportFormat::MasscanList, b"open icmp 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: missing fields");
+    }
+
+    fn masscan_status(record_type: u8, timestamp: u32, address: [u8; 4], protocol: u8) -> Vec<u8> {
+        let mut record = vec![record_type, 13];
+        record.extend_from_slice(&timestamp.to_be_bytes());
+        record.extend_from_slice(&address);
+        record.extend_from_slice(&[protocol, 0, 0, 0, 64]);
+        record
+    }
+
+    #[test]
+    fn reads_masscan_binaries() {
+        let mut binary = b"masscan/1.1".to_vec();
+        binary.resize(MASSCAN_HEADER_SIZE, 0);
+
+        binary.extend(masscan_status(6, 1_700_000_300, [10, 0, 0, 1], 1));
+        binary.extend(masscan_status(7, 1_700_000_200, [10, 0, 0, 2], 1));
+        binary.extend(masscan_status(6, 1_700_000_100, [10, 0, 0, 3], 6));
+        // A record of another type, with a length that takes two bytes
+        binary.extend_from_slice(&[5, 0x81, 0x00]);
+        binary.extend_from_slice(&[0; 128]);
+        binary.extend(masscan_status(0x6d, 0, [0; 4], 0));
+        binary.extend(masscan_status(6, 1_700_000_000, [10, 0, 0, 4], 1));
+
+        let set = read(ImportFormat::MasscanBinary, &binary).unwrap();
+
+        assert_eq!((set.records, set.skipped), (2, 2));
+        assert_eq!(set.scanned_at, Some(1_700_000_200));
+        assert_eq!(
+            result(&set, "10.0.0.1"),
+            Some(PingResult::Success(UNKNOWN_RTT))
+        );
+        assert_eq!(result(&set, "10.0.0.2"), Some(PingResult::Error));
+        assert_eq!(result(&set, "10.0.0.4"), None);
+
+        let error = read(ImportFormat::MasscanBinary, &[0; MASSCAN_HEADER_SIZE]).unwrap_err();
+        assert_eq!(error.to_string(), "not a masscan binary file");
+    }
+
+    #[test]
+    fn builds_slash_16s_within_the_scope() {
+        let mut set = ImportSet::default();
+        set.insert(Ipv4Addr::new(10, 0, 1, 1), PingResult::Success(UNKNOWN_RTT));
+        set.insert(Ipv4Addr::new(10, 0, 1, 1), PingResult::Error);
+        set.insert(Ipv4Addr::new(10, 0, 2, 1), PingResult::Error);
+
+        let slash_16 = "10.0.x.x".parse().ok().unwrap();
+        let scope: Subnet = "10.0.1.x".parse().ok().unwrap();
+        let in_scope = |slash_24: Subnet| scope.contains(*slash_24);
+
+        let results = set.slash_16_results(slash_16, in_scope, None).unwrap();
+
+        let slash_24 = results[1].as_ref().unwrap();
+        assert_eq!(slash_24[1], PingResult::Success(UNKNOWN_RTT));
+        assert_eq!(slash_24[2], PingResult::Timeout);
+        assert_eq!(results.iter().flatten().count(), 1);
+
+        /* Existing /24s outside the scope are kept, those inside replaced */
+
+        let existing = set
+            .slash_16_results(slash_16, |slash_24| slash_24.octets()[2] < 3, None)
+            .unwrap();
+        let mut empty = ImportSet::default();
+        empty.insert(Ipv4Addr::new(10, 0, 5, 5), PingResult::Error);
+
+        let merged = empty
+            .slash_16_results(slash_16, in_scope, Some(&existing))
+            .unwrap();
+
+        assert_eq!(merged[0], existing[0]);
+        assert_eq!(merged[2], existing[2]);
+        assert!(merged[1]
+            .as_ref()
+            .unwrap()
+            .iter()
+            .all(|result| *result == PingResult::Timeout));
+        assert_eq!(merged.iter().flatten().count(), 3);
+    }
+}
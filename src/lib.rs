@@ -1,8 +1,21 @@
 #![feature(const_async_blocks)]
 #![feature(type_alias_impl_trait)]
 
+pub mod attribution;
+pub mod delegation;
+pub mod diff;
+pub mod export;
 pub mod file;
+pub mod font;
+pub mod geoip;
 pub mod gui;
+pub mod iana;
+pub mod import;
 pub mod ping;
+pub mod progress;
+pub mod render;
+pub mod report;
+pub mod routing;
 pub mod stats;
 pub mod subnet;
+pub mod tiles;
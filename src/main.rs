@@ -8,14 +8,18 @@ use std::{
 use futures::future::join_all;
 
 use ping_the_internet::{
-    file::{read_slash_16, save_slash_16},
+    file::{
+        default_data_root, read_slash_16, save_slash_16, summary::cached_slash_16_summary,
+        StorageError,
+    },
     gui::{
         self, Slash16State, Slash32State, CURRENT_START_TIME, PENDING_SLASH_16, SLASH_16_STATES,
         SLASH_32_STATES,
     },
     ping::{ping, PingResult},
     stats::{
-        print_stats_table_header, print_stats_table_row, Analysis, Slash16Result, SubnetResults,
+        print_stats_table_header, print_stats_table_message, print_stats_table_row, Analysis,
+        Slash16Result, SubnetResults,
     },
     subnet::{Subnet, SubnetMask},
 };
@@ -86,7 +90,7 @@ async fn pinger_main() -> Result<(), Box<dyn Error>> {
                     states[state_i][state_j] = Slash16State::Completed;
                 }
             } else {
-                println!("| {:>13} | {:^57} |", format!("{slash_16}"), "Skipped");
+                print_stats_table_message(slash_16, "Skipped", true);
 
                 {
                     let mut states = SLASH_16_STATES.lock().unwrap();
@@ -99,13 +103,26 @@ async fn pinger_main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-async fn ping_slash_16(slash_16: Subnet) -> Result<Option<Slash16Result>, std::io::Error> {
+async fn ping_slash_16(slash_16: Subnet) -> Result<Option<Slash16Result>, StorageError> {
     assert_eq!(slash_16.mask(), SubnetMask::Slash16);
 
-    if read_slash_16(slash_16).await.unwrap().is_some() {
+    /* Skip /16s which have already been scanned, re-scanning any that were damaged */
+
+    if cached_slash_16_summary(&default_data_root(), slash_16)?.is_some() {
         return Ok(None);
     }
 
+    match read_slash_16(slash_16).await {
+        Ok(Some(_)) => return Ok(None),
+        Ok(None) => {}
+        Err(e) if e.is_damaged() => eprintln!("Re-scanning {slash_16}: {e}"),
+        Err(StorageError::UnsupportedVersion(version)) => {
+            eprintln!("Skipping {slash_16}: written by a newer format version ({version})");
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    }
+
     {
         let mut states = SLASH_32_STATES.lock().unwrap();
         *states = [[Slash32State::Scheduled; 256]; 256];
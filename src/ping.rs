@@ -19,14 +19,27 @@ use crate::gui::{Slash32State, SLASH_32_STATES};
 
 pub static PING_PERMITS: Semaphore = Semaphore::const_new(1024);
 
+/// Stored in place of the RTT of a reply whose RTT was never measured, e.g. one
+/// imported from masscan, as the largest RTT a /16 file can hold
+pub const UNKNOWN_RTT: Duration = Duration::from_millis(u16::MAX as u64);
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum PingResult {
+    /// A reply, with its RTT or [`UNKNOWN_RTT`]
     Success(Duration),
     Timeout,
     Error,
 }
 
 impl PingResult {
+    /// The RTT of a reply, None for failures and for replies of unknown RTT
+    pub fn rtt(&self) -> Option<Duration> {
+        match self {
+            Self::Success(time) if *time != UNKNOWN_RTT => Some(*time),
+            _ => None,
+        }
+    }
+
     pub async fn serialize_into<W: AsyncWrite + Unpin>(
         &self,
         mut w: W,
@@ -118,7 +131,7 @@ pub async fn ping(address: Ipv4Addr) -> PingResult {
         drop(permit);
 
         let state = match result {
-            PingResult::Success(_) => Slash32State::Success,
+            PingResult::Success(time) => Slash32State::Success(time),
             PingResult::Timeout => Slash32State::Timeout,
             PingResult::Error => Slash32State::Error,
         };
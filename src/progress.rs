@@ -0,0 +1,85 @@
+use std::{
+    io::{IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+/// How often the progress line is redrawn
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Reports the throughput and ETA of a long pass over many /16s on stderr
+///
+/// On a terminal the report is a single line redrawn in place, which has to be
+/// cleared with [`Progress::clear`] before printing anything else
+pub struct Progress {
+    what: &'static str,
+    total: u64,
+    done: u64,
+    start_time: Instant,
+    last_report: Instant,
+    terminal: bool,
+}
+
+impl Progress {
+    pub fn new(what: &'static str, total: u64) -> Self {
+        Self {
+            what,
+            total,
+            done: 0,
+            start_time: Instant::now(),
+            last_report: Instant::now(),
+            terminal: std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Counts finished items, reporting if it has been a while
+    pub fn tick(&mut self, count: u64) {
+        self.done += count;
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+        }
+    }
+
+    /// Erases the progress line so a row can be printed
+    pub fn clear(&self) {
+        if self.terminal {
+            eprint!("\r\x1b[2K");
+        }
+    }
+
+    /// Reports one last time
+    pub fn finish(mut self) {
+        self.report();
+
+        if self.terminal {
+            eprintln!();
+        }
+    }
+
+    fn report(&mut self) {
+        self.last_report = Instant::now();
+
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let rate = self.done as f64 / elapsed.max(f64::EPSILON);
+        let eta = Duration::try_from_secs_f64(self.total.saturating_sub(self.done) as f64 / rate)
+            .map_or_else(|_| "?".to_string(), |eta| format!("{eta:.0?}"));
+
+        let line = format!(
+            "{}/{} {} ({:.2}%) | {:.1} {}/s | ETA {}",
+            self.done,
+            self.total,
+            self.what,
+            self.done as f64 / self.total.max(1) as f64 * 100.0,
+            rate,
+            self.what,
+            eta,
+        );
+
+        match self.terminal {
+            true => eprint!("\r\x1b[2K{line}"),
+            false => eprintln!("{line}"),
+        }
+
+        std::io::stderr().flush().ok();
+    }
+}
@@ -0,0 +1,734 @@
+use std::{str::FromStr, time::Duration};
+
+use image::{Rgb, RgbImage};
+
+use crate::{
+    diff::{AddressDiff, Slash16Diff},
+    file::summary::Slash16Summary,
+    font::{draw_label, draw_text, text_width, GLYPH_HEIGHT},
+    iana::slash_8_registration,
+    ping::PingResult,
+    stats::Slash16Result,
+    subnet::{Subnet, SubnetMask},
+};
+
+/// The largest side of a map rendered as a single image, bigger maps have to be tiled
+pub const MAX_MAP_SIZE: u32 = 4096;
+
+/// The colours maps are drawn with
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    /// Space with no results
+    pub unscanned: Rgb<u8>,
+    pub success: Rgb<u8>,
+    pub timeout: Rgb<u8>,
+    pub error: Rgb<u8>,
+    /// Scanned space where nothing replied, fading towards `success` as more of it does
+    pub dead: Rgb<u8>,
+    /// An address that replied in both runs of a diff
+    pub still_alive: Rgb<u8>,
+}
+
+impl Palette {
+    pub const DEFAULT: Self = Self {
+        unscanned: Rgb([0x50, 0x50, 0x50]),
+        success: Rgb([0x40, 0xFF, 0x40]),
+        timeout: Rgb([0xA3, 0xB3, 0xC0]),
+        error: Rgb([0xFF, 0x50, 0x50]),
+        dead: Rgb([0x10, 0x10, 0x10]),
+        still_alive: Rgb([0x40, 0x80, 0xFF]),
+    };
+
+    /// Dark to light greys, for printing
+    pub const GRAYSCALE: Self = Self {
+        unscanned: Rgb([0x00, 0x00, 0x00]),
+        success: Rgb([0xFF, 0xFF, 0xFF]),
+        timeout: Rgb([0x60, 0x60, 0x60]),
+        error: Rgb([0xA0, 0xA0, 0xA0]),
+        dead: Rgb([0x30, 0x30, 0x30]),
+        still_alive: Rgb([0xD0, 0xD0, 0xD0]),
+    };
+
+    /// Blue and orange from the Okabe-Ito palette, which stay apart for red-green colour blindness
+    pub const COLORBLIND: Self = Self {
+        unscanned: Rgb([0x50, 0x50, 0x50]),
+        success: Rgb([0x56, 0xB4, 0xE9]),
+        timeout: Rgb([0xA0, 0xA0, 0xA0]),
+        error: Rgb([0xE6, 0x9F, 0x00]),
+        dead: Rgb([0x10, 0x10, 0x10]),
+        still_alive: Rgb([0xF0, 0xE4, 0x42]),
+    };
+
+    /// Dark colours on white, for slides and paper
+    pub const LIGHT: Self = Self {
+        unscanned: Rgb([0xFF, 0xFF, 0xFF]),
+        success: Rgb([0x1A, 0x7F, 0x1A]),
+        timeout: Rgb([0xC8, 0xD0, 0xD8]),
+        error: Rgb([0xD0, 0x30, 0x30]),
+        dead: Rgb([0xE4, 0xE4, 0xE4]),
+        still_alive: Rgb([0x20, 0x50, 0xC0]),
+    };
+
+    /// The colour of a single address
+    pub fn address_color(&self, result: &PingResult) -> Rgb<u8> {
+        match result {
+            PingResult::Success(_) => self.success,
+            PingResult::Timeout => self.timeout,
+            PingResult::Error => self.error,
+        }
+    }
+
+    /// The colour of an address in a diff
+    pub fn diff_color(&self, diff: &AddressDiff) -> Rgb<u8> {
+        match diff {
+            AddressDiff::Appeared => self.success,
+            AddressDiff::Disappeared => self.error,
+            AddressDiff::StillAlive(_) => self.still_alive,
+            AddressDiff::StillDown => self.timeout,
+        }
+    }
+
+    /// The colour of a block of addresses, brighter the more of it replied
+    ///
+    /// The scale is logarithmic so the many /24s with only a handful of hosts
+    /// still stand out
+    pub fn density_color(&self, alive: u64, scanned: u64) -> Rgb<u8> {
+        if scanned == 0 {
+            return self.unscanned;
+        }
+
+        let alive_per_slash_24 = alive as f32 / scanned as f32 * 256.0;
+        let intensity = alive_per_slash_24.ln_1p() / 256f32.ln_1p();
+
+        lerp(self.dead, self.success, intensity)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl FromStr for Palette {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::DEFAULT),
+            "grayscale" => Ok(Self::GRAYSCALE),
+            "colorblind" => Ok(Self::COLORBLIND),
+            "light" => Ok(Self::LIGHT),
+            _ => Err(()),
+        }
+    }
+}
+
+pub(crate) fn lerp(from: Rgb<u8>, to: Rgb<u8>, t: f32) -> Rgb<u8> {
+    let t = t.clamp(0.0, 1.0);
+
+    Rgb(std::array::from_fn(|i| {
+        (from[i] as f32 + (to[i] as f32 - from[i] as f32) * t).round() as u8
+    }))
+}
+
+/// How RTTs are spread along the colour scale
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RttScale {
+    Linear,
+    /// Every tenfold increase gets the same share of the scale, so nearby and
+    /// far away hosts can be told apart on the same map
+    #[default]
+    Log,
+}
+
+impl FromStr for RttScale {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "log" => Ok(Self::Log),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The viridis colour scale at 8 evenly spaced points, which gets steadily
+/// lighter so it still reads in greyscale and with colour blindness
+const VIRIDIS: [[u8; 3]; 8] = [
+    [0x44, 0x01, 0x54],
+    [0x46, 0x33, 0x7E],
+    [0x36, 0x5C, 0x8D],
+    [0x27, 0x7F, 0x8E],
+    [0x1F, 0xA1, 0x87],
+    [0x4A, 0xC1, 0x6D],
+    [0x9F, 0xDA, 0x3A],
+    [0xFD, 0xE7, 0x25],
+];
+
+/// The colour at a fraction of the viridis scale
+pub fn viridis(t: f32) -> Rgb<u8> {
+    let t = t.clamp(0.0, 1.0) * (VIRIDIS.len() - 1) as f32;
+    let i = (t as usize).min(VIRIDIS.len() - 2);
+
+    lerp(Rgb(VIRIDIS[i]), Rgb(VIRIDIS[i + 1]), t - i as f32)
+}
+
+/// Colours RTTs along the viridis scale, from dark purple for the fastest to
+/// yellow for the slowest
+#[derive(Debug, Clone, Copy)]
+pub struct RttColoring {
+    pub scale: RttScale,
+    /// Faster RTTs get the same colour as this one
+    pub min: Duration,
+    /// Slower RTTs get the same colour as this one
+    pub max: Duration,
+}
+
+impl RttColoring {
+    pub fn new(scale: RttScale, min: Duration, max: Duration) -> Self {
+        assert!(min < max, "The RTT range must not be empty");
+        assert!(
+            scale == RttScale::Linear || !min.is_zero(),
+            "A log scale cannot start at 0ms"
+        );
+
+        Self { scale, min, max }
+    }
+
+    /// Where an RTT falls on the scale, from 0 at `min` to 1 at `max`
+    pub fn fraction(&self, rtt: Duration) -> f32 {
+        let rtt = rtt.clamp(self.min, self.max).as_secs_f64();
+        let min = self.min.as_secs_f64();
+        let max = self.max.as_secs_f64();
+
+        let fraction = match self.scale {
+            RttScale::Linear => (rtt - min) / (max - min),
+            RttScale::Log => (rtt / min).ln() / (max / min).ln(),
+        };
+
+        fraction as f32
+    }
+
+    pub fn color(&self, rtt: Duration) -> Rgb<u8> {
+        viridis(self.fraction(rtt))
+    }
+
+    /// Round RTTs to mark on a legend, at most `max_ticks` of them
+    ///
+    /// A log scale gets 1, 2 and 5 of every power of ten in range, or only the
+    /// powers of ten when that would be too many
+    pub fn ticks(&self, max_ticks: usize) -> Vec<Duration> {
+        let min = self.min.as_secs_f64() * 1000.0;
+        let max = self.max.as_secs_f64() * 1000.0;
+
+        /* Leeway for the rounding of the steps */
+
+        let in_range = |ms: f64| ms >= min * (1.0 - 1e-9) && ms <= max * (1.0 + 1e-9);
+
+        let ticks: Vec<f64> = match self.scale {
+            RttScale::Log => {
+                let first_decade = min.log10().floor() as i32;
+                let last_decade = max.log10().floor() as i32;
+
+                let ticks_with = |multiples: &[f64]| -> Vec<f64> {
+                    (first_decade..=last_decade)
+                        .flat_map(|decade| {
+                            multiples
+                                .iter()
+                                .map(move |multiple| multiple * 10f64.powi(decade))
+                        })
+                        .filter(|ms| in_range(*ms))
+                        .collect()
+                };
+
+                let ticks = ticks_with(&[1.0, 2.0, 5.0]);
+
+                match ticks.len() <= max_ticks {
+                    true => ticks,
+                    false => ticks_with(&[1.0]),
+                }
+            }
+            RttScale::Linear => {
+                let rough_step = (max - min) / max_ticks.max(1) as f64;
+                let magnitude = 10f64.powf(rough_step.log10().floor());
+                let step = [1.0, 2.0, 5.0, 10.0]
+                    .into_iter()
+                    .map(|multiple| multiple * magnitude)
+                    .find(|step| *step >= rough_step)
+                    .unwrap();
+
+                let first = (min / step).ceil() as u64;
+
+                (first..)
+                    .map(|i| i as f64 * step)
+                    .take_while(|ms| *ms <= max * (1.0 + 1e-9))
+                    .filter(|ms| in_range(*ms))
+                    .collect()
+            }
+        };
+
+        ticks
+            .into_iter()
+            .map(|ms| Duration::from_micros((ms * 1000.0).round() as u64))
+            .collect()
+    }
+}
+
+impl Default for RttColoring {
+    fn default() -> Self {
+        Self::new(
+            RttScale::Log,
+            Duration::from_millis(1),
+            Duration::from_millis(1000),
+        )
+    }
+}
+
+/// What a map shows and in which colours
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MapStyle {
+    pub palette: Palette,
+    /// Colours what replied by its RTT rather than by how much of it replied
+    pub rtt: Option<RttColoring>,
+}
+
+impl MapStyle {
+    /// The colour of a single address
+    pub fn address_color(&self, result: &PingResult) -> Rgb<u8> {
+        match (self.rtt, result) {
+            (Some(rtt), PingResult::Success(_)) => match result.rtt() {
+                Some(time) => rtt.color(time),
+                None => self.palette.success,
+            },
+            (Some(_), _) => self.palette.dead,
+            (None, result) => self.palette.address_color(result),
+        }
+    }
+
+    /// The colour of a block of addresses, from its counts and the median RTT of what replied
+    pub fn block_color(&self, alive: u64, scanned: u64, median_rtt: Option<Duration>) -> Rgb<u8> {
+        match (self.rtt, median_rtt) {
+            _ if scanned == 0 => self.palette.unscanned,
+            (Some(rtt), Some(median_rtt)) => rtt.color(median_rtt),
+            (Some(_), None) if alive != 0 => self.palette.success,
+            (Some(_), None) => self.palette.dead,
+            (None, _) => self.palette.density_color(alive, scanned),
+        }
+    }
+}
+
+/// The space filling curve addresses are laid out along
+///
+/// Both visit every subnet as a whole square, so a subnet looks the same on its
+/// own map as on the map of the whole internet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Curve {
+    /// Neighbouring subnets are always next to each other
+    #[default]
+    Hilbert,
+    /// The bits of the index interleaved into x and y, with jumps between quadrants
+    ZOrder,
+}
+
+impl Curve {
+    /// Where the nth cell of a square of the given side is, as (x, y)
+    pub fn position(&self, index: u64, side: u64) -> (u32, u32) {
+        match self {
+            Self::Hilbert => {
+                let (x, y) = hilbert_curve::convert_1d_to_2d(index as usize, side as usize);
+
+                (x as u32, y as u32)
+            }
+            Self::ZOrder => {
+                let mut x = 0;
+                let mut y = 0;
+
+                for bit in 0..32 {
+                    x |= ((index >> (2 * bit)) & 1) << bit;
+                    y |= ((index >> (2 * bit + 1)) & 1) << bit;
+                }
+
+                (x as u32, y as u32)
+            }
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hilbert" => Ok(Self::Hilbert),
+            "z-order" => Ok(Self::ZOrder),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a subnet is drawn, with one pixel per subnet of the `pixel` mask
+#[derive(Debug, Clone, Copy)]
+pub struct MapLayout {
+    pub subnet: Subnet,
+    /// /16, /24 or /32
+    pub pixel: SubnetMask,
+    pub curve: Curve,
+}
+
+impl MapLayout {
+    pub fn new(subnet: Subnet, pixel: SubnetMask, curve: Curve) -> Self {
+        assert!(
+            matches!(
+                pixel,
+                SubnetMask::Slash16 | SubnetMask::Slash24 | SubnetMask::Slash32
+            ),
+            "Maps have a pixel per /16, /24 or /32"
+        );
+        assert!(
+            pixel.prefix_length() >= subnet.mask().prefix_length(),
+            "A pixel cannot be bigger than the subnet"
+        );
+
+        Self {
+            subnet,
+            pixel,
+            curve,
+        }
+    }
+
+    /// The finest pixels a subnet can have without its map being bigger than [`MAX_MAP_SIZE`]
+    pub fn finest(subnet: Subnet, curve: Curve) -> Self {
+        let pixel = match subnet.mask() {
+            SubnetMask::Slash0 => SubnetMask::Slash24,
+            _ => SubnetMask::Slash32,
+        };
+
+        Self::new(subnet, pixel, curve)
+    }
+
+    /// The side of the map in pixels
+    pub fn side(&self) -> u32 {
+        1 << ((self.pixel.prefix_length() - self.subnet.mask().prefix_length()) / 2)
+    }
+
+    /// Where the pixel of an address is on the map
+    ///
+    /// This is its place on the map of the whole internet, wrapped around the
+    /// side of the subnet's square
+    pub fn position(&self, address: u32) -> (u32, u32) {
+        let bits = self.pixel.prefix_length() as u32;
+        let index = (address as u64) >> (32 - bits);
+        let (x, y) = self.curve.position(index, 1 << (bits / 2));
+
+        (x % self.side(), y % self.side())
+    }
+
+    /// Where the subnet's square is on the map of the whole internet, in
+    /// multiples of its side, as (column, row)
+    pub fn tile_position(&self) -> (u32, u32) {
+        let bits = self.subnet.mask().prefix_length() as u32;
+        let index = u32::from(self.subnet.base_address()) as u64 >> (32 - bits);
+
+        self.curve.position(index, 1 << (bits / 2))
+    }
+
+    /// Creates the map, all unscanned
+    pub fn new_map(&self, style: &MapStyle) -> RgbImage {
+        RgbImage::from_pixel(self.side(), self.side(), style.palette.unscanned)
+    }
+
+    /// Draws the summary of a /16 on a map with a pixel per /16 or /24, leaving
+    /// out the /24s that fall outside of the subnet
+    pub fn draw_slash_16_summary(
+        &self,
+        map: &mut RgbImage,
+        style: &MapStyle,
+        subnet: Subnet,
+        summary: &Slash16Summary,
+    ) {
+        let first = u32::from(subnet.base_address());
+
+        match self.pixel {
+            SubnetMask::Slash16 => {
+                let anal = &summary.analysis;
+                let (x, y) = self.position(first);
+
+                let color = style.block_color(anal.alive, anal.scanned(), anal.rtt.median());
+
+                map.put_pixel(x, y, color);
+            }
+            SubnetMask::Slash24 => {
+                for (c, slash_24) in summary.slash_24s.iter().enumerate() {
+                    let address = first + c as u32 * 256;
+
+                    if !self.subnet.contains(address.into()) {
+                        continue;
+                    }
+
+                    let (x, y) = self.position(address);
+
+                    let color = match slash_24 {
+                        Some(slash_24) => {
+                            let scanned = slash_24.alive + slash_24.timed_out + slash_24.errored;
+
+                            style.block_color(
+                                slash_24.alive as u64,
+                                scanned as u64,
+                                slash_24.median_rtt,
+                            )
+                        }
+                        None => style.palette.unscanned,
+                    };
+
+                    map.put_pixel(x, y, color);
+                }
+            }
+            _ => panic!("Summaries can only be drawn a pixel per /16 or /24"),
+        }
+    }
+
+    /// Draws the addresses of a /16 on a map with a pixel per /32, leaving out
+    /// what falls outside of the subnet
+    pub fn draw_slash_16_addresses(
+        &self,
+        map: &mut RgbImage,
+        style: &MapStyle,
+        subnet: Subnet,
+        results: &Slash16Result,
+    ) {
+        self.draw_slash_16_cells(map, subnet, results.iter(), |result| {
+            style.address_color(result)
+        });
+    }
+
+    /// Draws a /16 of a diff on a map with a pixel per /32
+    pub fn draw_slash_16_diff(
+        &self,
+        map: &mut RgbImage,
+        style: &MapStyle,
+        subnet: Subnet,
+        diff: &Slash16Diff,
+    ) {
+        self.draw_slash_16_cells(map, subnet, diff.iter(), |diff| {
+            style.palette.diff_color(diff)
+        });
+    }
+
+    fn draw_slash_16_cells<'a, T: 'a>(
+        &self,
+        map: &mut RgbImage,
+        subnet: Subnet,
+        slash_24s: impl Iterator<Item = &'a Option<std::sync::Arc<[T; 256]>>>,
+        color: impl Fn(&T) -> Rgb<u8>,
+    ) {
+        assert_eq!(
+            self.pixel,
+            SubnetMask::Slash32,
+            "Addresses can only be drawn a pixel per /32"
+        );
+
+        let first = u32::from(subnet.base_address());
+
+        for (c, slash_24) in slash_24s.enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            for (d, cell) in slash_24.iter().enumerate() {
+                let address = first + c as u32 * 256 + d as u32;
+
+                if !self.subnet.contains(address.into()) {
+                    continue;
+                }
+
+                let (x, y) = self.position(address);
+
+                map.put_pixel(x, y, color(cell));
+            }
+        }
+    }
+
+    /// Labels the map with who IANA gave the space to, every /8 for the whole
+    /// internet and otherwise the /8 the subnet is in
+    pub fn draw_labels(&self, map: &mut RgbImage) {
+        match self.subnet.mask() {
+            SubnetMask::Slash0 => {
+                let block_size = self.side() / 16;
+
+                for a in 0..=u8::MAX {
+                    let (x, y) = self.curve.position(a as u64, 16);
+
+                    draw_slash_8_label(
+                        map,
+                        a,
+                        x * block_size + block_size / 2,
+                        y * block_size + block_size / 2,
+                        block_size,
+                    );
+                }
+            }
+            _ => {
+                let center = self.side() / 2;
+
+                draw_slash_8_label(map, self.subnet.octets()[0], center, center, self.side());
+            }
+        }
+    }
+}
+
+/// Draws the number and IANA label of a /8 centred on (x, y), as big as fits in a square of the given size
+fn draw_slash_8_label(map: &mut RgbImage, slash_8: u8, x: u32, y: u32, size: u32) {
+    let number = slash_8.to_string();
+    let label = slash_8_registration(slash_8).label;
+
+    let widest = text_width(&number, 1).max(text_width(label, 1));
+    let scale = (size / 85).min(size * 3 / 4 / widest).max(1);
+
+    draw_label(map, x as i64, y as i64, &[&number, label], scale);
+}
+
+const LEGEND_BACKGROUND: Rgb<u8> = Rgb([0x18, 0x18, 0x18]);
+const LEGEND_TEXT: Rgb<u8> = Rgb([0xFF, 0xFF, 0xFF]);
+
+/// Draws the colour bar of an RTT scale with round RTTs marked underneath
+pub fn draw_rtt_legend(coloring: &RttColoring, width: u32) -> RgbImage {
+    const TEXT_SCALE: u32 = 2;
+    const MARGIN: u32 = 32;
+    const BAR_HEIGHT: u32 = 24;
+    const TICK_LENGTH: u32 = 6;
+
+    let text_height = GLYPH_HEIGHT * TEXT_SCALE;
+    let bar_top = MARGIN / 2 + text_height + 8;
+    let labels_top = bar_top + BAR_HEIGHT + TICK_LENGTH + 4;
+    let height = labels_top + text_height + MARGIN / 2;
+
+    let mut legend = RgbImage::from_pixel(width, height, LEGEND_BACKGROUND);
+    let bar_width = width.saturating_sub(2 * MARGIN).max(2);
+
+    for x in 0..bar_width {
+        let color = viridis(x as f32 / (bar_width - 1) as f32);
+
+        for y in bar_top..bar_top + BAR_HEIGHT {
+            legend.put_pixel((MARGIN + x).min(width - 1), y, color);
+        }
+    }
+
+    let title = match coloring.scale {
+        RttScale::Linear => "RTT, linear scale",
+        RttScale::Log => "RTT, log scale",
+    };
+
+    draw_text(
+        &mut legend,
+        MARGIN as i64,
+        (MARGIN / 2) as i64,
+        title,
+        TEXT_SCALE,
+        LEGEND_TEXT,
+    );
+
+    let max_ticks = (bar_width / 80).max(2) as usize;
+
+    for tick in coloring.ticks(max_ticks) {
+        let x = MARGIN + (coloring.fraction(tick) * (bar_width - 1) as f32).round() as u32;
+
+        for y in bar_top + BAR_HEIGHT..bar_top + BAR_HEIGHT + TICK_LENGTH {
+            legend.put_pixel(x.min(width - 1), y, LEGEND_TEXT);
+        }
+
+        let label = format_rtt(tick);
+        let left = x as i64 - text_width(&label, TEXT_SCALE) as i64 / 2;
+
+        draw_text(
+            &mut legend,
+            left,
+            labels_top as i64,
+            &label,
+            TEXT_SCALE,
+            LEGEND_TEXT,
+        );
+    }
+
+    legend
+}
+
+/// Formats a round RTT as e.g. `0.5ms`, `20ms` or `1.5s`
+pub fn format_rtt(rtt: Duration) -> String {
+    let ms = rtt.as_micros() as f64 / 1000.0;
+
+    match ms >= 1000.0 {
+        true => format!("{}s", ms / 1000.0),
+        false => format!("{ms}ms"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} is not {b}");
+    }
+
+    #[test]
+    fn places_rtts_on_linear_scales() {
+        let coloring = RttColoring::new(RttScale::Linear, ms(0), ms(400));
+
+        assert_close(coloring.fraction(ms(0)), 0.0);
+        assert_close(coloring.fraction(ms(100)), 0.25);
+        assert_close(coloring.fraction(ms(400)), 1.0);
+
+        /* RTTs outside of the range take the colour of its ends */
+
+        assert_close(coloring.fraction(ms(1000)), 1.0);
+        assert_eq!(coloring.color(ms(1000)), viridis(1.0));
+    }
+
+    #[test]
+    fn places_rtts_on_log_scales() {
+        let coloring = RttColoring::new(RttScale::Log, ms(1), ms(1000));
+
+        assert_close(coloring.fraction(ms(1)), 0.0);
+        assert_close(coloring.fraction(ms(10)), 1.0 / 3.0);
+        assert_close(coloring.fraction(ms(100)), 2.0 / 3.0);
+        assert_close(coloring.fraction(ms(1000)), 1.0);
+        assert_close(coloring.fraction(Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn marks_round_rtts_on_linear_scales() {
+        let coloring = RttColoring::new(RttScale::Linear, ms(0), ms(400));
+
+        assert_eq!(coloring.ticks(5), [0, 100, 200, 300, 400].map(ms).to_vec());
+        assert_eq!(coloring.ticks(2), [0, 200, 400].map(ms).to_vec());
+
+        let coloring = RttColoring::new(RttScale::Linear, ms(15), ms(95));
+
+        assert_eq!(coloring.ticks(4), [20, 40, 60, 80].map(ms).to_vec());
+    }
+
+    #[test]
+    fn marks_round_rtts_on_log_scales() {
+        let coloring = RttColoring::new(RttScale::Log, ms(1), ms(1000));
+
+        assert_eq!(
+            coloring.ticks(10),
+            [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000].map(ms).to_vec()
+        );
+
+        /* Only the powers of ten when 1, 2 and 5 would be too many */
+
+        assert_eq!(coloring.ticks(9), [1, 10, 100, 1000].map(ms).to_vec());
+
+        let coloring = RttColoring::new(RttScale::Log, Duration::from_micros(500), ms(3000));
+
+        assert_eq!(coloring.ticks(12)[..2], [Duration::from_micros(500), ms(1)]);
+        assert_eq!(coloring.ticks(12).last(), Some(&ms(2000)));
+    }
+}
@@ -0,0 +1,414 @@
+use std::{fmt::Write, time::Duration};
+
+use image::Rgb;
+
+use crate::{
+    iana::{registration_of, slash_8_registration},
+    render::{format_rtt, Curve, RttColoring},
+    stats::{Analysis, RttStats, RTT_BUCKETS},
+    subnet::Subnet,
+};
+
+/// Kept inline so every page works on its own, e.g. opened from a zip
+const STYLE: &str = "
+body { max-width: 1100px; margin: 2em auto; padding: 0 1em; font-family: system-ui, sans-serif; color: #202020; }
+a { color: #1a6fb0; }
+table { border-collapse: collapse; font-variant-numeric: tabular-nums; }
+th, td { padding: 0.2em 0.6em; text-align: left; }
+td.n, th.n { text-align: right; }
+table.subnets tr:nth-child(even) { background: #f2f2f2; }
+table.subnets td.message { color: #808080; }
+figure { margin: 1em 0; }
+figcaption { color: #606060; font-size: 0.9em; }
+svg.map, img.map { display: block; width: 100%; max-width: 768px; image-rendering: pixelated; }
+svg.map a rect { fill: transparent; }
+svg.map a:hover rect { stroke: #ffffff; stroke-width: 0.06; }
+svg.histogram { display: block; width: 100%; max-width: 768px; }
+svg.histogram text { font-size: 11px; fill: #606060; }
+";
+
+/// A page of the HTML report, built up a section at a time
+pub struct ReportPage {
+    html: String,
+}
+
+impl ReportPage {
+    /// Starts a page, with a link back to the page above it if any
+    pub fn new(title: &str, up: Option<(&str, &str)>) -> Self {
+        let mut html = String::new();
+
+        write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n",
+            escape_html(title)
+        )
+        .unwrap();
+
+        if let Some((href, text)) = up {
+            writeln!(
+                html,
+                "<p><a href=\"{}\">&larr; {}</a></p>",
+                escape_html(href),
+                escape_html(text)
+            )
+            .unwrap();
+        }
+
+        writeln!(html, "<h1>{}</h1>", escape_html(title)).unwrap();
+
+        Self { html }
+    }
+
+    pub fn push_heading(&mut self, text: &str) {
+        writeln!(self.html, "<h2>{}</h2>", escape_html(text)).unwrap();
+    }
+
+    pub fn push_paragraph(&mut self, text: &str) {
+        writeln!(self.html, "<p>{}</p>", escape_html(text)).unwrap();
+    }
+
+    /// The totals of a subnet, with the share of it that was pinged and of
+    /// that what replied
+    pub fn push_summary(&mut self, anal: &Analysis, damaged: u32) {
+        let scanned = anal.scanned();
+        let rtt = |rtt: Option<Duration>| rtt.map_or("-".to_string(), format_rtt);
+
+        let mut rows = vec![
+            (
+                "Pinged",
+                format!(
+                    "{} of {} ({:.2}%)",
+                    group_digits(scanned),
+                    group_digits(anal.get_max()),
+                    100.0 - anal.unscanned_percent()
+                ),
+            ),
+            (
+                "Alive",
+                format!(
+                    "{} ({:.2}% of pinged)",
+                    group_digits(anal.alive),
+                    percent(anal.alive, scanned)
+                ),
+            ),
+            (
+                "Timed out",
+                format!(
+                    "{} ({:.2}% of pinged)",
+                    group_digits(anal.timed_out),
+                    percent(anal.timed_out, scanned)
+                ),
+            ),
+            (
+                "Errored",
+                format!(
+                    "{} ({:.2}% of pinged)",
+                    group_digits(anal.errored),
+                    percent(anal.errored, scanned)
+                ),
+            ),
+            (
+                "RTT",
+                format!(
+                    "min {}, median {}, p90 {}, p99 {}, max {}",
+                    rtt(anal.rtt.min),
+                    rtt(anal.rtt.median()),
+                    rtt(anal.rtt.p90()),
+                    rtt(anal.rtt.p99()),
+                    rtt(anal.rtt.max)
+                ),
+            ),
+        ];
+
+        if damaged != 0 {
+            rows.push((
+                "Unreadable",
+                format!("{damaged} /16s, counted as unscanned"),
+            ));
+        }
+
+        writeln!(self.html, "<table class=\"summary\">").unwrap();
+
+        for (name, value) in rows {
+            writeln!(self.html, "<tr><th>{name}</th><td>{value}</td></tr>").unwrap();
+        }
+
+        writeln!(self.html, "</table>").unwrap();
+    }
+
+    /// A map of the whole internet, a /8 per square of a 16x16 grid, with every
+    /// square that has a link going to it
+    pub fn push_overview(
+        &mut self,
+        src: &str,
+        curve: Curve,
+        link: impl Fn(u8) -> Option<String>,
+        caption: &str,
+    ) {
+        writeln!(
+            self.html,
+            "<figure>\n<svg class=\"map\" viewBox=\"0 0 16 16\" xmlns=\"http://www.w3.org/2000/svg\">\n<image href=\"{}\" width=\"16\" height=\"16\" style=\"image-rendering: pixelated\"/>",
+            escape_html(src)
+        )
+        .unwrap();
+
+        for a in 0..=u8::MAX {
+            let Some(href) = link(a) else {
+                continue;
+            };
+
+            let (x, y) = curve.position(a as u64, 16);
+            let registration = slash_8_registration(a);
+
+            writeln!(
+                self.html,
+                "<a href=\"{}\"><rect x=\"{x}\" y=\"{y}\" width=\"1\" height=\"1\"><title>{a}.x.x.x {}</title></rect></a>",
+                escape_html(&href),
+                escape_html(registration.label)
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            self.html,
+            "</svg>\n<figcaption>{}</figcaption>\n</figure>",
+            escape_html(caption)
+        )
+        .unwrap();
+    }
+
+    pub fn push_map(&mut self, src: &str, caption: &str) {
+        writeln!(
+            self.html,
+            "<figure>\n<a href=\"{0}\"><img class=\"map\" src=\"{0}\" alt=\"{1}\"></a>\n<figcaption>{1}</figcaption>\n</figure>",
+            escape_html(src),
+            escape_html(caption)
+        )
+        .unwrap();
+    }
+
+    /// How many replies took how long, as an inline SVG bar chart with a bar
+    /// per bucket of the histogram, coloured as on an RTT map
+    pub fn push_rtt_histogram(&mut self, rtt: &RttStats) {
+        const WIDTH: f32 = 768.0;
+        const HEIGHT: f32 = 200.0;
+        const LEFT: f32 = 64.0;
+        const BOTTOM: f32 = 24.0;
+
+        let Some(last) = rtt.histogram.iter().rposition(|count| *count != 0) else {
+            self.push_paragraph("Nothing replied.");
+            return;
+        };
+
+        /* Up to the slowest bucket, but never fewer than the 1ms ones */
+
+        let buckets = (last + 1).max(16);
+        let most = *rtt.histogram.iter().max().unwrap();
+        let bar_width = (WIDTH - LEFT) / buckets as f32;
+        let chart_height = HEIGHT - BOTTOM - 8.0;
+        let coloring = RttColoring::default();
+
+        writeln!(
+            self.html,
+            "<figure>\n<svg class=\"histogram\" viewBox=\"0 0 {WIDTH} {HEIGHT}\" xmlns=\"http://www.w3.org/2000/svg\">"
+        )
+        .unwrap();
+
+        for (bucket, count) in rtt.histogram[..buckets].iter().enumerate() {
+            let floor = RttStats::bucket_floor(bucket);
+            let range = match bucket + 1 < RTT_BUCKETS {
+                true => format!("{floor}-{}ms", RttStats::bucket_floor(bucket + 1)),
+                false => format!("{floor}ms and over"),
+            };
+
+            let height = *count as f32 / most as f32 * chart_height;
+            let Rgb([r, g, b]) = coloring.color(Duration::from_millis(floor as u64));
+
+            writeln!(
+                self.html,
+                "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{height:.2}\" fill=\"#{r:02x}{g:02x}{b:02x}\"><title>{range}: {}</title></rect>",
+                LEFT + bucket as f32 * bar_width,
+                8.0 + chart_height - height,
+                bar_width * 0.9,
+                group_digits(*count)
+            )
+            .unwrap();
+        }
+
+        /* The count axis, then round RTTs under the buckets they fall in */
+
+        let axis_y = 8.0 + chart_height;
+
+        writeln!(
+            self.html,
+            "<line x1=\"{LEFT}\" y1=\"{axis_y}\" x2=\"{WIDTH}\" y2=\"{axis_y}\" stroke=\"#808080\"/>\n<text x=\"{}\" y=\"16\" text-anchor=\"end\">{}</text>\n<text x=\"{}\" y=\"{axis_y}\" text-anchor=\"end\">0</text>",
+            LEFT - 6.0,
+            group_digits(most),
+            LEFT - 6.0
+        )
+        .unwrap();
+
+        let ticks = [1, 10, 100, 1000, 10000]
+            .iter()
+            .flat_map(|decade| [1u64, 2, 5].map(|step| step * decade))
+            .filter(|ms| RttStats::bucket_of(Duration::from_millis(*ms)) < buckets);
+
+        let mut last_x = f32::MIN;
+
+        for ms in ticks {
+            let bucket = RttStats::bucket_of(Duration::from_millis(ms));
+            let x = LEFT + (bucket as f32 + 0.45) * bar_width;
+
+            /* Where the 1ms buckets are narrow, 2ms would sit on top of 1ms */
+
+            if x - last_x < 40.0 {
+                continue;
+            }
+
+            last_x = x;
+
+            writeln!(
+                self.html,
+                "<text x=\"{x:.2}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                HEIGHT - 6.0,
+                format_rtt(Duration::from_millis(ms))
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            self.html,
+            "</svg>\n<figcaption>Replies by RTT, a bar per millisecond up to 16ms and per eighth of a doubling after that</figcaption>\n</figure>"
+        )
+        .unwrap();
+    }
+
+    /// Starts a table of subnets, a row per subnet pushed after it
+    pub fn begin_subnet_table(&mut self) {
+        writeln!(
+            self.html,
+            "<table class=\"subnets\">\n<tr><th>Subnet</th><th>Registry</th><th class=\"n\">Pinged</th><th class=\"n\">Alive</th><th class=\"n\">Alive of pinged</th><th class=\"n\">Median RTT</th><th class=\"n\">P90 RTT</th><th class=\"n\">P99 RTT</th></tr>"
+        )
+        .unwrap();
+    }
+
+    /// A row of counts, with the subnet linking to its own page if it has one
+    pub fn push_subnet_row(&mut self, subnet: Subnet, anal: &Analysis, link: Option<&str>) {
+        let rtt = |rtt: Option<Duration>| rtt.map_or("-".to_string(), format_rtt);
+
+        writeln!(
+            self.html,
+            "<tr><td>{}</td>{}<td class=\"n\">{:.2}%</td><td class=\"n\">{}</td><td class=\"n\">{:.2}%</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+            subnet_cell(subnet, link),
+            registry_cell(subnet),
+            100.0 - anal.unscanned_percent(),
+            group_digits(anal.alive),
+            anal.alive_percent_of_scanned(),
+            rtt(anal.rtt.median()),
+            rtt(anal.rtt.p90()),
+            rtt(anal.rtt.p99()),
+        )
+        .unwrap();
+    }
+
+    /// A row with a message in place of the counts, e.g. for a subnet that was not scanned
+    pub fn push_subnet_message(&mut self, subnet: Subnet, message: &str) {
+        writeln!(
+            self.html,
+            "<tr><td>{}</td>{}<td class=\"message\" colspan=\"6\">{}</td></tr>",
+            subnet_cell(subnet, None),
+            registry_cell(subnet),
+            escape_html(message)
+        )
+        .unwrap();
+    }
+
+    pub fn end_subnet_table(&mut self) {
+        writeln!(self.html, "</table>").unwrap();
+    }
+
+    /// Closes the page, returning its HTML
+    pub fn finish(mut self) -> String {
+        writeln!(self.html, "</body>\n</html>").unwrap();
+
+        self.html
+    }
+}
+
+fn subnet_cell(subnet: Subnet, link: Option<&str>) -> String {
+    match link {
+        Some(href) => format!("<a href=\"{}\">{subnet}</a>", escape_html(href)),
+        None => subnet.to_string(),
+    }
+}
+
+/// The short name of whoever IANA gave the /8 of a subnet to, with their full name on hover
+fn registry_cell(subnet: Subnet) -> String {
+    match registration_of(subnet) {
+        Some(registration) => format!(
+            "<td title=\"{}\">{}</td>",
+            escape_html(registration.designation),
+            escape_html(registration.label)
+        ),
+        None => "<td></td>".to_string(),
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    match whole {
+        0 => 0.0,
+        _ => part as f64 / whole as f64 * 100.0,
+    }
+}
+
+/// Formats a count with its thousands separated, e.g. `16,777,216`
+fn group_digits(count: u64) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+
+    for (i, digit) in digits.chars().enumerate() {
+        if i != 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+
+        grouped.push(digit);
+    }
+
+    grouped
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_digits_by_thousands() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(1000), "1,000");
+        assert_eq!(group_digits(65536), "65,536");
+        assert_eq!(group_digits(123456), "123,456");
+        assert_eq!(group_digits(1 << 32), "4,294,967,296");
+        assert_eq!(group_digits(u64::MAX), "18,446,744,073,709,551,615");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(escape_html("plain text"), "plain text");
+        assert_eq!(
+            escape_html(r#"<a href="x">R&D</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;R&amp;D&lt;/a&gt;"
+        );
+
+        /* Entities already in the text are escaped again rather than kept */
+
+        assert_eq!(escape_html("&lt;"), "&amp;lt;");
+    }
+}
@@ -0,0 +1,901 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind},
+    net::Ipv4Addr,
+    path::Path,
+};
+
+use flate2::bufread::MultiGzDecoder;
+
+use crate::{
+    attribution::{density_percent, AddressCounts, Attributed},
+    ping::PingResult,
+    stats::{RttStats, Slash16Result},
+    subnet::{Subnet, SubnetMask},
+};
+
+pub mod mrt;
+
+/// The AS or ASes a prefix is originated by
+///
+/// Prefixes seen with more than one origin (MOAS) or ending in an AS set have
+/// all of them, sorted, and are reported as a single origin like `AS701_702`
+/// the way CAIDA's pfx2as does
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Origin(pub Vec<u32>);
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AS")?;
+
+        for (i, asn) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, "_")?;
+            }
+
+            write!(f, "{asn}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An announced prefix
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub network: Ipv4Addr,
+    pub length: u8,
+    /// Index into [`RoutingTable::origins`]
+    pub origin: usize,
+    /// The addresses this is the longest match for, i.e. minus any more specific routes
+    pub routed: u64,
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.length)
+    }
+}
+
+/// A range of addresses which all have the same longest matching route
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    start: u32,
+    end: u32,
+    route: u32,
+}
+
+/// A snapshot of the announced IPv4 prefixes and their origins
+///
+/// Routes are flattened into sorted, non-overlapping ranges so that the
+/// longest-prefix match of a run of addresses is a walk rather than a search
+#[derive(Debug, Clone, Default)]
+pub struct RoutingTable {
+    routes: Vec<Route>,
+    origins: Vec<Origin>,
+    intervals: Vec<Interval>,
+}
+
+impl RoutingTable {
+    /// Reads a CAIDA pfx2as file or an MRT TABLE_DUMP_V2 RIB dump, either of which may be gzipped
+    ///
+    /// bzip2 compressed dumps (as RouteViews publishes them) have to be decompressed first
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+
+        Self::read(reader)
+    }
+
+    /// Reads a routing table, detecting its format and compression from the first bytes
+    pub fn read(mut reader: impl BufRead + 'static) -> std::io::Result<Self> {
+        let mut reader: Box<dyn BufRead> = match reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            true => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+            false => Box::new(reader),
+        };
+
+        let start = reader.fill_buf()?;
+
+        if start.starts_with(b"BZh") {
+            return Err(invalid_data(
+                "bzip2 compressed routing tables have to be decompressed first".to_string(),
+            ));
+        }
+
+        /* MRT records start with a timestamp then a type, whose high byte is always 0 */
+
+        let is_mrt = start.get(4) == Some(&0);
+
+        let mut announcements = Vec::new();
+        let on_route = |network, length, origin| announcements.push((network, length, origin));
+
+        match is_mrt {
+            true => mrt::read_rib(reader, on_route)?,
+            false => read_pfx2as(reader, on_route)?,
+        }
+
+        Ok(Self::from_announcements(announcements))
+    }
+
+    /// Builds a table from `(network, length, origin ASes)`, merging the origins of duplicate prefixes
+    pub fn from_announcements(
+        announcements: impl IntoIterator<Item = (Ipv4Addr, u8, Vec<u32>)>,
+    ) -> Self {
+        /* Shorter prefixes sort first, so a route always comes before those inside it */
+
+        let mut prefixes: BTreeMap<(u32, u8), Vec<u32>> = BTreeMap::new();
+
+        for (network, length, origin) in announcements {
+            let length = length.min(32);
+            let network = u32::from(network) & prefix_mask(length);
+
+            prefixes
+                .entry((network, length))
+                .or_default()
+                .extend(origin);
+        }
+
+        let mut table = Self::default();
+        let mut origin_ids: HashMap<Origin, usize> = HashMap::new();
+
+        for ((network, length), mut asns) in prefixes {
+            asns.sort_unstable();
+            asns.dedup();
+
+            let origin = Origin(asns);
+
+            let origin = *origin_ids.entry(origin.clone()).or_insert_with(|| {
+                table.origins.push(origin);
+                table.origins.len() - 1
+            });
+
+            table.routes.push(Route {
+                network: network.into(),
+                length,
+                origin,
+                routed: 0,
+            });
+        }
+
+        table.flatten();
+        table
+    }
+
+    /// Splits the routes into ranges of addresses with the same longest match
+    fn flatten(&mut self) {
+        let mut intervals = Vec::new();
+        let mut emit = |start: u64, end: u64, route: usize| {
+            if start <= end {
+                intervals.push(Interval {
+                    start: start as u32,
+                    end: end as u32,
+                    route: route as u32,
+                });
+            }
+        };
+
+        /* The routes enclosing the current one, innermost last */
+
+        let mut stack: Vec<(u64, usize)> = Vec::new();
+        let mut cursor: u64 = 0;
+
+        for (i, route) in self.routes.iter().enumerate() {
+            let start = u32::from(route.network) as u64;
+            let end = start + (1u64 << (32 - route.length)) - 1;
+
+            while let Some(&(enclosing_end, enclosing)) = stack.last() {
+                if enclosing_end >= start {
+                    break;
+                }
+
+                emit(cursor, enclosing_end, enclosing);
+                cursor = cursor.max(enclosing_end + 1);
+                stack.pop();
+            }
+
+            if let Some(&(_, enclosing)) = stack.last().filter(|_| start > cursor) {
+                emit(cursor, start - 1, enclosing);
+            }
+
+            cursor = start;
+            stack.push((end, i));
+        }
+
+        while let Some((end, route)) = stack.pop() {
+            emit(cursor, end, route);
+            cursor = cursor.max(end + 1);
+        }
+
+        for interval in &intervals {
+            self.routes[interval.route as usize].routed +=
+                (interval.end - interval.start) as u64 + 1;
+        }
+
+        self.intervals = intervals;
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    pub fn origins(&self) -> &[Origin] {
+        &self.origins
+    }
+
+    /// The number of addresses covered by at least one route
+    pub fn routed_addresses(&self) -> u64 {
+        self.routes.iter().map(|route| route.routed).sum()
+    }
+
+    /// Returns the index of the longest matching route of an address, if any
+    pub fn lookup(&self, address: Ipv4Addr) -> Option<usize> {
+        let address = u32::from(address);
+        let i = self
+            .intervals
+            .partition_point(|interval| interval.end < address);
+
+        self.intervals
+            .get(i)
+            .filter(|interval| interval.start <= address)
+            .map(|interval| interval.route as usize)
+    }
+
+    /// The addresses of a subnet each route is the longest match for, indexed like [`Self::routes`]
+    pub fn routed_in(&self, scope: Subnet) -> Vec<u64> {
+        let scope = scope.address_range();
+        let mut routed = vec![0; self.routes.len()];
+
+        let i = self
+            .intervals
+            .partition_point(|interval| interval.end < *scope.start());
+
+        for interval in self.intervals[i..]
+            .iter()
+            .take_while(|interval| interval.start <= *scope.end())
+        {
+            let start = interval.start.max(*scope.start());
+            let end = interval.end.min(*scope.end());
+
+            routed[interval.route as usize] += (end - start) as u64 + 1;
+        }
+
+        routed
+    }
+
+    /// Attributes every scanned address of a /16 inside the scope to its longest matching route
+    pub fn attribute_slash_16(
+        &self,
+        subnet: Subnet,
+        scope: Subnet,
+        results: &Slash16Result,
+    ) -> Slash16Routes {
+        assert_eq!(
+            subnet.mask(),
+            SubnetMask::Slash16,
+            "attribute_slash_16 only takes /16 subnets"
+        );
+
+        let base = u32::from(subnet.base_address());
+
+        let mut attribution = Slash16Routes::default();
+        let mut i = self
+            .intervals
+            .partition_point(|interval| interval.end < base);
+
+        for (c, slash_24) in results.iter().enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            for (d, ping_result) in slash_24.iter().enumerate() {
+                let address = base | (c as u32) << 8 | d as u32;
+
+                if !scope.contains(address.into()) {
+                    continue;
+                }
+
+                while self
+                    .intervals
+                    .get(i)
+                    .is_some_and(|interval| interval.end < address)
+                {
+                    i += 1;
+                }
+
+                let route = self
+                    .intervals
+                    .get(i)
+                    .filter(|interval| interval.start <= address)
+                    .map(|interval| interval.route as usize);
+
+                let rtt = match route {
+                    Some(route) => {
+                        attribution
+                            .routes
+                            .entry(route)
+                            .or_default()
+                            .count(ping_result);
+
+                        attribution
+                            .origins
+                            .entry(self.routes[route].origin)
+                            .or_default()
+                    }
+                    None => {
+                        attribution.unrouted.count(ping_result);
+
+                        &mut attribution.unrouted_rtt
+                    }
+                };
+
+                if let Some(time) = ping_result.rtt() {
+                    rtt.record(time);
+                }
+            }
+        }
+
+        attribution
+    }
+}
+
+/// The counts of the scanned addresses of a route
+///
+/// Only the sum of the RTTs is kept, as a histogram for each of the million or
+/// so routes would not fit in memory, so routes report the mean RTT
+#[derive(Debug, Clone, Default)]
+pub struct RouteTotals {
+    pub counts: AddressCounts,
+    pub rtt_total_ms: u64,
+    /// The replies whose RTT is known, which `rtt_total_ms` is the sum of
+    pub rtts: u64,
+}
+
+impl RouteTotals {
+    fn count(&mut self, ping_result: &PingResult) {
+        self.counts.count(ping_result);
+
+        if let Some(time) = ping_result.rtt() {
+            self.rtt_total_ms += time.as_millis() as u64;
+            self.rtts += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &RouteTotals) {
+        self.counts.merge(&other.counts);
+        self.rtt_total_ms += other.rtt_total_ms;
+        self.rtts += other.rtts;
+    }
+
+    /// The mean RTT of the addresses that replied with a known RTT, in ms
+    pub fn mean_rtt(&self) -> Option<f32> {
+        (self.rtts != 0).then(|| self.rtt_total_ms as f32 / self.rtts as f32)
+    }
+}
+
+impl Attributed for RouteTotals {
+    fn counts(&self) -> &AddressCounts {
+        &self.counts
+    }
+}
+
+/// The routes the scanned addresses of a single /16 were attributed to
+#[derive(Debug, Clone, Default)]
+pub struct Slash16Routes {
+    pub routes: HashMap<usize, RouteTotals>,
+    /// The RTTs of each origin, which are few enough to keep whole
+    pub origins: HashMap<usize, RttStats>,
+    pub unrouted: AddressCounts,
+    pub unrouted_rtt: RttStats,
+}
+
+/// The totals of an origin across all of its routes
+#[derive(Debug, Clone, Default)]
+pub struct OriginTotals {
+    pub counts: AddressCounts,
+    pub rtt: RttStats,
+    /// The number of prefixes it originates that overlap the scope
+    pub routes: u32,
+    /// The addresses of the scope it is the longest match for
+    pub routed: u64,
+}
+
+impl OriginTotals {
+    /// The share of its routed space that replied
+    pub fn density_percent(&self) -> f32 {
+        density_percent(self.counts.alive, self.routed)
+    }
+}
+
+impl Attributed for OriginTotals {
+    fn counts(&self) -> &AddressCounts {
+        &self.counts
+    }
+}
+
+/// The attribution of a whole run, summed up from [`Slash16Routes`]
+#[derive(Debug, Clone)]
+pub struct RoutingReport {
+    /// Indexed like [`RoutingTable::routes`]
+    pub routes: Vec<RouteTotals>,
+    /// The addresses of the scope each route is the longest match for, indexed like [`RoutingTable::routes`]
+    pub routed: Vec<u64>,
+    /// Indexed like [`RoutingTable::origins`]
+    pub origins: Vec<OriginTotals>,
+    pub unrouted: AddressCounts,
+    pub unrouted_rtt: RttStats,
+}
+
+impl RoutingReport {
+    /// Starts with the routed space of every route clipped to the scope, so
+    /// densities are of the part of a route that was asked about
+    pub fn new(table: &RoutingTable, scope: Subnet) -> Self {
+        let routed = table.routed_in(scope);
+        let scope = scope.address_range();
+
+        let mut origins = vec![OriginTotals::default(); table.origins.len()];
+
+        for (route, routed) in table.routes.iter().zip(&routed) {
+            let start = u32::from(route.network);
+            let end = start | !prefix_mask(route.length);
+
+            if start > *scope.end() || end < *scope.start() {
+                continue;
+            }
+
+            origins[route.origin].routes += 1;
+            origins[route.origin].routed += routed;
+        }
+
+        Self {
+            routes: vec![RouteTotals::default(); table.routes.len()],
+            routed,
+            origins,
+            unrouted: AddressCounts::default(),
+            unrouted_rtt: RttStats::default(),
+        }
+    }
+
+    pub fn add(&mut self, table: &RoutingTable, attribution: &Slash16Routes) {
+        for (route, totals) in &attribution.routes {
+            self.routes[*route].merge(totals);
+            self.origins[table.routes[*route].origin]
+                .counts
+                .merge(&totals.counts);
+        }
+
+        for (origin, rtt) in &attribution.origins {
+            self.origins[*origin].rtt.merge(rtt);
+        }
+
+        self.unrouted.merge(&attribution.unrouted);
+        self.unrouted_rtt.merge(&attribution.unrouted_rtt);
+    }
+
+    /// The number of addresses of the scope covered by at least one route
+    pub fn routed_addresses(&self) -> u64 {
+        self.routed.iter().sum()
+    }
+
+    /// The number of scanned addresses, routed or not
+    pub fn scanned(&self) -> u64 {
+        self.origins
+            .iter()
+            .map(|origin| origin.counts.scanned())
+            .sum::<u64>()
+            + self.unrouted.scanned()
+    }
+}
+
+pub fn print_origin_table_header() {
+    println!(
+        "| {:^4} | {:^20} | {:^8} | {:^10} | {:^10} | {:^10} | {:^9} | {:^9} | {:^6} |",
+        "RANK",
+        "ORIGIN",
+        "PREFIXES",
+        "ROUTED",
+        "SCANNED",
+        "SUCCEEDED",
+        "ALIVE %",
+        "DENSITY",
+        "MED ms",
+    );
+    println!(
+        "|{:->6}|{:->22}|{:->10}|{:->12}|{:->12}|{:->12}|{:->11}|{:->11}|{:->8}|",
+        "", "", "", "", "", "", "", "", ""
+    );
+}
+
+pub fn print_origin_table_row(rank: usize, origin: &Origin, totals: &OriginTotals) {
+    println!(
+        "| {:>4} | {:>20} | {:>8} | {:>10} | {:>10} | {:>10} | {:>9} | {:>9} | {:>6} |",
+        rank,
+        format!("{origin}"),
+        totals.routes,
+        totals.routed,
+        totals.counts.scanned(),
+        totals.counts.alive,
+        format!("{:.2}%", totals.counts.alive_percent()),
+        format!("{:.2}%", totals.density_percent()),
+        totals
+            .rtt
+            .median()
+            .map_or("-".to_string(), |rtt| rtt.as_millis().to_string()),
+    );
+}
+
+pub fn print_origin_csv_header() {
+    println!(
+        "rank,origin,prefixes,routed,scanned,alive,alive_percent,density_percent,median_rtt_ms"
+    );
+}
+
+pub fn print_origin_csv_row(rank: usize, origin: &Origin, totals: &OriginTotals) {
+    println!(
+        "{},{},{},{},{},{},{:.2},{:.2},{}",
+        rank,
+        origin,
+        totals.routes,
+        totals.routed,
+        totals.counts.scanned(),
+        totals.counts.alive,
+        totals.counts.alive_percent(),
+        totals.density_percent(),
+        totals
+            .rtt
+            .median()
+            .map_or(String::new(), |rtt| rtt.as_millis().to_string()),
+    );
+}
+
+pub fn print_route_table_header() {
+    println!(
+        "| {:^4} | {:^18} | {:^20} | {:^10} | {:^10} | {:^10} | {:^9} | {:^9} | {:^7} |",
+        "RANK",
+        "PREFIX",
+        "ORIGIN",
+        "ROUTED",
+        "SCANNED",
+        "SUCCEEDED",
+        "ALIVE %",
+        "DENSITY",
+        "MEAN ms",
+    );
+    println!(
+        "|{:->6}|{:->20}|{:->22}|{:->12}|{:->12}|{:->12}|{:->11}|{:->11}|{:->9}|",
+        "", "", "", "", "", "", "", "", ""
+    );
+}
+
+pub fn print_route_table_row(
+    rank: usize,
+    route: &Route,
+    origin: &Origin,
+    totals: &RouteTotals,
+    routed: u64,
+) {
+    println!(
+        "| {:>4} | {:>18} | {:>20} | {:>10} | {:>10} | {:>10} | {:>9} | {:>9} | {:>7} |",
+        rank,
+        format!("{route}"),
+        format!("{origin}"),
+        routed,
+        totals.counts.scanned(),
+        totals.counts.alive,
+        format!("{:.2}%", totals.counts.alive_percent()),
+        format!("{:.2}%", density_percent(totals.counts.alive, routed)),
+        totals
+            .mean_rtt()
+            .map_or("-".to_string(), |rtt| format!("{rtt:.1}")),
+    );
+}
+
+pub fn print_route_csv_header() {
+    println!("rank,prefix,origin,routed,scanned,alive,alive_percent,density_percent,mean_rtt_ms");
+}
+
+pub fn print_route_csv_row(
+    rank: usize,
+    route: &Route,
+    origin: &Origin,
+    totals: &RouteTotals,
+    routed: u64,
+) {
+    println!(
+        "{},{},{},{},{},{},{:.2},{:.2},{}",
+        rank,
+        route,
+        origin,
+        routed,
+        totals.counts.scanned(),
+        totals.counts.alive,
+        totals.counts.alive_percent(),
+        density_percent(totals.counts.alive, routed),
+        totals
+            .mean_rtt()
+            .map_or(String::new(), |rtt| format!("{rtt:.1}")),
+    );
+}
+
+/// Reads `<network>\t<length>\t<origin>` lines, with MOAS origins joined by `_` and AS sets by `,`
+fn read_pfx2as(
+    reader: impl BufRead,
+    mut on_route: impl FnMut(Ipv4Addr, u8, Vec<u32>),
+) -> std::io::Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<_> = line.split_whitespace().collect();
+
+        let [network, length, origin, ..] = fields.as_slice() else {
+            return Err(invalid_data(format!("line {}: missing fields", i + 1)));
+        };
+
+        let invalid = |field: &str| invalid_data(format!("line {}: invalid {field}", i + 1));
+
+        let network: Ipv4Addr = network.parse().map_err(|_| invalid("network"))?;
+        let length: u8 = length
+            .parse()
+            .ok()
+            .filter(|length| *length <= 32)
+            .ok_or_else(|| invalid("prefix length"))?;
+
+        let origin = origin
+            .split(['_', ','])
+            .map(|asn| asn.trim_matches(['{', '}']).parse())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| invalid("origin"))?;
+
+        on_route(network, length, origin);
+    }
+
+    Ok(())
+}
+
+fn prefix_mask(length: u8) -> u32 {
+    u32::MAX.checked_shl(32 - length as u32).unwrap_or(0)
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    const PFX2AS: &str = "\
+# A comment, then a blank line
+
+10.0.0.0\t8\t64500
+192.0.2.0\t24\t64501_64502
+198.51.100.0\t22\t{64503,64504}
+203.0.113.0\t24\t64505_{64506,64507}
+";
+
+    fn announcements(input: &str) -> std::io::Result<Vec<(Ipv4Addr, u8, Vec<u32>)>> {
+        let mut announcements = Vec::new();
+
+        read_pfx2as(input.as_bytes(), |network, length, origin| {
+            announcements.push((network, length, origin))
+        })?;
+
+        Ok(announcements)
+    }
+
+    #[test]
+    fn reads_pfx2as_lines() {
+        assert_eq!(
+            announcements(PFX2AS).unwrap(),
+            [
+                (Ipv4Addr::new(10, 0, 0, 0), 8, vec![64500]),
+                (Ipv4Addr::new(192, 0, 2, 0), 24, vec![64501, 64502]),
+                (Ipv4Addr::new(198, 51, 100, 0), 22, vec![64503, 64504]),
+                (Ipv4Addr::new(203, 0, 113, 0), 24, vec![64505, 64506, 64507]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_pfx2as_lines() {
+        for (line, message) in [
+            ("10.0.0.0\t8", "line 2: missing fields"),
+            ("10.0.0\t8\t64500", "line 2: invalid network"),
+            ("10.0.0.0\t33\t64500", "line 2: invalid prefix length"),
+            ("10.0.0.0\t8\tAS64500", "line 2: invalid origin"),
+        ] {
+            let error = announcements(&format!("# header\n{line}\n")).unwrap_err();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn detects_the_format_and_compression() {
+        let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+        gzipped.write_all(PFX2AS.as_bytes()).unwrap();
+
+        let table = RoutingTable::read(std::io::Cursor::new(gzipped.finish().unwrap())).unwrap();
+
+        assert_eq!(table.routes().len(), 4);
+        assert_eq!(
+            table.origins()[table.routes()[1].origin].to_string(),
+            "AS64501_64502"
+        );
+
+        let table = RoutingTable::read(std::io::Cursor::new(mrt::tests::sample_rib())).unwrap();
+
+        let routes: Vec<_> = table.routes().iter().map(Route::to_string).collect();
+        assert_eq!(routes, ["0.0.0.0/0", "10.0.0.0/15", "192.0.2.0/24"]);
+
+        let bzipped = RoutingTable::read(&b"BZh91AY&SY"[..]).unwrap_err();
+        assert_eq!(bzipped.kind(), ErrorKind::InvalidData);
+    }
+
+    fn routing_table(announcements: &[(&str, u8, &[u32])]) -> RoutingTable {
+        RoutingTable::from_announcements(
+            announcements.iter().map(|(network, length, origin)| {
+                (network.parse().unwrap(), *length, origin.to_vec())
+            }),
+        )
+    }
+
+    /// Nested, adjacent and duplicate prefixes under a default route
+    fn nested_table() -> RoutingTable {
+        routing_table(&[
+            ("10.1.2.0", 24, &[64503]),
+            ("0.0.0.0", 0, &[64500]),
+            ("10.0.0.0", 8, &[64501]),
+            ("10.1.0.0", 16, &[64502]),
+            ("10.2.0.0", 16, &[64504]),
+            // Announced again by another AS, and with host bits set
+            ("10.1.77.1", 16, &[64505]),
+            ("192.0.2.0", 24, &[64506]),
+            ("192.0.3.0", 24, &[64506]),
+            ("10.255.255.255", 32, &[64507]),
+        ])
+    }
+
+    fn route_index(table: &RoutingTable, route: &str) -> usize {
+        table
+            .routes()
+            .iter()
+            .position(|candidate| candidate.to_string() == route)
+            .unwrap()
+    }
+
+    /// The longest matching route of an address, by checking every route
+    fn brute_force_lookup(table: &RoutingTable, address: u32) -> Option<usize> {
+        table
+            .routes()
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| address & prefix_mask(route.length) == u32::from(route.network))
+            .max_by_key(|(_, route)| route.length)
+            .map(|(i, _)| i)
+    }
+
+    /// The first, last and neighbouring addresses of every route, and a spread of others
+    fn sample_addresses(table: &RoutingTable) -> Vec<u32> {
+        let mut addresses = vec![0, u32::MAX];
+
+        for route in table.routes() {
+            let start = u32::from(route.network);
+            let end = start | !prefix_mask(route.length);
+
+            addresses.extend([start.wrapping_sub(1), start, end, end.wrapping_add(1)]);
+        }
+
+        let mut state: u32 = 1;
+
+        for _ in 0..100_000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            addresses.push(state);
+        }
+
+        addresses
+    }
+
+    #[test]
+    fn flattens_nested_and_adjacent_routes() {
+        let table = nested_table();
+
+        let routed: Vec<_> = table
+            .routes()
+            .iter()
+            .map(|route| {
+                (
+                    route.to_string(),
+                    table.origins()[route.origin].to_string(),
+                    route.routed,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            routed,
+            [
+                (
+                    "0.0.0.0/0".to_string(),
+                    "AS64500".to_string(),
+                    (1 << 32) - (1 << 24) - 512
+                ),
+                (
+                    "10.0.0.0/8".to_string(),
+                    "AS64501".to_string(),
+                    (1 << 24) - 2 * 65536 - 1
+                ),
+                (
+                    "10.1.0.0/16".to_string(),
+                    "AS64502_64505".to_string(),
+                    65536 - 256
+                ),
+                ("10.1.2.0/24".to_string(), "AS64503".to_string(), 256),
+                ("10.2.0.0/16".to_string(), "AS64504".to_string(), 65536),
+                ("10.255.255.255/32".to_string(), "AS64507".to_string(), 1),
+                ("192.0.2.0/24".to_string(), "AS64506".to_string(), 256),
+                ("192.0.3.0/24".to_string(), "AS64506".to_string(), 256),
+            ]
+        );
+
+        assert_eq!(table.routed_addresses(), 1 << 32);
+
+        /* Both adjacent /24s share an origin */
+
+        assert_eq!(table.origins().len(), 7);
+
+        let scope = "10.1.x.x".parse().ok().unwrap();
+        let routed_in = table.routed_in(scope);
+
+        assert_eq!(routed_in[route_index(&table, "10.1.0.0/16")], 65536 - 256);
+        assert_eq!(routed_in[route_index(&table, "10.1.2.0/24")], 256);
+        assert_eq!(routed_in.iter().sum::<u64>(), 65536);
+    }
+
+    #[test]
+    fn looks_up_the_longest_match() {
+        let table = nested_table();
+
+        for address in sample_addresses(&table) {
+            assert_eq!(
+                table.lookup(address.into()),
+                brute_force_lookup(&table, address),
+                "{}",
+                Ipv4Addr::from(address)
+            );
+        }
+
+        /* Without a default route, addresses between routes have no match */
+
+        let gapped = routing_table(&[
+            ("10.0.0.0", 8, &[64501]),
+            ("10.0.0.0", 9, &[64502]),
+            ("10.128.0.0", 9, &[64503]),
+            ("11.0.0.0", 24, &[64504]),
+            ("11.0.1.128", 25, &[64505]),
+        ]);
+
+        assert_eq!(gapped.routes()[0].routed, 0);
+        assert_eq!(gapped.routed_addresses(), (1 << 24) + 256 + 128);
+        assert_eq!(gapped.lookup(Ipv4Addr::new(9, 255, 255, 255)), None);
+        assert_eq!(gapped.lookup(Ipv4Addr::new(11, 0, 1, 0)), None);
+
+        for address in sample_addresses(&gapped) {
+            assert_eq!(
+                gapped.lookup(address.into()),
+                brute_force_lookup(&gapped, address),
+                "{}",
+                Ipv4Addr::from(address)
+            );
+        }
+    }
+}
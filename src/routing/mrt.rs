@@ -0,0 +1,316 @@
+use std::{
+    io::{ErrorKind, Read},
+    net::Ipv4Addr,
+};
+
+use nom::{
+    bytes::complete::take,
+    multi::{count, length_data},
+    number::complete::{be_u16, be_u32, u8 as be_u8},
+    IResult,
+};
+
+use super::invalid_data;
+
+/// The MRT type of RIB dumps, see RFC 6396
+const TABLE_DUMP_V2: u16 = 13;
+
+const RIB_IPV4_UNICAST: u16 = 2;
+/// The same with a path ID in every entry, see RFC 8050
+const RIB_IPV4_UNICAST_ADDPATH: u16 = 8;
+
+/// The BGP path attribute the origin is read from
+const AS_PATH: u8 = 2;
+
+const AS_SET: u8 = 1;
+const AS_SEQUENCE: u8 = 2;
+
+/// Reads the IPv4 unicast routes of a TABLE_DUMP_V2 RIB dump, calling back with
+/// the prefix and the origins seen by every peer that has it
+///
+/// Records of any other type are skipped, so a dump of updates yields nothing
+pub fn read_rib(
+    mut reader: impl Read,
+    mut on_route: impl FnMut(Ipv4Addr, u8, Vec<u32>),
+) -> std::io::Result<()> {
+    let mut header = [0; 12];
+    let mut data = Vec::new();
+
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+
+        let record_type = u16::from_be_bytes([header[4], header[5]]);
+        let subtype = u16::from_be_bytes([header[6], header[7]]);
+        let length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+
+        data.resize(length as usize, 0);
+        reader.read_exact(&mut data)?;
+
+        let add_path = match (record_type, subtype) {
+            (TABLE_DUMP_V2, RIB_IPV4_UNICAST) => false,
+            (TABLE_DUMP_V2, RIB_IPV4_UNICAST_ADDPATH) => true,
+            _ => continue,
+        };
+
+        let (_, (network, length, origin)) = parse_rib_ipv4(&data, add_path)
+            .map_err(|_| invalid_data("invalid MRT RIB record".to_string()))?;
+
+        if !origin.is_empty() {
+            on_route(network, length, origin);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a sequence number, a prefix and the RIB entries of every peer
+fn parse_rib_ipv4(input: &[u8], add_path: bool) -> IResult<&[u8], (Ipv4Addr, u8, Vec<u32>)> {
+    let (input, _sequence) = be_u32(input)?;
+    let (input, length) = be_u8(input)?;
+
+    if length > 32 {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+
+    let (input, prefix) = take(length.div_ceil(8))(input)?;
+
+    let mut network = [0; 4];
+    network[..prefix.len()].copy_from_slice(prefix);
+
+    let (input, entry_count) = be_u16(input)?;
+    let (input, entries) = count(
+        |input| parse_rib_entry(input, add_path),
+        entry_count as usize,
+    )(input)?;
+
+    let mut origin: Vec<u32> = entries.into_iter().flatten().collect();
+    origin.sort_unstable();
+    origin.dedup();
+
+    Ok((input, (network.into(), length, origin)))
+}
+
+/// Parses the peer, the time and the path attributes of a route, returning its origins
+fn parse_rib_entry(input: &[u8], add_path: bool) -> IResult<&[u8], Vec<u32>> {
+    let (input, _peer_index) = be_u16(input)?;
+    let (input, _originated) = be_u32(input)?;
+
+    let input = match add_path {
+        true => be_u32(input)?.0,
+        false => input,
+    };
+
+    let (input, mut attributes) = length_data(be_u16)(input)?;
+
+    let mut origin = Vec::new();
+
+    while !attributes.is_empty() {
+        let (rest, flags) = be_u8(attributes)?;
+        let (rest, attribute_type) = be_u8(rest)?;
+
+        // The extended length flag
+        let (rest, value) = match flags & 0x10 {
+            0 => length_data(be_u8)(rest)?,
+            _ => length_data(be_u16)(rest)?,
+        };
+
+        if attribute_type == AS_PATH {
+            origin = parse_as_path_origin(value)?.1;
+        }
+
+        attributes = rest;
+    }
+
+    Ok((input, origin))
+}
+
+/// Returns the last AS of the path, or every AS of a trailing AS set
+///
+/// Paths in TABLE_DUMP_V2 always use 4 byte ASNs
+fn parse_as_path_origin(mut input: &[u8]) -> IResult<&[u8], Vec<u32>> {
+    let mut origin = Vec::new();
+
+    while !input.is_empty() {
+        let (rest, segment_type) = be_u8(input)?;
+        let (rest, asn_count) = be_u8(rest)?;
+        let (rest, asns) = count(be_u32, asn_count as usize)(rest)?;
+
+        match segment_type {
+            AS_SEQUENCE => origin = asns.last().into_iter().copied().collect(),
+            AS_SET => origin = asns,
+            // Confederation segments never hold the origin
+            _ => {}
+        }
+
+        input = rest;
+    }
+
+    Ok((input, origin))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An AS_PATH attribute made of `(segment type, ASNs)` segments
+    fn as_path(segments: &[(u8, &[u32])]) -> Vec<u8> {
+        let mut value = Vec::new();
+
+        for (segment_type, asns) in segments {
+            value.extend_from_slice(&[*segment_type, asns.len() as u8]);
+
+            for asn in *asns {
+                value.extend_from_slice(&asn.to_be_bytes());
+            }
+        }
+
+        // Transitive, so that it is like any other attribute of a real dump
+        let mut attribute = vec![0x40, AS_PATH, value.len() as u8];
+        attribute.extend_from_slice(&value);
+        attribute
+    }
+
+    /// A RIB entry with an ORIGIN attribute before the given AS_PATH
+    fn rib_entry(path: &[u8], add_path: bool) -> Vec<u8> {
+        let mut attributes = vec![0x40, 1, 1, 0];
+        attributes.extend_from_slice(path);
+
+        let mut entry = vec![0, 3, 0x65, 0x4d, 0x0a, 0x00];
+
+        if add_path {
+            entry.extend_from_slice(&7u32.to_be_bytes());
+        }
+
+        entry.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+        entry.extend_from_slice(&attributes);
+        entry
+    }
+
+    fn rib_ipv4(prefix: &[u8], length: u8, entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = 42u32.to_be_bytes().to_vec();
+        data.push(length);
+        data.extend_from_slice(prefix);
+        data.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+
+        for entry in entries {
+            data.extend_from_slice(entry);
+        }
+
+        data
+    }
+
+    /// Wraps a record body in the 12 byte MRT header
+    pub(crate) fn mrt_record(record_type: u16, subtype: u16, body: &[u8]) -> Vec<u8> {
+        let mut record = 1_700_000_000u32.to_be_bytes().to_vec();
+        record.extend_from_slice(&record_type.to_be_bytes());
+        record.extend_from_slice(&subtype.to_be_bytes());
+        record.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        record.extend_from_slice(body);
+        record
+    }
+
+    /// A dump with a peer index table, two RIB records and an add-path one
+    pub(crate) fn sample_rib() -> Vec<u8> {
+        let sequence = as_path(&[(AS_SEQUENCE, &[3356, 1299, 64500])]);
+        let moas = as_path(&[(AS_SEQUENCE, &[174, 64501])]);
+        let set = as_path(&[(AS_SEQUENCE, &[3356]), (AS_SET, &[64503, 64502])]);
+
+        let mut dump = mrt_record(TABLE_DUMP_V2, 1, &[0; 10]);
+
+        dump.extend(mrt_record(
+            TABLE_DUMP_V2,
+            RIB_IPV4_UNICAST,
+            &rib_ipv4(
+                &[10, 0],
+                15,
+                &[rib_entry(&sequence, false), rib_entry(&moas, false)],
+            ),
+        ));
+
+        dump.extend(mrt_record(
+            TABLE_DUMP_V2,
+            RIB_IPV4_UNICAST,
+            &rib_ipv4(&[192, 0, 2], 24, &[rib_entry(&set, false)]),
+        ));
+
+        dump.extend(mrt_record(
+            TABLE_DUMP_V2,
+            RIB_IPV4_UNICAST_ADDPATH,
+            &rib_ipv4(&[], 0, &[rib_entry(&sequence, true)]),
+        ));
+
+        dump
+    }
+
+    #[test]
+    fn parses_origins_of_rib_records() {
+        let sequence = as_path(&[(AS_SEQUENCE, &[3356, 1299, 64500])]);
+        let set = as_path(&[(AS_SEQUENCE, &[3356]), (AS_SET, &[64503, 64502])]);
+        let confederation = as_path(&[(AS_SEQUENCE, &[3356, 64500]), (3, &[65000])]);
+
+        let data = rib_ipv4(
+            &[10, 0],
+            15,
+            &[rib_entry(&sequence, false), rib_entry(&sequence, false)],
+        );
+
+        let (rest, route) = parse_rib_ipv4(&data, false).unwrap();
+
+        assert!(rest.is_empty());
+        assert_eq!(route, (Ipv4Addr::new(10, 0, 0, 0), 15, vec![64500]));
+
+        let data = rib_ipv4(&[192, 0, 2, 128], 25, &[rib_entry(&set, true)]);
+
+        assert_eq!(
+            parse_rib_ipv4(&data, true).unwrap().1,
+            (Ipv4Addr::new(192, 0, 2, 128), 25, vec![64502, 64503])
+        );
+
+        let data = rib_ipv4(&[], 0, &[rib_entry(&confederation, false)]);
+
+        assert_eq!(
+            parse_rib_ipv4(&data, false).unwrap().1,
+            (Ipv4Addr::UNSPECIFIED, 0, vec![64500])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rib_records() {
+        let data = rib_ipv4(&[10, 0, 0, 0, 0], 33, &[]);
+        assert!(parse_rib_ipv4(&data, false).is_err());
+
+        let data = rib_ipv4(&[10], 8, &[rib_entry(&as_path(&[]), false)]);
+        assert!(parse_rib_ipv4(&data[..data.len() - 1], false).is_err());
+    }
+
+    #[test]
+    fn reads_rib_dumps() {
+        let mut routes = Vec::new();
+
+        read_rib(&sample_rib()[..], |network, length, origin| {
+            routes.push((network, length, origin))
+        })
+        .unwrap();
+
+        assert_eq!(
+            routes,
+            [
+                (Ipv4Addr::new(10, 0, 0, 0), 15, vec![64500, 64501]),
+                (Ipv4Addr::new(192, 0, 2, 0), 24, vec![64502, 64503]),
+                (Ipv4Addr::UNSPECIFIED, 0, vec![64500]),
+            ]
+        );
+
+        let dump = sample_rib();
+        let error = read_rib(&dump[..dump.len() - 1], |_, _, _| {}).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
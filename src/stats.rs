@@ -1,10 +1,19 @@
-use std::sync::Arc;
+use std::{
+    io::{Stdout, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 
 use crate::{
+    iana::{registration_of, Slash8Registration},
     ping::PingResult,
     subnet::{Subnet, SubnetMask},
 };
 
+pub mod rank;
+
 pub type Slash8Result = Arc<[Option<Slash16Result>; 256]>;
 pub type Slash16Result = Arc<[Option<Slash24Result>; 256]>;
 pub type Slash24Result = Arc<[Slash32Result; 256]>;
@@ -18,37 +27,85 @@ pub enum SubnetResults {
     Slash32(Slash32Result),
 }
 
-#[derive(Debug, Clone)]
+/// The counts of every state in a subnet
+///
+/// Analyses of subnets can be merged into the analysis of a larger subnet, so
+/// /8 and whole-internet roll-ups never need all of their results at once
+#[derive(Debug, Clone, Serialize)]
 pub struct Analysis {
+    #[serde(serialize_with = "serialize_mask")]
     pub mask: SubnetMask,
-    pub alive: u32,
-    pub timed_out: u32,
-    pub errored: u32,
+    pub alive: u64,
+    pub timed_out: u64,
+    pub errored: u64,
+    /// The addresses which have no result, from /24s or /16s that were not scanned
+    pub unscanned: u64,
+    pub rtt: RttStats,
 }
 
 impl Analysis {
-    fn new(mask: SubnetMask) -> Self {
+    /// An analysis of a subnet with nothing counted yet, to merge the analyses of its subnets into
+    pub fn new(mask: SubnetMask) -> Self {
         Self {
             mask,
             alive: 0,
             timed_out: 0,
             errored: 0,
+            unscanned: 0,
+            rtt: RttStats::default(),
         }
     }
 
-    fn get_max(&self) -> u32 {
+    /// The analysis of a subnet none of which was scanned
+    pub fn unscanned(mask: SubnetMask) -> Self {
+        let mut anal = Self::new(mask);
+        anal.unscanned = anal.get_max();
+        anal
+    }
+
+    /// Adds the counts of a subnet inside of this one, e.g. a /16 to its /8
+    pub fn merge(&mut self, other: &Analysis) {
+        self.alive += other.alive;
+        self.timed_out += other.timed_out;
+        self.errored += other.errored;
+        self.unscanned += other.unscanned;
+        self.rtt.merge(&other.rtt);
+    }
+
+    /// The number of addresses with a result
+    pub fn scanned(&self) -> u64 {
+        self.alive + self.timed_out + self.errored
+    }
+
+    fn count(&mut self, ping_result: &PingResult) {
+        match ping_result {
+            PingResult::Success(_) => {
+                self.alive += 1;
+
+                if let Some(rtt) = ping_result.rtt() {
+                    self.rtt.record(rtt);
+                }
+            }
+            PingResult::Timeout => self.timed_out += 1,
+            PingResult::Error => self.errored += 1,
+        }
+    }
+
+    /// The number of addresses in the subnet
+    pub fn get_max(&self) -> u64 {
         let power = match self.mask {
+            SubnetMask::Slash0 => 32,
+            SubnetMask::Slash8 => 24,
             SubnetMask::Slash16 => 16,
             SubnetMask::Slash24 => 8,
             SubnetMask::Slash32 => 0,
-            _ => unreachable!(),
         };
 
-        2u32.pow(power)
+        2u64.pow(power)
     }
 
-    fn compute_percent(&self, value: u32) -> f32 {
-        (value as f32 / (self.get_max()) as f32) * 100.0
+    fn compute_percent(&self, value: u64) -> f32 {
+        (value as f64 / (self.get_max()) as f64 * 100.0) as f32
     }
 
     pub fn alive_percent(&self) -> f32 {
@@ -63,6 +120,19 @@ impl Analysis {
         self.compute_percent(self.errored)
     }
 
+    pub fn unscanned_percent(&self) -> f32 {
+        self.compute_percent(self.unscanned)
+    }
+
+    /// The share of the scanned addresses that replied, rather than of the whole subnet
+    pub fn alive_percent_of_scanned(&self) -> f32 {
+        if self.scanned() == 0 {
+            return 0.0;
+        }
+
+        (self.alive as f64 / self.scanned() as f64 * 100.0) as f32
+    }
+
     pub fn of_subnet(results: SubnetResults) -> Self {
         match results {
             SubnetResults::Slash8(results) => Self::of_slash_8(results),
@@ -76,24 +146,9 @@ impl Analysis {
         let mut anal = Analysis::new(SubnetMask::Slash8);
 
         for slash_16 in &*results {
-            let Some(slash_16) = slash_16 else {
-                anal.errored += 65536;
-                continue;
-            };
-
-            for slash_24 in &**slash_16 {
-                let Some(slash_24) = slash_24 else {
-                    anal.errored += 256;
-                    continue;
-                };
-
-                for ping_result in &**slash_24 {
-                    match ping_result {
-                        PingResult::Success(_) => anal.alive += 1,
-                        PingResult::Timeout => anal.timed_out += 1,
-                        PingResult::Error => anal.errored += 1,
-                    }
-                }
+            match slash_16 {
+                Some(slash_16) => anal.merge(&Self::of_slash_16(slash_16.clone())),
+                None => anal.merge(&Self::unscanned(SubnetMask::Slash16)),
             }
         }
 
@@ -105,16 +160,12 @@ impl Analysis {
 
         for slash_24 in &*results {
             let Some(slash_24) = slash_24 else {
-                anal.errored += 256;
+                anal.unscanned += 256;
                 continue;
             };
 
             for ping_result in &**slash_24 {
-                match ping_result {
-                    PingResult::Success(_) => anal.alive += 1,
-                    PingResult::Timeout => anal.timed_out += 1,
-                    PingResult::Error => anal.errored += 1,
-                }
+                anal.count(ping_result);
             }
         }
 
@@ -125,11 +176,7 @@ impl Analysis {
         let mut anal = Analysis::new(SubnetMask::Slash24);
 
         for ping_result in &*results {
-            match ping_result {
-                PingResult::Success(_) => anal.alive += 1,
-                PingResult::Timeout => anal.timed_out += 1,
-                PingResult::Error => anal.errored += 1,
-            }
+            anal.count(ping_result);
         }
 
         anal
@@ -138,41 +185,708 @@ impl Analysis {
     fn of_slash_32(ping_result: Slash32Result) -> Self {
         let mut anal = Analysis::new(SubnetMask::Slash32);
 
-        match ping_result {
-            PingResult::Success(_) => anal.alive += 1,
-            PingResult::Timeout => anal.timed_out += 1,
-            PingResult::Error => anal.errored += 1,
-        }
+        anal.count(&ping_result);
 
         anal
     }
 }
 
+fn serialize_mask<S: Serializer>(mask: &SubnetMask, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u8(mask.prefix_length())
+}
+
+/// The number of buckets in an [`RttStats`] histogram
+///
+/// RTTs under 16 ms get a bucket each, then every power of two up to the
+/// largest storable RTT (65535 ms) is split into 8 buckets
+pub const RTT_BUCKETS: usize = 16 + 12 * 8;
+
+/// The distribution of the RTTs of the successes in a subnet
+///
+/// RTTs are kept in a log-scale histogram so the stats of any number of
+/// subnets can be added together, which makes the percentiles approximate
+/// (within 1/8th of the RTT), while the min and max are exact
+#[derive(Debug, Clone)]
+pub struct RttStats {
+    pub histogram: [u64; RTT_BUCKETS],
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+impl Default for RttStats {
+    fn default() -> Self {
+        Self {
+            histogram: [0; RTT_BUCKETS],
+            min: None,
+            max: None,
+        }
+    }
+}
+
+impl RttStats {
+    pub fn record(&mut self, rtt: Duration) {
+        self.histogram[Self::bucket_of(rtt)] += 1;
+
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+    }
+
+    /// Adds the RTTs of another subnet to these
+    pub fn merge(&mut self, other: &RttStats) {
+        for (count, other) in self.histogram.iter_mut().zip(other.histogram) {
+            *count += other;
+        }
+
+        self.min = self.min.into_iter().chain(other.min).min();
+        self.max = self.max.into_iter().chain(other.max).max();
+    }
+
+    /// The number of RTTs recorded
+    pub fn count(&self) -> u64 {
+        self.histogram.iter().sum()
+    }
+
+    /// Returns the RTT which `percentile` percent of the RTTs are at or under, None if there are none
+    pub fn percentile(&self, percentile: f32) -> Option<Duration> {
+        let count = self.count();
+
+        if count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile / 100.0 * count as f32).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+
+        let bucket = self
+            .histogram
+            .iter()
+            .position(|bucket_count| {
+                seen += bucket_count;
+                seen >= rank
+            })
+            .unwrap();
+
+        let rtt = Duration::from_millis(Self::bucket_floor(bucket) as u64);
+
+        Some(rtt.clamp(self.min.unwrap(), self.max.unwrap()))
+    }
+
+    pub fn median(&self) -> Option<Duration> {
+        self.percentile(50.0)
+    }
+
+    pub fn p90(&self) -> Option<Duration> {
+        self.percentile(90.0)
+    }
+
+    pub fn p99(&self) -> Option<Duration> {
+        self.percentile(99.0)
+    }
+
+    pub fn bucket_of(rtt: Duration) -> usize {
+        let ms = rtt.as_millis().min(u16::MAX as u128) as u16;
+
+        if ms < 16 {
+            return ms as usize;
+        }
+
+        /* The power of two, then the 3 bits after the leading one */
+
+        let power = 15 - ms.leading_zeros() as usize;
+        let step = (ms >> (power - 3)) as usize & 0b111;
+
+        16 + (power - 4) * 8 + step
+    }
+
+    /// The smallest RTT in ms that falls in the given bucket
+    pub fn bucket_floor(bucket: usize) -> u16 {
+        if bucket < 16 {
+            return bucket as u16;
+        }
+
+        let power = 4 + (bucket - 16) / 8;
+        let step = (bucket - 16) % 8;
+
+        ((8 + step) << (power - 3)) as u16
+    }
+}
+
+/// Serialised as the percentiles in ms along with the histogram, so that
+/// consumers can still merge the RTTs of several subnets
+impl Serialize for RttStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let ms = |rtt: Option<Duration>| rtt.map(|rtt| rtt.as_millis() as u64);
+
+        let mut state = serializer.serialize_struct("RttStats", 7)?;
+        state.serialize_field("count", &self.count())?;
+        state.serialize_field("min_ms", &ms(self.min))?;
+        state.serialize_field("median_ms", &ms(self.median()))?;
+        state.serialize_field("p90_ms", &ms(self.p90()))?;
+        state.serialize_field("p99_ms", &ms(self.p99()))?;
+        state.serialize_field("max_ms", &ms(self.max))?;
+        state.serialize_field("histogram", &self.histogram[..])?;
+        state.end()
+    }
+}
+
+/// The formats the stats can be printed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// The fixed width table, for reading in a terminal
+    Table,
+    /// A single JSON array with an object per row
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+    /// A GitHub flavoured Markdown table
+    Markdown,
+}
+
+impl std::str::FromStr for StatsFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "markdown" | "md" => Ok(Self::Markdown),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A row of the stats, as it is serialised to JSON
+#[derive(Debug, Clone, Serialize)]
+struct StatsRow<'a> {
+    subnet: String,
+    /// Either `ok`, `not_found` or `unreadable`
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    #[serde(flatten)]
+    analysis: Option<&'a Analysis>,
+    /// The IANA registration of the /8 the subnet is in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registry: Option<&'static Slash8Registration>,
+}
+
+/// Prints the rows of the stats to stdout, or any writer, in one of the [`StatsFormat`]s
+///
+/// The table format is printed exactly like [`print_stats_table_row`] does
+pub struct StatsPrinter<W: Write = Stdout> {
+    writer: W,
+    format: StatsFormat,
+    rows: u64,
+}
+
+impl StatsPrinter {
+    pub fn new(format: StatsFormat) -> Self {
+        Self::with_writer(std::io::stdout(), format)
+    }
+}
+
+impl<W: Write> StatsPrinter<W> {
+    pub fn with_writer(writer: W, format: StatsFormat) -> Self {
+        Self {
+            writer,
+            format,
+            rows: 0,
+        }
+    }
+
+    pub fn print_header(&mut self) -> std::io::Result<()> {
+        let w = &mut self.writer;
+
+        match self.format {
+            StatsFormat::Table => write!(w, "{}", stats_table_header()),
+            StatsFormat::Json => writeln!(w, "["),
+            StatsFormat::Csv => writeln!(
+                w,
+                "subnet,status,alive,timed_out,errored,unscanned,rtt_min_ms,rtt_median_ms,rtt_p90_ms,rtt_p99_ms,rtt_max_ms,registry,registry_status,message"
+            ),
+            StatsFormat::Markdown => {
+                writeln!(
+                    w,
+                    "| IP ADDRESS | SUCCEEDED | TIMED OUT | ERRORED | UNSCANNED | MIN ms | MED ms | P90 ms | P99 ms | MAX ms | REGISTRY |"
+                )?;
+                writeln!(w, "|---|--:|--:|--:|--:|--:|--:|--:|--:|--:|---|")
+            }
+        }
+    }
+
+    /// Prints the analysis of a subnet, or that it was not found if None
+    pub fn print_row(&mut self, subnet: Subnet, anal: Option<&Analysis>) -> std::io::Result<()> {
+        let status = match anal {
+            Some(_) => "ok",
+            None => "not_found",
+        };
+
+        self.print(subnet, status, anal, None)
+    }
+
+    /// Prints a subnet that could not be read, with the reason why
+    pub fn print_message(&mut self, subnet: Subnet, message: &str) -> std::io::Result<()> {
+        self.print(subnet, "unreadable", None, Some(message))
+    }
+
+    /// Closes the JSON array, the other formats have no footer
+    pub fn finish(mut self) -> std::io::Result<W> {
+        if self.format == StatsFormat::Json {
+            if self.rows != 0 {
+                writeln!(self.writer)?;
+            }
+
+            writeln!(self.writer, "]")?;
+        }
+
+        Ok(self.writer)
+    }
+
+    fn print(
+        &mut self,
+        subnet: Subnet,
+        status: &'static str,
+        anal: Option<&Analysis>,
+        message: Option<&str>,
+    ) -> std::io::Result<()> {
+        let w = &mut self.writer;
+
+        let rtt = |rtt: Option<Duration>| match rtt {
+            Some(rtt) => rtt.as_millis().to_string(),
+            None => "".to_string(),
+        };
+
+        let registry = registration_of(subnet);
+
+        let csv_registry = registry.map_or(",".to_string(), |registry| {
+            format!("\"{}\",{}", registry.designation, registry.status)
+        });
+
+        match self.format {
+            StatsFormat::Table => match (message, anal) {
+                (Some(message), _) => writeln!(w, "{}", stats_table_message(subnet, message))?,
+                (None, Some(anal)) => writeln!(w, "{}", stats_table_row(subnet, anal))?,
+                (None, None) => writeln!(w, "{}", stats_table_message(subnet, "NOT FOUND"))?,
+            },
+            StatsFormat::Json => {
+                let row = StatsRow {
+                    subnet: subnet.to_string(),
+                    status,
+                    message,
+                    analysis: anal,
+                    registry,
+                };
+
+                if self.rows != 0 {
+                    writeln!(w, ",")?;
+                }
+
+                write!(w, "  {}", serde_json::to_string(&row).unwrap())?;
+            }
+            StatsFormat::Csv => match anal {
+                Some(anal) => writeln!(
+                    w,
+                    "{},{},{},{},{},{},{},{},{},{},{},{},",
+                    subnet,
+                    status,
+                    anal.alive,
+                    anal.timed_out,
+                    anal.errored,
+                    anal.unscanned,
+                    rtt(anal.rtt.min),
+                    rtt(anal.rtt.median()),
+                    rtt(anal.rtt.p90()),
+                    rtt(anal.rtt.p99()),
+                    rtt(anal.rtt.max),
+                    csv_registry,
+                )?,
+                None => writeln!(
+                    w,
+                    "{},{},,,,,,,,,,{},{}",
+                    subnet,
+                    status,
+                    csv_registry,
+                    message
+                        .map(|message| format!("\"{}\"", message.replace('"', "\"\"")))
+                        .unwrap_or_default(),
+                )?,
+            },
+            StatsFormat::Markdown => match anal {
+                Some(anal) => {
+                    let rtt = |value: Option<Duration>| match value {
+                        Some(_) => rtt(value),
+                        None => "-".to_string(),
+                    };
+
+                    writeln!(
+                        w,
+                        "| {} | {} ({:.2}%) | {} ({:.2}%) | {} ({:.2}%) | {} ({:.2}%) | {} | {} | {} | {} | {} | {} |",
+                        subnet,
+                        anal.alive,
+                        anal.alive_percent(),
+                        anal.timed_out,
+                        anal.timed_out_percent(),
+                        anal.errored,
+                        anal.errored_percent(),
+                        anal.unscanned,
+                        anal.unscanned_percent(),
+                        rtt(anal.rtt.min),
+                        rtt(anal.rtt.median()),
+                        rtt(anal.rtt.p90()),
+                        rtt(anal.rtt.p99()),
+                        rtt(anal.rtt.max),
+                        registry.map_or("", |registry| registry.designation),
+                    )?
+                }
+                None => writeln!(
+                    w,
+                    "| {} | {} | | | | | | | | | {} |",
+                    subnet,
+                    message.unwrap_or("NOT FOUND").replace('|', "\\|"),
+                    registry.map_or("", |registry| registry.designation),
+                )?,
+            },
+        }
+
+        self.rows += 1;
+
+        Ok(())
+    }
+}
+
 pub fn print_stats_table_header() {
-    println!(
-        "| {:^13} | {:^17} | {:^17} | {:^17} |",
-        "IP ADDRESS", "SUCCEEDED", "TIMED OUT", "ERRORED",
-    );
-    println!("|{:->15}|{:->19}|{:->19}|{:->19}|", "", "", "", "");
+    print!("{}", stats_table_header());
 }
 
 pub fn print_stats_table_row(subnet: Subnet, anal: Option<Analysis>, new_line: bool) {
-    if let Some(anal) = anal {
-        print!(
-            "| {:>13} | {:>5} | {:>9} | {:>5} | {:>9} | {:>5} | {:>9} |",
-            format!("{subnet}"),
-            anal.alive,
-            format!("({:.2}%)", anal.alive_percent()),
-            anal.timed_out,
-            format!("({:.2}%)", anal.timed_out_percent()),
-            anal.errored,
-            format!("({:.2}%)", anal.errored_percent()),
-        );
-    } else {
-        print!("| {:>13} | {:^57} |", format!("{subnet}"), "NOT FOUND");
+    match anal {
+        Some(anal) => print!("{}", stats_table_row(subnet, &anal)),
+        None => print!("{}", stats_table_message(subnet, "NOT FOUND")),
     }
 
     if new_line {
         println!();
     }
 }
+
+/// Prints a row with a message in place of the counts, e.g. for a /16 that could not be read
+pub fn print_stats_table_message(subnet: Subnet, message: &str, new_line: bool) {
+    print!("{}", stats_table_message(subnet, message));
+
+    if new_line {
+        println!();
+    }
+}
+
+/// The header of the stats table and the line under it
+fn stats_table_header() -> String {
+    let titles = format!(
+        "| {:^13} | {:^22} | {:^22} | {:^22} | {:^22} | {:^6} | {:^6} | {:^6} | {:^6} | {:^6} | {:^16} |",
+        "IP ADDRESS",
+        "SUCCEEDED",
+        "TIMED OUT",
+        "ERRORED",
+        "UNSCANNED",
+        "MIN ms",
+        "MED ms",
+        "P90 ms",
+        "P99 ms",
+        "MAX ms",
+        "REGISTRY",
+    );
+    let line = format!(
+        "|{:->15}|{:->24}|{:->24}|{:->24}|{:->24}|{:->8}|{:->8}|{:->8}|{:->8}|{:->8}|{:->18}|",
+        "", "", "", "", "", "", "", "", "", "", ""
+    );
+
+    format!("{titles}\n{line}\n")
+}
+
+/// A row of the stats table, without a line break
+fn stats_table_row(subnet: Subnet, anal: &Analysis) -> String {
+    let rtt = |rtt: Option<Duration>| match rtt {
+        Some(rtt) => rtt.as_millis().to_string(),
+        None => "-".to_string(),
+    };
+
+    format!(
+        "| {:>13} | {:>10} | {:>9} | {:>10} | {:>9} | {:>10} | {:>9} | {:>10} | {:>9} | {:>6} | {:>6} | {:>6} | {:>6} | {:>6} | {:<16} |",
+        format!("{subnet}"),
+        anal.alive,
+        format!("({:.2}%)", anal.alive_percent()),
+        anal.timed_out,
+        format!("({:.2}%)", anal.timed_out_percent()),
+        anal.errored,
+        format!("({:.2}%)", anal.errored_percent()),
+        anal.unscanned,
+        format!("({:.2}%)", anal.unscanned_percent()),
+        rtt(anal.rtt.min),
+        rtt(anal.rtt.median()),
+        rtt(anal.rtt.p90()),
+        rtt(anal.rtt.p99()),
+        rtt(anal.rtt.max),
+        registry_label(subnet),
+    )
+}
+
+/// A row of the stats table with a message in place of the counts, without a line break
+fn stats_table_message(subnet: Subnet, message: &str) -> String {
+    format!(
+        "| {:>13} | {:^142} | {:<16} |",
+        format!("{subnet}"),
+        message,
+        registry_label(subnet)
+    )
+}
+
+/// The short name of whoever IANA gave the /8 of a subnet to
+fn registry_label(subnet: Subnet) -> &'static str {
+    registration_of(subnet).map_or("", |registry| registry.label)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{file::tests::sample_slash_16, ping::UNKNOWN_RTT};
+
+    use super::*;
+
+    fn slash_16(seed: u32) -> Analysis {
+        Analysis::of_subnet(SubnetResults::Slash16(sample_slash_16(seed)))
+    }
+
+    fn assert_same(a: &Analysis, b: &Analysis) {
+        assert_eq!(
+            (a.alive, a.timed_out, a.errored, a.unscanned),
+            (b.alive, b.timed_out, b.errored, b.unscanned)
+        );
+        assert_eq!(a.rtt.histogram, b.rtt.histogram);
+        assert_eq!((a.rtt.min, a.rtt.max), (b.rtt.min, b.rtt.max));
+    }
+
+    #[test]
+    fn buckets_cover_every_rtt() {
+        for ms in 0..=u16::MAX {
+            let bucket = RttStats::bucket_of(Duration::from_millis(ms as u64));
+            let floor = RttStats::bucket_floor(bucket);
+
+            assert!(floor <= ms, "{ms} ms is under the floor of bucket {bucket}");
+
+            if bucket + 1 < RTT_BUCKETS {
+                assert!(ms < RttStats::bucket_floor(bucket + 1));
+            }
+
+            /* Buckets are never wider than an eighth of their floor */
+
+            assert!(
+                ms - floor <= floor / 8,
+                "{ms} ms is too far from {floor} ms"
+            );
+        }
+
+        assert_eq!(RttStats::bucket_of(Duration::ZERO), 0);
+        assert_eq!(
+            RttStats::bucket_of(Duration::from_millis(u16::MAX as u64)),
+            RTT_BUCKETS - 1
+        );
+        assert_eq!(
+            RttStats::bucket_of(Duration::from_secs(3600)),
+            RTT_BUCKETS - 1
+        );
+    }
+
+    #[test]
+    fn approximates_percentiles() {
+        let mut rtt = RttStats::default();
+
+        assert_eq!(rtt.median(), None);
+
+        for ms in 1..=1000 {
+            rtt.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(rtt.count(), 1000);
+        assert_eq!(rtt.min, Some(Duration::from_millis(1)));
+        assert_eq!(rtt.max, Some(Duration::from_millis(1000)));
+
+        for (percentile, exact) in [(50.0, 500), (90.0, 900), (99.0, 990)] {
+            let approx = rtt.percentile(percentile).unwrap().as_millis() as u64;
+
+            assert!(
+                approx <= exact && exact - approx <= exact / 8,
+                "p{percentile} is {approx}"
+            );
+        }
+
+        /* The extremes stay within the exact min and max */
+
+        assert_eq!(rtt.percentile(0.0), Some(Duration::from_millis(1)));
+        assert!(rtt.percentile(100.0).unwrap() <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn leaves_unknown_rtts_out_of_the_distribution() {
+        let mut slash_24 = vec![PingResult::Timeout; 256];
+        slash_24[0] = PingResult::Success(UNKNOWN_RTT);
+        slash_24[1] = PingResult::Success(Duration::from_millis(42));
+
+        let anal = Analysis::of_subnet(SubnetResults::Slash24(Arc::new(
+            slash_24.try_into().unwrap(),
+        )));
+
+        assert_eq!(anal.alive, 2);
+        assert_eq!(anal.rtt.count(), 1);
+        assert_eq!(anal.rtt.median(), Some(Duration::from_millis(42)));
+    }
+
+    #[test]
+    fn merges_in_any_grouping() {
+        let (a, b, c) = (slash_16(1), slash_16(2), slash_16(3));
+
+        let mut left = Analysis::new(SubnetMask::Slash8);
+        let mut a_b = a.clone();
+        a_b.merge(&b);
+        left.merge(&a_b);
+        left.merge(&c);
+
+        let mut right = Analysis::new(SubnetMask::Slash8);
+        let mut b_c = b.clone();
+        b_c.merge(&c);
+        right.merge(&a);
+        right.merge(&b_c);
+
+        assert_same(&left, &right);
+        assert_eq!(
+            left.rtt.count(),
+            a.rtt.count() + b.rtt.count() + c.rtt.count()
+        );
+    }
+
+    #[test]
+    fn counts_unscanned_space() {
+        /* Every fifth /24 of a sample /16 is missing */
+
+        let anal = slash_16(0);
+        let missing = sample_slash_16(0).iter().filter(|s| s.is_none()).count() as u64;
+
+        assert!(missing > 0);
+        assert_eq!(anal.unscanned, missing * 256);
+        assert_eq!(anal.scanned() + anal.unscanned, anal.get_max());
+
+        /* A /8 counts the /16s it is missing as unscanned */
+
+        let mut slash_8 = vec![None; 256];
+        slash_8[4] = Some(sample_slash_16(0));
+
+        let slash_8 =
+            Analysis::of_subnet(SubnetResults::Slash8(Arc::new(slash_8.try_into().unwrap())));
+
+        assert_eq!(slash_8.unscanned, 255 * 65536 + anal.unscanned);
+        assert_eq!(slash_8.scanned(), anal.scanned());
+        assert_eq!(slash_8.scanned() + slash_8.unscanned, slash_8.get_max());
+
+        assert_eq!(Analysis::unscanned(SubnetMask::Slash0).unscanned, 1 << 32);
+        assert_eq!(
+            Analysis::unscanned(SubnetMask::Slash0).unscanned_percent(),
+            100.0
+        );
+    }
+
+    fn printed(format: StatsFormat) -> String {
+        let mut slash_24 = vec![PingResult::Timeout; 256];
+        slash_24[0] = PingResult::Success(Duration::from_millis(20));
+        slash_24[1] = PingResult::Success(Duration::from_millis(40));
+        slash_24[2] = PingResult::Error;
+
+        let anal = Analysis::of_subnet(SubnetResults::Slash24(Arc::new(
+            slash_24.try_into().unwrap(),
+        )));
+
+        let mut printer = StatsPrinter::with_writer(Vec::new(), format);
+
+        printer.print_header().unwrap();
+        printer
+            .print_row("10.1.2.x".parse().ok().unwrap(), Some(&anal))
+            .unwrap();
+        printer
+            .print_row("10.1.3.x".parse().ok().unwrap(), None)
+            .unwrap();
+        printer
+            .print_message("10.1.4.x".parse().ok().unwrap(), "bad \"zlib\" | stream")
+            .unwrap();
+
+        String::from_utf8(printer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn prints_tables() {
+        let out = printed(StatsFormat::Table);
+        let lines: Vec<_> = out.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+        assert!(lines[2].starts_with("|      10.1.2.x |          2 |   (0.78%) |"));
+        assert!(
+            lines[2].ends_with("|     20 |     20 |     40 |     40 |     40 | Private          |")
+        );
+        assert!(lines[3].contains(" NOT FOUND "));
+        assert!(lines[4].contains(r#" bad "zlib" | stream "#));
+    }
+
+    #[test]
+    fn prints_json() {
+        let rows: serde_json::Value = serde_json::from_str(&printed(StatsFormat::Json)).unwrap();
+
+        assert_eq!(rows.as_array().unwrap().len(), 3);
+
+        assert_eq!(rows[0]["status"], "ok");
+        assert_eq!(rows[0]["mask"], 24);
+        assert_eq!(rows[0]["alive"], 2);
+        assert_eq!(rows[0]["timed_out"], 253);
+        assert_eq!(rows[0]["rtt"]["median_ms"], 20);
+        assert_eq!(rows[0]["rtt"]["max_ms"], 40);
+        assert_eq!(rows[0]["registry"]["status"], "RESERVED");
+
+        assert_eq!(rows[1]["status"], "not_found");
+        assert!(rows[1].get("alive").is_none());
+
+        assert_eq!(rows[2]["status"], "unreadable");
+        assert_eq!(rows[2]["message"], r#"bad "zlib" | stream"#);
+
+        /* An empty array is still valid JSON */
+
+        let printer = StatsPrinter::with_writer(Vec::new(), StatsFormat::Json);
+        assert_eq!(printer.finish().unwrap(), b"]\n");
+    }
+
+    #[test]
+    fn prints_csv() {
+        assert_eq!(
+            printed(StatsFormat::Csv),
+            [
+                "subnet,status,alive,timed_out,errored,unscanned,rtt_min_ms,rtt_median_ms,rtt_p90_ms,rtt_p99_ms,rtt_max_ms,registry,registry_status,message",
+                r#"10.1.2.x,ok,2,253,1,0,20,20,40,40,40,"IANA - Private Use",RESERVED,"#,
+                r#"10.1.3.x,not_found,,,,,,,,,,"IANA - Private Use",RESERVED,"#,
+                r#"10.1.4.x,unreadable,,,,,,,,,,"IANA - Private Use",RESERVED,"bad ""zlib"" | stream""#,
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn prints_markdown() {
+        assert_eq!(
+            printed(StatsFormat::Markdown),
+            [
+                "| IP ADDRESS | SUCCEEDED | TIMED OUT | ERRORED | UNSCANNED | MIN ms | MED ms | P90 ms | P99 ms | MAX ms | REGISTRY |",
+                "|---|--:|--:|--:|--:|--:|--:|--:|--:|--:|---|",
+                "| 10.1.2.x | 2 (0.78%) | 253 (98.83%) | 1 (0.39%) | 0 (0.00%) | 20 | 20 | 40 | 40 | 40 | IANA - Private Use |",
+                "| 10.1.3.x | NOT FOUND | | | | | | | | | IANA - Private Use |",
+                r#"| 10.1.4.x | bad "zlib" \| stream | | | | | | | | | IANA - Private Use |"#,
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
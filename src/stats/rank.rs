@@ -0,0 +1,321 @@
+use std::time::Duration;
+
+use crate::{
+    file::summary::Slash16Summary,
+    subnet::{Subnet, SubnetMask},
+};
+
+/// How much larger than the top N the candidates may grow before they are cut down again
+const MIN_SPARE_CANDIDATES: usize = 4096;
+
+/// What subnets are ranked by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankKey {
+    Alive,
+    AlivePercent,
+    /// Subnets with no replies have no median and are left out
+    MedianRtt,
+}
+
+impl std::str::FromStr for RankKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alive" => Ok(Self::Alive),
+            "alive-percent" => Ok(Self::AlivePercent),
+            "median-rtt" => Ok(Self::MedianRtt),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The subnets a ranking leaves out, every subnet passes the default filter
+#[derive(Debug, Clone, Copy)]
+pub struct RankFilter {
+    pub min_alive: u64,
+    /// Of the whole subnet, so a /24 with every address replying is at 100%
+    pub min_alive_percent: f32,
+    pub max_alive_percent: f32,
+}
+
+impl RankFilter {
+    pub fn allows(&self, entry: &RankedSubnet) -> bool {
+        entry.alive >= self.min_alive
+            && entry.alive_percent >= self.min_alive_percent
+            && entry.alive_percent <= self.max_alive_percent
+    }
+}
+
+impl Default for RankFilter {
+    fn default() -> Self {
+        Self {
+            min_alive: 0,
+            min_alive_percent: 0.0,
+            max_alive_percent: 100.0,
+        }
+    }
+}
+
+/// The counts of a /16 or /24 that rankings are sorted by
+#[derive(Debug, Clone)]
+pub struct RankedSubnet {
+    pub subnet: Subnet,
+    pub alive: u64,
+    pub scanned: u64,
+    pub alive_percent: f32,
+    pub median_rtt: Option<Duration>,
+}
+
+/// Keeps the best subnets seen so far by a [`RankKey`]
+///
+/// Only the top N are needed in the end, so candidates are cut down every so
+/// often rather than holding every /24 of the internet
+#[derive(Debug, Clone)]
+pub struct Ranking {
+    key: RankKey,
+    ascending: bool,
+    /// None keeps every subnet that passes the filter
+    top: Option<usize>,
+    filter: RankFilter,
+    candidates: Vec<RankedSubnet>,
+}
+
+impl Ranking {
+    /// Ranks from the highest to the lowest key, unless `ascending`
+    pub fn new(key: RankKey, ascending: bool, top: Option<usize>, filter: RankFilter) -> Self {
+        Self {
+            key,
+            ascending,
+            top,
+            filter,
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, entry: RankedSubnet) {
+        if !self.filter.allows(&entry) {
+            return;
+        }
+
+        if self.key == RankKey::MedianRtt && entry.median_rtt.is_none() {
+            return;
+        }
+
+        self.candidates.push(entry);
+
+        if let Some(top) = self.top {
+            if self.candidates.len() >= top * 2 + MIN_SPARE_CANDIDATES {
+                self.cut_down(top);
+            }
+        }
+    }
+
+    /// Ranks a /16, or each of its /24s when ranking /24s
+    pub fn push_summary(&mut self, subnet: Subnet, summary: &Slash16Summary, mask: SubnetMask) {
+        match mask {
+            SubnetMask::Slash16 => {
+                let anal = &summary.analysis;
+
+                self.push(RankedSubnet {
+                    subnet,
+                    alive: anal.alive,
+                    scanned: anal.scanned(),
+                    alive_percent: anal.alive_percent(),
+                    median_rtt: anal.rtt.median(),
+                })
+            }
+            SubnetMask::Slash24 => {
+                let [a, b, ..] = subnet.octets();
+
+                for (c, slash_24) in summary.slash_24s.iter().enumerate() {
+                    let Some(slash_24) = slash_24 else {
+                        continue;
+                    };
+
+                    self.push(RankedSubnet {
+                        subnet: Subnet::new([a, b, c as u8, 0].into(), SubnetMask::Slash24),
+                        alive: slash_24.alive as u64,
+                        scanned: (slash_24.alive + slash_24.timed_out + slash_24.errored) as u64,
+                        alive_percent: slash_24.alive as f32 / 256.0 * 100.0,
+                        median_rtt: slash_24.median_rtt,
+                    });
+                }
+            }
+            _ => panic!("Only /16s and /24s can be ranked"),
+        }
+    }
+
+    /// Returns the ranked subnets, best first
+    pub fn finish(mut self) -> Vec<RankedSubnet> {
+        match self.top {
+            Some(top) => self.cut_down(top),
+            None => self.sort(),
+        }
+
+        self.candidates
+    }
+
+    fn cut_down(&mut self, top: usize) {
+        self.sort();
+        self.candidates.truncate(top);
+    }
+
+    fn sort(&mut self) {
+        let (key, ascending) = (self.key, self.ascending);
+
+        self.candidates.sort_by(|a, b| {
+            let ordering = match key {
+                RankKey::Alive => a.alive.cmp(&b.alive),
+                RankKey::AlivePercent => a.alive_percent.total_cmp(&b.alive_percent),
+                RankKey::MedianRtt => a.median_rtt.cmp(&b.median_rtt),
+            };
+
+            let ordering = match ascending {
+                true => ordering,
+                false => ordering.reverse(),
+            };
+
+            /* Ties go to the lower address so rankings are the same every run */
+
+            ordering.then_with(|| u32::from(*a.subnet).cmp(&u32::from(*b.subnet)))
+        });
+    }
+}
+
+pub fn print_ranking_table_header() {
+    println!(
+        "| {:^6} | {:^13} | {:^10} | {:^9} | {:^10} | {:^6} |",
+        "RANK", "IP ADDRESS", "SUCCEEDED", "ALIVE %", "SCANNED", "MED ms",
+    );
+    println!(
+        "|{:->8}|{:->15}|{:->12}|{:->11}|{:->12}|{:->8}|",
+        "", "", "", "", "", ""
+    );
+}
+
+pub fn print_ranking_table_row(rank: usize, entry: &RankedSubnet) {
+    println!(
+        "| {:>6} | {:>13} | {:>10} | {:>9} | {:>10} | {:>6} |",
+        rank,
+        format!("{}", entry.subnet),
+        entry.alive,
+        format!("{:.2}%", entry.alive_percent),
+        entry.scanned,
+        entry
+            .median_rtt
+            .map_or("-".to_string(), |rtt| rtt.as_millis().to_string()),
+    );
+}
+
+pub fn print_ranking_csv_header() {
+    println!("rank,subnet,alive,scanned,alive_percent,median_rtt_ms");
+}
+
+pub fn print_ranking_csv_row(rank: usize, entry: &RankedSubnet) {
+    println!(
+        "{},{},{},{},{:.2},{}",
+        rank,
+        entry.subnet,
+        entry.alive,
+        entry.scanned,
+        entry.alive_percent,
+        entry
+            .median_rtt
+            .map_or(String::new(), |rtt| rtt.as_millis().to_string()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(subnet: &str, alive: u64, median_rtt: Option<u64>) -> RankedSubnet {
+        RankedSubnet {
+            subnet: subnet.parse().ok().unwrap(),
+            alive,
+            scanned: 256,
+            alive_percent: alive as f32 / 256.0 * 100.0,
+            median_rtt: median_rtt.map(Duration::from_millis),
+        }
+    }
+
+    fn ranked(ranking: Ranking) -> Vec<String> {
+        ranking
+            .finish()
+            .iter()
+            .map(|entry| entry.subnet.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn breaks_ties_by_address() {
+        for ascending in [false, true] {
+            let mut ranking = Ranking::new(RankKey::Alive, ascending, None, RankFilter::default());
+
+            ranking.push(entry("10.0.9.x", 7, None));
+            ranking.push(entry("10.0.3.x", 7, None));
+            ranking.push(entry("10.0.5.x", 100, None));
+            ranking.push(entry("10.0.1.x", 7, None));
+
+            let expected = match ascending {
+                false => ["10.0.5.x", "10.0.1.x", "10.0.3.x", "10.0.9.x"],
+                true => ["10.0.1.x", "10.0.3.x", "10.0.9.x", "10.0.5.x"],
+            };
+
+            assert_eq!(ranked(ranking), expected);
+        }
+    }
+
+    #[test]
+    fn filters_by_thresholds() {
+        let filter = RankFilter {
+            min_alive: 10,
+            min_alive_percent: 50.0,
+            max_alive_percent: 90.0,
+        };
+
+        let mut ranking = Ranking::new(RankKey::AlivePercent, false, None, filter);
+
+        ranking.push(entry("10.0.0.x", 9, None));
+        ranking.push(entry("10.0.1.x", 127, None));
+        ranking.push(entry("10.0.2.x", 128, None));
+        ranking.push(entry("10.0.3.x", 230, None));
+        ranking.push(entry("10.0.4.x", 231, None));
+
+        /* Bounds are inclusive, 128 is exactly 50% */
+
+        assert_eq!(ranked(ranking), ["10.0.3.x", "10.0.2.x"]);
+    }
+
+    #[test]
+    fn leaves_subnets_without_a_median_out_of_rtt_rankings() {
+        let mut ranking = Ranking::new(RankKey::MedianRtt, true, None, RankFilter::default());
+
+        ranking.push(entry("10.0.0.x", 0, None));
+        ranking.push(entry("10.0.1.x", 5, Some(80)));
+        ranking.push(entry("10.0.2.x", 5, Some(20)));
+
+        assert_eq!(ranked(ranking), ["10.0.2.x", "10.0.1.x"]);
+    }
+
+    #[test]
+    fn keeps_the_top_across_cut_downs() {
+        let mut ranking = Ranking::new(RankKey::Alive, false, Some(3), RankFilter::default());
+
+        for i in 0..3 * MIN_SPARE_CANDIDATES as u32 {
+            let alive = (i.wrapping_mul(2654435761) >> 20) as u64 % 200;
+
+            ranking.push(RankedSubnet {
+                subnet: Subnet::new((i << 8).into(), SubnetMask::Slash24),
+                ..entry("0.0.0.x", alive, None)
+            });
+        }
+
+        ranking.push(entry("200.0.0.x", 255, None));
+        ranking.push(entry("100.0.0.x", 255, None));
+        ranking.push(entry("150.0.0.x", 254, None));
+
+        assert_eq!(ranked(ranking), ["100.0.0.x", "200.0.0.x", "150.0.0.x"]);
+    }
+}
@@ -21,6 +21,19 @@ pub enum SubnetMask {
     Slash32,
 }
 
+impl SubnetMask {
+    /// The number of leading bits of the base address that are fixed, e.g. 16 for a /16
+    pub fn prefix_length(&self) -> u8 {
+        match self {
+            SubnetMask::Slash0 => 0,
+            SubnetMask::Slash8 => 8,
+            SubnetMask::Slash16 => 16,
+            SubnetMask::Slash24 => 24,
+            SubnetMask::Slash32 => 32,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Subnet {
     base_address: Ipv4Addr,
@@ -50,10 +63,49 @@ impl Subnet {
         self.mask
     }
 
+    /// Returns whether the given address is inside this subnet
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        let prefix = match self.mask {
+            SubnetMask::Slash0 => 0,
+            SubnetMask::Slash8 => 1,
+            SubnetMask::Slash16 => 2,
+            SubnetMask::Slash24 => 3,
+            SubnetMask::Slash32 => 4,
+        };
+
+        self.base_address.octets()[..prefix] == address.octets()[..prefix]
+    }
+
+    /// The first and last address of this subnet, as integers
+    pub fn address_range(&self) -> RangeInclusive<u32> {
+        let start = u32::from(self.base_address);
+        let host_mask = u32::MAX
+            .checked_shr(self.mask.prefix_length() as u32)
+            .unwrap_or(0);
+
+        start..=start | host_mask
+    }
+
     /// Iterates through all the subnets one class lower than this subnet
     pub fn iter_subnets(&self) -> impl Iterator<Item = Subnet> {
         SubnetIterator::new(*self)
     }
+
+    /// Iterates through all the /16 subnets which overlap with this subnet
+    pub fn iter_slash_16s(&self) -> Box<dyn Iterator<Item = Subnet>> {
+        let octets = self.base_address.octets();
+
+        match self.mask {
+            SubnetMask::Slash0 => Box::new(self.iter_subnets().flat_map(|s| s.iter_subnets())),
+            SubnetMask::Slash8 => Box::new(self.iter_subnets()),
+            SubnetMask::Slash16 | SubnetMask::Slash24 | SubnetMask::Slash32 => {
+                Box::new(std::iter::once(Subnet::new(
+                    [octets[0], octets[1], 0, 0].into(),
+                    SubnetMask::Slash16,
+                )))
+            }
+        }
+    }
 }
 
 impl Default for Subnet {
@@ -0,0 +1,283 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use image::{ImageFormat, ImageResult, RgbImage};
+
+use crate::{
+    file::summary::Slash16Summary,
+    ping::PingResult,
+    render::{Curve, MapLayout, MapStyle},
+    stats::Slash16Result,
+    subnet::{Subnet, SubnetMask},
+};
+
+/// The side of a tile in pixels, as web maps expect
+pub const TILE_SIZE: u32 = 256;
+
+/// The deepest zoom level, where a pixel is an address and a tile a /16
+pub const MAX_ZOOM: u8 = 8;
+
+/// The zoom level where a pixel is a /24 and a tile a /8
+pub const SLASH_8_ZOOM: u8 = 4;
+
+/// Where the nth tile of a zoom level goes, as (x, y)
+///
+/// A zoom level has 4^zoom tiles, each a run of the address space as long as
+/// the others, so the nth tile covers the nth such run
+pub fn tile_position(curve: Curve, zoom: u8, index: u64) -> (u32, u32) {
+    curve.position(index, 1 << zoom)
+}
+
+/// The path of a tile in the standard `z/x/y.png` layout
+pub fn tile_path(root: &Path, zoom: u8, (x, y): (u32, u32)) -> PathBuf {
+    root.join(zoom.to_string())
+        .join(x.to_string())
+        .join(format!("{y}.png"))
+}
+
+/// Saves a tile under a pyramid, through a temporary file so an interrupted
+/// run never leaves a truncated tile behind
+pub fn save_tile(root: &Path, zoom: u8, position: (u32, u32), tile: &RgbImage) -> ImageResult<()> {
+    let path = tile_path(root, zoom, position);
+    let partial = path.with_extension("png.partial");
+
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    tile.save_with_format(&partial, ImageFormat::Png)?;
+    std::fs::rename(partial, path)?;
+
+    Ok(())
+}
+
+/// The addresses that replied and that were scanned under every pixel of a
+/// square, so a tile can be coloured and summed into the tile above it
+#[derive(Debug, Clone)]
+pub struct CountTile {
+    size: u32,
+    /// (alive, scanned) by row
+    cells: Vec<[u32; 2]>,
+}
+
+impl CountTile {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            cells: vec![[0, 0]; (size * size) as usize],
+        }
+    }
+
+    /// Counts the addresses of a /16, a tile of the deepest zoom level
+    pub fn of_slash_16(curve: Curve, subnet: Subnet, results: &Slash16Result) -> Self {
+        let layout = MapLayout::new(subnet, SubnetMask::Slash32, curve);
+        let first = u32::from(subnet.base_address());
+
+        let mut tile = Self::new(TILE_SIZE);
+
+        for (c, slash_24) in results.iter().enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            for (d, result) in slash_24.iter().enumerate() {
+                let (x, y) = layout.position(first + c as u32 * 256 + d as u32);
+                let alive = matches!(result, PingResult::Success(_)) as u32;
+
+                tile.add(x, y, alive, 1);
+            }
+        }
+
+        tile
+    }
+
+    /// Counts the /24s of a /16 onto the tile of its /8, whose pixels are /24s
+    pub fn add_slash_16_summary(&mut self, curve: Curve, subnet: Subnet, summary: &Slash16Summary) {
+        assert_eq!(self.size, TILE_SIZE);
+
+        let slash_8 = Subnet::new([subnet.octets()[0], 0, 0, 0].into(), SubnetMask::Slash8);
+        let layout = MapLayout::new(slash_8, SubnetMask::Slash24, curve);
+        let first = u32::from(subnet.base_address());
+
+        for (c, slash_24) in summary.slash_24s.iter().enumerate() {
+            let Some(slash_24) = slash_24 else {
+                continue;
+            };
+
+            let (x, y) = layout.position(first + c as u32 * 256);
+            let scanned = slash_24.alive + slash_24.timed_out + slash_24.errored;
+
+            self.add(x, y, slash_24.alive as u32, scanned as u32);
+        }
+    }
+
+    fn add(&mut self, x: u32, y: u32, alive: u32, scanned: u32) {
+        let cell = &mut self.cells[(y * self.size + x) as usize];
+
+        cell[0] += alive;
+        cell[1] += scanned;
+    }
+
+    /// Whether any address under the tile was scanned
+    pub fn is_scanned(&self) -> bool {
+        self.cells.iter().any(|[_, scanned]| *scanned != 0)
+    }
+
+    /// Sums every 2x2 block of pixels into one, giving the quarter this tile
+    /// takes up in the tile of the zoom level above
+    pub fn downsample(&self) -> Self {
+        let mut half = Self::new(self.size / 2);
+
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let [alive, scanned] = self.cells[(y * self.size + x) as usize];
+
+                half.add(x / 2, y / 2, alive, scanned);
+            }
+        }
+
+        half
+    }
+
+    /// Puts downsampled tiles together into the tile above them, each given
+    /// with its (column, row) in the 2x2 grid of quarters
+    pub fn assemble(quarters: impl IntoIterator<Item = ((u32, u32), Self)>) -> Self {
+        let mut tile = Self::new(TILE_SIZE);
+        let half = TILE_SIZE / 2;
+
+        for ((column, row), quarter) in quarters {
+            assert_eq!(quarter.size, half);
+
+            for y in 0..half {
+                for x in 0..half {
+                    let [alive, scanned] = quarter.cells[(y * half + x) as usize];
+
+                    tile.add(column * half + x, row * half + y, alive, scanned);
+                }
+            }
+        }
+
+        tile
+    }
+
+    /// Colours every pixel by how much of it replied
+    pub fn render(&self, style: &MapStyle) -> RgbImage {
+        RgbImage::from_fn(self.size, self.size, |x, y| {
+            let [alive, scanned] = self.cells[(y * self.size + x) as usize];
+
+            style.block_color(alive as u64, scanned as u64, None)
+        })
+    }
+}
+
+/// Where a tile goes among the four quarters of the tile above it, as (column, row)
+pub fn quarter_of(curve: Curve, zoom: u8, index: u64) -> (u32, u32) {
+    let (x, y) = tile_position(curve, zoom, index);
+    let (parent_x, parent_y) = tile_position(curve, zoom - 1, index / 4);
+
+    (x - 2 * parent_x, y - 2 * parent_y)
+}
+
+/// Puts the downsampled tiles of a zoom level together into the tiles of the
+/// level above, by index, leaving out tiles with nothing under them
+pub fn assemble_level(
+    curve: Curve,
+    zoom: u8,
+    quarters: impl IntoIterator<Item = (u64, CountTile)>,
+) -> Vec<(u64, CountTile)> {
+    let mut parents: BTreeMap<u64, Vec<(u64, CountTile)>> = BTreeMap::new();
+
+    for (index, quarter) in quarters {
+        parents.entry(index / 4).or_default().push((index, quarter));
+    }
+
+    parents
+        .into_iter()
+        .map(|(parent, quarters)| {
+            let quarters = quarters
+                .into_iter()
+                .map(|(index, quarter)| (quarter_of(curve, zoom, index), quarter));
+
+            (parent, CountTile::assemble(quarters))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Curve; 2] = [Curve::Hilbert, Curve::ZOrder];
+
+    #[test]
+    fn puts_quarters_where_their_tiles_are() {
+        for curve in CURVES {
+            for zoom in 1..=MAX_ZOOM {
+                for parent in 0..(1u64 << (2 * (zoom - 1))).min(256) {
+                    let (parent_x, parent_y) = tile_position(curve, zoom - 1, parent);
+
+                    let quarters: Vec<_> = (parent * 4..parent * 4 + 4)
+                        .map(|index| {
+                            let quarter = quarter_of(curve, zoom, index);
+
+                            assert_eq!(
+                                tile_position(curve, zoom, index),
+                                (2 * parent_x + quarter.0, 2 * parent_y + quarter.1)
+                            );
+
+                            quarter
+                        })
+                        .collect();
+
+                    /* The four tiles of a parent fill all of its quarters */
+
+                    for quarter in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        assert!(quarters.contains(&quarter), "{curve:?} {zoom} {parent}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn assembles_levels_in_place() {
+        let half = TILE_SIZE / 2;
+        let zoom = 3;
+
+        for curve in CURVES {
+            /* Mark the corner of each tile with its index, leaving out a whole parent */
+
+            let quarters = (0..1u64 << (2 * zoom))
+                .filter(|index| index / 4 != 5)
+                .map(|index| {
+                    let mut quarter = CountTile::new(half);
+                    quarter.add(0, 0, index as u32, 1000);
+
+                    (index, quarter)
+                });
+
+            let parents = assemble_level(curve, zoom, quarters);
+
+            assert_eq!(parents.len(), 15);
+            assert!(parents.iter().all(|(parent, _)| *parent != 5));
+
+            for (parent, tile) in parents {
+                let (parent_x, parent_y) = tile_position(curve, zoom - 1, parent);
+
+                for index in parent * 4..parent * 4 + 4 {
+                    /* Where the tile is on the whole level, in pixels of the level above */
+
+                    let (x, y) = tile_position(curve, zoom, index);
+                    let (x, y) = (
+                        x * half - parent_x * TILE_SIZE,
+                        y * half - parent_y * TILE_SIZE,
+                    );
+
+                    assert_eq!(
+                        tile.cells[(y * TILE_SIZE + x) as usize],
+                        [index as u32, 1000]
+                    );
+                }
+            }
+        }
+    }
+}